@@ -1,17 +1,508 @@
+import builtins
+import os
 import typing as t
 
 class PyNodeData():
     tag_name: str
     attributes: t.Dict[str, str]
+    in_noscript: bool
+
+class PySearchMatch:
+    node: PyNode
+    start: int
+    end: int
+    path: t.List[str]
+
+class PyShadowRoot:
+    mode: t.Literal['open', 'closed']
+    children: t.List[PyNode]
+
+class PySpan:
+    start: int
+    end: int
+    line: int
+    column: int
+
+class PySourcePosition:
+    start: PySpan
+    end: t.Optional[PySpan]
+
+NodeType = t.Literal['element', 'text', 'comment', 'doctype', 'document']
+
+# What PyNode.to_dict returns, in_noscript and shadow_root only when set
+NodeDict = t.Dict[str, t.Any]
 
 class PyNode:
+    node_type: NodeType
     children: t.List[PyNode]
     data: PyNodeData
+    shadow_root: t.Optional[PyShadowRoot]
+    position: t.Optional[PySourcePosition]
+    source_span: t.Optional[t.Tuple[int, int]]
+    def parent(self) -> t.Optional[PyNode]: ...
+    def next_sibling(self) -> t.Optional[PyNode]: ...
+    def previous_sibling(self) -> t.Optional[PyNode]: ...
+    def ancestors(self) -> t.List[PyNode]: ...
     def get_inmidiate_text_node(self) -> PyNode: ...
     def get_inmidiate_node(self) -> PyNode: ...
-    def get_all_nodes(self) -> t.List[PyNode]: ...
-    def get_text_nodes(self) -> t.List[PyNode]: ...
-    def get_nodes(self, node_type: str) -> t.List[PyNode]: ...
+    def get_all_nodes(self, include_shadow: bool = False) -> t.List[PyNode]: ...
+    def get_text_nodes(self, include_shadow: bool = False) -> t.List[PyNode]: ...
+    def get_nodes(self, node_type: str, include_shadow: bool = False) -> t.List[PyNode]: ...
+    def search(self, pattern: str, regex: bool = False) -> t.List[PySearchMatch]: ...
+    def query_selector(self, selectors: str) -> t.Optional[PyNode]: ...
+    def query_selector_all(self, selectors: str) -> t.List[PyNode]: ...
+    def get_element_by_id(self, id: str) -> t.Optional[PyNode]: ...
+    def get_elements_by_class_name(self, class_names: str) -> t.List[PyNode]: ...
+    def get_elements_by_tag_name(self, tag_name: str) -> t.List[PyNode]: ...
+    def find(self, attr: str, value: t.Optional[str] = None) -> t.List[PyNode]: ...
+    def to_html(self) -> str: ...
+    def inner_html(self) -> str: ...
+    def text(self) -> str: ...
+    def to_xhtml(self, xml_declaration: bool = False) -> str: ...
+    def to_dict(self) -> NodeDict: ...
+    def to_json(self, indent: t.Optional[int] = None) -> str: ...
+    @staticmethod
+    def from_dict(data: NodeDict) -> PyNode: ...
+    def stylesheets(self) -> t.List[PyStylesheet]: ...
+    def accessibility_tree(self) -> PyAccessibilityNode: ...
+    def computed_style(self, stylesheets: t.Optional[t.List[PyStylesheet]] = None) -> t.Dict[str, str]: ...
+    def layout(
+        self,
+        width: float,
+        measure: t.Optional[t.Callable[[str, float], float]] = None,
+        stylesheets: t.Optional[t.List[PyStylesheet]] = None,
+    ) -> PyLayoutBox: ...
+    def display_list(
+        self,
+        width: float,
+        scroll: float = 0.0,
+        height: t.Optional[float] = None,
+        measure: t.Optional[t.Callable[[str, float], float]] = None,
+        stylesheets: t.Optional[t.List[PyStylesheet]] = None,
+    ) -> t.List[t.Union[PyDrawRect, PyDrawText]]: ...
+
+class PyDrawRect:
+    x: float
+    y: float
+    width: float
+    height: float
+    color: str
+
+class PyDrawText:
+    x: float
+    y: float
+    text: str
+    color: str
+    font_size: float
+    font_weight: str
+    font_style: str
+
+class PyLayoutBox:
+    kind: t.Literal['block', 'line', 'text']
+    x: float
+    y: float
+    width: float
+    height: float
+    text: t.Optional[str]
+    style: t.Dict[str, str]
+    node: PyNode
+    children: t.List[PyLayoutBox]
+
+class PyAccessibilityNode:
+    role: str
+    name: str
+    properties: t.Dict[str, str]
+    node: PyNode
+    children: t.List[PyAccessibilityNode]
+
+class PyDeclaration:
+    name: str
+    value: str
+    important: bool
+
+class PyRule:
+    selector: str
+    specificity: t.List[t.Tuple[int, int, int]]
+    declarations: t.List[PyDeclaration]
+
+class PyStylesheet:
+    rules: t.List[PyRule]
+    def __init__(self, css: str) -> None: ...
+
+HttpVersion = t.Literal['auto', '1.0', '1.1', '2']
+
+RequestBody = t.Union[bytes, str, t.BinaryIO, t.TextIO, os.PathLike]
+
+Headers = t.Union[t.Mapping[str, str], t.Iterable[t.Tuple[str, str]]]
+
+# PEM text, or the path of a PEM file
+PemData = t.Union[str, bytes, os.PathLike]
+
+# A (username, password) tuple for Basic authentication or a Bearer token
+Auth = t.Union[t.Tuple[str, str], str]
+
+class ConnectError(builtins.ConnectionError): ...
+class TimeoutError(builtins.TimeoutError): ...
+class BlockedError(builtins.ConnectionError): ...
+
+def request(
+    url: str,
+    http_version: t.Optional[HttpVersion] = None,
+    body: t.Optional[RequestBody] = None,
+    max_redirects: t.Optional[int] = None,
+    method: t.Optional[str] = None,
+    headers: t.Optional[Headers] = None,
+    timeout: t.Optional[float] = None,
+    connect_timeout: t.Optional[float] = None,
+    retries: t.Optional[int] = None,
+    proxy: t.Optional[str] = None,
+    ca_bundle: t.Optional[PemData] = None,
+    client_cert: t.Optional[PemData] = None,
+    client_key: t.Optional[PemData] = None,
+    danger_accept_invalid_certs: bool = False,
+    auth: t.Optional[Auth] = None,
+) -> str: ...
+
+class PyHeaders:
+    def get(self, name: str, default: t.Optional[str] = None) -> t.Optional[str]: ...
+    def get_all(self, name: str) -> t.List[str]: ...
+    def keys(self) -> t.List[str]: ...
+    def items(self) -> t.List[t.Tuple[str, str]]: ...
+    def __getitem__(self, name: str) -> str: ...
+    def __contains__(self, name: str) -> bool: ...
+    def __len__(self) -> int: ...
+    def __iter__(self) -> t.Iterator[str]: ...
+
+class PyResponse:
+    url: str
+    status: int
+    reason: str
+    version: str
+    headers: PyHeaders
+    redirects: t.List[str]
+    body: bytes
+    encoding: str
+    text: str
+    ok: bool
+
+def fetch(
+    url: str,
+    http_version: t.Optional[HttpVersion] = None,
+    body: t.Optional[RequestBody] = None,
+    max_redirects: t.Optional[int] = None,
+    method: t.Optional[str] = None,
+    headers: t.Optional[Headers] = None,
+    timeout: t.Optional[float] = None,
+    connect_timeout: t.Optional[float] = None,
+    retries: t.Optional[int] = None,
+    proxy: t.Optional[str] = None,
+    ca_bundle: t.Optional[PemData] = None,
+    client_cert: t.Optional[PemData] = None,
+    client_key: t.Optional[PemData] = None,
+    danger_accept_invalid_certs: bool = False,
+    auth: t.Optional[Auth] = None,
+) -> PyResponse: ...
+
+def fetch_many(
+    urls: t.Sequence[str],
+    max_concurrency: int = 8,
+    http_version: t.Optional[HttpVersion] = None,
+    max_redirects: t.Optional[int] = None,
+    method: t.Optional[str] = None,
+    headers: t.Optional[Headers] = None,
+    timeout: t.Optional[float] = None,
+    connect_timeout: t.Optional[float] = None,
+    retries: t.Optional[int] = None,
+    proxy: t.Optional[str] = None,
+    ca_bundle: t.Optional[PemData] = None,
+    client_cert: t.Optional[PemData] = None,
+    client_key: t.Optional[PemData] = None,
+    danger_accept_invalid_certs: bool = False,
+    auth: t.Optional[Auth] = None,
+) -> t.List[t.Union[str, Exception]]: ...
+
+class PyCookie:
+    name: str
+    value: str
+    domain: str
+    path: str
+    expires: t.Optional[int]
+    secure: bool
+    http_only: bool
+    host_only: bool
+
+class PyCookieJar:
+    cookies: t.List[PyCookie]
+    def __init__(self) -> None: ...
+    def __len__(self) -> int: ...
+    def set(
+        self,
+        name: str,
+        value: str,
+        domain: str,
+        path: str = '/',
+        subdomains: bool = False,
+        secure: bool = False,
+        expires: t.Optional[int] = None,
+    ) -> None: ...
+    def clear(self) -> None: ...
+    def dumps(self) -> str: ...
+    @staticmethod
+    def loads(source: str) -> PyCookieJar: ...
+
+class PyCacheStats:
+    hits: int
+    revalidated: int
+    misses: int
+
+class PyFilterStats:
+    checked: int
+    blocked: int
+
+class PySession:
+    cookies: PyCookieJar
+    cache_stats: t.Optional[PyCacheStats]
+    filtering: bool
+    filter_stats: PyFilterStats
+    def __init__(
+        self,
+        http_version: t.Optional[HttpVersion] = None,
+        idle_timeout: float = 30.0,
+        cookie_jar: t.Optional[PyCookieJar] = None,
+        cache: bool = False,
+        cache_dir: t.Optional[os.PathLike] = None,
+        proxy: t.Optional[str] = None,
+        ca_bundle: t.Optional[PemData] = None,
+        client_cert: t.Optional[PemData] = None,
+        client_key: t.Optional[PemData] = None,
+        danger_accept_invalid_certs: bool = False,
+    ) -> None: ...
+    def add_filters(self, rules: str) -> int: ...
+    def clear_filters(self) -> None: ...
+    def is_blocked(self, url: str, document: bool = False) -> bool: ...
+    def clear_cache(self) -> None: ...
+    def request(
+        self,
+        url: str,
+        body: t.Optional[RequestBody] = None,
+        max_redirects: t.Optional[int] = None,
+        method: t.Optional[str] = None,
+        headers: t.Optional[Headers] = None,
+        timeout: t.Optional[float] = None,
+        connect_timeout: t.Optional[float] = None,
+        retries: t.Optional[int] = None,
+        auth: t.Optional[Auth] = None,
+    ) -> str: ...
+    def fetch(
+        self,
+        url: str,
+        body: t.Optional[RequestBody] = None,
+        max_redirects: t.Optional[int] = None,
+        method: t.Optional[str] = None,
+        headers: t.Optional[Headers] = None,
+        timeout: t.Optional[float] = None,
+        connect_timeout: t.Optional[float] = None,
+        retries: t.Optional[int] = None,
+        auth: t.Optional[Auth] = None,
+    ) -> PyResponse: ...
+    def fetch_many(
+        self,
+        urls: t.Sequence[str],
+        max_concurrency: int = 8,
+        max_redirects: t.Optional[int] = None,
+        method: t.Optional[str] = None,
+        headers: t.Optional[Headers] = None,
+        timeout: t.Optional[float] = None,
+        connect_timeout: t.Optional[float] = None,
+        retries: t.Optional[int] = None,
+        auth: t.Optional[Auth] = None,
+    ) -> t.List[t.Union[str, Exception]]: ...
+    def fetch_image(self, url: str) -> PyImage: ...
+    def download(
+        self,
+        url: str,
+        path: t.Union[str, os.PathLike],
+        progress: t.Optional[t.Callable[[int, t.Optional[int]], None]] = None,
+        resume: bool = True,
+    ) -> PyDownload: ...
+    def close(self) -> None: ...
+
+class PyImage:
+    width: int
+    height: int
+    pixels: bytes
+
+def decode_image(data: bytes) -> PyImage: ...
+
+class PyDownload:
+    size: int
+    total: t.Optional[int]
+    resumed: bool
+
+def download(
+    url: str,
+    path: t.Union[str, os.PathLike],
+    progress: t.Optional[t.Callable[[int, t.Optional[int]], None]] = None,
+    resume: bool = True,
+) -> PyDownload: ...
+
+NoscriptMode = t.Literal['parse', 'flag', 'skip']
+
+class PyParserOptions:
+    noscript: NoscriptMode
+    declarative_shadow_dom: bool
+    decode_entities: bool
+    document: bool
+    comments: bool
+    void_elements: t.List[str]
+    source_positions: bool
+    def __init__(
+        self,
+        noscript: t.Optional[NoscriptMode] = None,
+        declarative_shadow_dom: bool = True,
+        decode_entities: bool = True,
+        document: bool = False,
+        comments: bool = False,
+        void_elements: t.List[str] = [],
+        source_positions: bool = False,
+    ) -> None: ...
+
+def load(
+    body: str,
+    noscript: t.Optional[NoscriptMode] = None,
+    declarative_shadow_dom: bool = True,
+    decode_entities: bool = True,
+    document: bool = False,
+    comments: bool = False,
+    source_positions: bool = False,
+    options: t.Optional[PyParserOptions] = None,
+) -> PyNode: ...
+
+# `handler` has any of on_start_tag(tag, attrs), on_end_tag(tag), on_text(text) and
+# on_comment(text)
+def parse_events(body: str, handler: object, decode_entities: bool = True) -> None: ...
+
+class PyStreamingParser:
+    def __init__(
+        self,
+        noscript: t.Optional[NoscriptMode] = None,
+        declarative_shadow_dom: bool = True,
+        decode_entities: bool = True,
+        document: bool = False,
+        comments: bool = False,
+        source_positions: bool = False,
+        options: t.Optional[PyParserOptions] = None,
+    ) -> None: ...
+    def feed(self, chunk: str) -> None: ...
+    def finish(self) -> PyNode: ...
+
+class PyPage:
+    url: str
+    mime_type: str
+    encoding: str
+    kind: t.Literal['document', 'text', 'download']
+    document: t.Optional[PyNode]
+    text: t.Optional[str]
+    redirects: t.List[str]
+    body: bytes
+    def links(self) -> t.List[PyLink]: ...
+
+class PyLink:
+    kind: t.Literal['anchor', 'image', 'link', 'script']
+    url: str
+
+def load_page(
+    url: str,
+    max_refresh_hops: t.Optional[int] = None,
+    max_redirects: t.Optional[int] = None,
+) -> PyPage: ...
+
+class PyTab:
+    session: PySession
+    current_url: t.Optional[str]
+    page: t.Optional[PyPage]
+    document: t.Optional[PyNode]
+    def __init__(
+        self,
+        session: t.Optional[PySession] = None,
+        max_refresh_hops: t.Optional[int] = None,
+        max_redirects: t.Optional[int] = None,
+    ) -> None: ...
+    history_index: int
+    can_go_back: bool
+    can_go_forward: bool
+    def navigate(self, url: str) -> t.Optional[PyNode]: ...
+    def follow(self, link: PyNode) -> t.Optional[PyNode]: ...
+    def back(self) -> t.Optional[PyNode]: ...
+    def forward(self) -> t.Optional[PyNode]: ...
+    def history(self) -> t.List[PyHistoryEntry]: ...
+
+class PyHistoryEntry:
+    url: str
+    title: t.Optional[str]
+
+class PyCrawler(t.Iterator[PyPage]):
+    def __iter__(self) -> PyCrawler: ...
+    def __next__(self) -> PyPage: ...
+
+def crawl(
+    start_url: str,
+    max_depth: int = 2,
+    max_pages: int = 100,
+    same_origin: bool = True,
+    concurrency: int = 4,
+    delay: float = 0.5,
+    respect_robots: bool = True,
+) -> PyCrawler: ...
+
+def load_xml(body: str) -> PyNode: ...
+
+class PyScriptResult:
+    document: PyNode
+    console: t.List[str]
+    errors: t.List[str]
+
+# Only built with the `javascript` feature
+def run_scripts(document: PyNode) -> PyScriptResult: ...
+
+class PySitemapEntry:
+    loc: str
+    lastmod: t.Optional[str]
+    changefreq: t.Optional[str]
+    priority: t.Optional[float]
+
+def fetch_sitemap(url: str) -> t.List[PySitemapEntry]: ...
+
+def grapheme_len(text: str) -> int: ...
+def display_width(text: str) -> int: ...
+def truncate(text: str, max_width: int, ellipsis: str = '…') -> str: ...
+
+class PyURL:
+    def __init__(self, url: str, base: t.Optional[str] = None) -> None: ...
+    @property
+    def href(self) -> str: ...
+    @property
+    def scheme(self) -> str: ...
+    @property
+    def host(self) -> str: ...
+    @property
+    def port(self) -> t.Optional[int]: ...
+    @property
+    def path(self) -> str: ...
+    @property
+    def query(self) -> t.Optional[str]: ...
+    @property
+    def fragment(self) -> t.Optional[str]: ...
+    @property
+    def origin(self) -> str: ...
+    def params(self) -> t.List[t.Tuple[str, str]]: ...
+    def get_param(self, name: str) -> t.Optional[str]: ...
+    def get_all_params(self, name: str) -> t.List[str]: ...
+    def set_param(self, name: str, value: str) -> None: ...
+    def append_param(self, name: str, value: str) -> None: ...
+    def delete_param(self, name: str) -> None: ...
+    def join(self, relative: str) -> PyURL: ...
 
-def request(url: str) -> str: ...
-def load(body: str) -> PyNode: ...
+def join(base: str, relative: str) -> str: ...
+def percent_encode(text: str) -> str: ...
+def percent_decode(text: str) -> str: ...
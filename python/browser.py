@@ -140,9 +140,11 @@ class Browser:
         self.display_list = []
 
     def load(self, url: str):
-        body = ewb.request(url)
-        root = ewb.load(body)
-        self.display_list = Layout(root).display_list
+        # load_page follows meta refresh redirects for us
+        page = ewb.load_page(url)
+        if page.document is None:
+            return
+        self.display_list = Layout(page.document).display_list
         self.draw()
 
     def draw(self):
@@ -0,0 +1,517 @@
+// Accessibility tree (https://www.w3.org/TR/core-aam-1.2/): the elements that mean something
+// to assistive technology with their role, accessible name and states. Names follow a
+// simplified version of https://www.w3.org/TR/accname-1.2/.
+
+use std::collections::HashMap;
+
+use crate::html::{Node, NodeType, Step, HIDDEN_ELEMENTS};
+
+// Roles whose name comes from their content when nothing names them explicitly
+static NAME_FROM_CONTENT: [&str; 15] = [
+    "button",
+    "cell",
+    "checkbox",
+    "columnheader",
+    "heading",
+    "link",
+    "menuitem",
+    "option",
+    "radio",
+    "row",
+    "rowheader",
+    "switch",
+    "tab",
+    "tooltip",
+    "treeitem",
+];
+
+// `aria-*` attributes that name or hide rather than describe a state
+static NAMING_ATTRIBUTES: [&str; 3] = ["aria-label", "aria-labelledby", "aria-hidden"];
+
+// Elements owned by a table, they have no role in one used for layout
+static TABLE_PARTS: [&str; 7] = ["caption", "tbody", "td", "tfoot", "th", "thead", "tr"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessibilityNode {
+    /// ARIA role, "text" for runs of text
+    pub role: String,
+    /// Accessible name, empty when there is none
+    pub name: String,
+    /// States and properties, like `level` of headings or `checked` of checkboxes, implied
+    /// by the element or given by its `aria-*` attributes (without the prefix)
+    pub properties: HashMap<String, String>,
+    /// Way from the root of the tree to the node
+    pub path: Vec<Step>,
+    pub children: Vec<AccessibilityNode>,
+}
+
+// What names are looked up in: elements by id and labels by the control they are for
+struct Labels<'a> {
+    by_id: HashMap<&'a str, &'a Node>,
+    by_control: HashMap<&'a str, &'a Node>,
+}
+
+// Where a node is
+#[derive(Clone, Copy, Default)]
+struct Scope<'a> {
+    /// The `<label>` it is in
+    label: Option<&'a Node>,
+    /// In a table with role `none` or `presentation`
+    layout_table: bool,
+}
+
+/// Accessibility tree of `root`. Elements without a role (`div`, `span`, ...) or with role
+/// `none`/`presentation` are left out and their content takes their place, hidden ones are
+/// left out with their content.
+pub fn accessibility_tree(root: &Node) -> AccessibilityNode {
+    let mut labels = Labels {
+        by_id: HashMap::new(),
+        by_control: HashMap::new(),
+    };
+    labels.collect(root);
+
+    let role = role(root).unwrap_or(match root.node_type() {
+        NodeType::Document => "document",
+        _ if ["html", "body"].contains(&root.data.tag_name.as_str()) => "document",
+        _ => "generic",
+    });
+    let mut children = Vec::new();
+    collect_children(
+        root,
+        &mut Vec::new(),
+        &labels,
+        Scope::default(),
+        &mut children,
+    );
+
+    AccessibilityNode {
+        role: role.to_string(),
+        name: name(root, role, &labels, None),
+        properties: properties(root, role),
+        path: Vec::new(),
+        children,
+    }
+}
+
+impl<'a> Labels<'a> {
+    fn collect(&mut self, node: &'a Node) {
+        if let Some(id) = node.data.attributes.get("id") {
+            self.by_id.entry(id).or_insert(node);
+        }
+
+        if node.data.tag_name == "label" {
+            if let Some(control) = node.data.attributes.get("for") {
+                self.by_control.entry(control).or_insert(node);
+            }
+        }
+
+        for child in &node.children {
+            self.collect(child);
+        }
+    }
+}
+
+// Accessibility nodes of the content of `node`, its shadow tree first
+fn collect_children<'a>(
+    node: &'a Node,
+    path: &mut Vec<Step>,
+    labels: &Labels,
+    scope: Scope<'a>,
+    output: &mut Vec<AccessibilityNode>,
+) {
+    let shadow_children = node
+        .shadow_root
+        .iter()
+        .flat_map(|shadow_root| shadow_root.children.iter().enumerate())
+        .map(|(index, child)| (Step::Shadow(index), child));
+    let children = node
+        .children
+        .iter()
+        .enumerate()
+        .map(|(index, child)| (Step::Child(index), child));
+
+    for (step, child) in shadow_children.chain(children) {
+        path.push(step);
+        collect(child, path, labels, scope, output);
+        path.pop();
+    }
+}
+
+fn collect<'a>(
+    node: &'a Node,
+    path: &mut Vec<Step>,
+    labels: &Labels,
+    mut scope: Scope<'a>,
+    output: &mut Vec<AccessibilityNode>,
+) {
+    match node.node_type() {
+        NodeType::Text => {
+            let text = collapse(node.data.attributes.get("content").map_or("", |text| text));
+
+            if !text.is_empty() {
+                output.push(AccessibilityNode {
+                    role: "text".to_string(),
+                    name: text,
+                    properties: HashMap::new(),
+                    path: path.clone(),
+                    children: Vec::new(),
+                });
+            }
+            return;
+        }
+        NodeType::Comment | NodeType::Doctype => return,
+        NodeType::Element | NodeType::Document => {}
+    }
+
+    if is_hidden(node) {
+        return;
+    }
+
+    let tag_name = node.data.tag_name.as_str();
+    let role = match role(node) {
+        Some(role) if !node.data.attributes.contains_key("role") => {
+            (!scope.layout_table || !TABLE_PARTS.contains(&tag_name)).then_some(role)
+        }
+        role => role,
+    };
+
+    match tag_name {
+        "label" => scope.label = Some(node),
+        "table" => scope.layout_table = matches!(role, Some("none" | "presentation")),
+        _ => {}
+    }
+
+    match role {
+        Some(role) if !["none", "presentation", "generic"].contains(&role) => {
+            let mut children = Vec::new();
+            collect_children(node, path, labels, scope, &mut children);
+
+            output.push(AccessibilityNode {
+                role: role.to_string(),
+                name: name(node, role, labels, scope.label),
+                properties: properties(node, role),
+                path: path.clone(),
+                children,
+            });
+        }
+        _ => collect_children(node, path, labels, scope, output),
+    }
+}
+
+fn is_hidden(node: &Node) -> bool {
+    let attribute = |name: &str| node.data.attributes.get(name).map(String::as_str);
+
+    HIDDEN_ELEMENTS.contains(&node.data.tag_name.as_str())
+        || node.data.in_noscript
+        || attribute("hidden").is_some()
+        || attribute("aria-hidden").is_some_and(|hidden| hidden.trim() == "true")
+        || (node.data.tag_name == "input"
+            && attribute("type").is_some_and(|kind| kind.eq_ignore_ascii_case("hidden")))
+}
+
+// The `role` attribute (its first token) wins over the role implied by the element
+fn role(node: &Node) -> Option<&str> {
+    let explicit = node
+        .data
+        .attributes
+        .get("role")
+        .and_then(|role| role.split_whitespace().next());
+
+    explicit.or_else(|| implicit_role(node))
+}
+
+// https://www.w3.org/TR/html-aria/#docconformance
+fn implicit_role(node: &Node) -> Option<&'static str> {
+    let attribute = |name: &str| node.data.attributes.get(name).map(String::as_str);
+    let named = attribute("aria-label").is_some() || attribute("aria-labelledby").is_some();
+
+    let role = match node.data.tag_name.as_str() {
+        "a" | "area" if attribute("href").is_some() => "link",
+        "article" => "article",
+        "aside" => "complementary",
+        "blockquote" => "blockquote",
+        "button" => "button",
+        "dd" => "definition",
+        "dialog" => "dialog",
+        "dt" => "term",
+        "fieldset" => "group",
+        "figure" => "figure",
+        "footer" => "contentinfo",
+        "form" => "form",
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "heading",
+        "header" => "banner",
+        "hr" => "separator",
+        "img" if attribute("alt") == Some("") => "presentation",
+        "img" => "img",
+        "input" => return input_role(attribute("type").unwrap_or("text")),
+        "li" => "listitem",
+        "main" => "main",
+        "menu" | "ol" | "ul" => "list",
+        "nav" => "navigation",
+        "option" => "option",
+        "p" => "paragraph",
+        "progress" => "progressbar",
+        // Only a landmark when it is named
+        "section" if named => "region",
+        "select"
+            if attribute("multiple").is_some()
+                || attribute("size").and_then(|size| size.parse::<u32>().ok()) > Some(1) =>
+        {
+            "listbox"
+        }
+        "select" => "combobox",
+        "table" => "table",
+        "tbody" | "tfoot" | "thead" => "rowgroup",
+        "td" => "cell",
+        "textarea" => "textbox",
+        "th" => "columnheader",
+        "tr" => "row",
+        _ => return None,
+    };
+
+    Some(role)
+}
+
+fn input_role(kind: &str) -> Option<&'static str> {
+    let role = match kind.to_ascii_lowercase().as_str() {
+        "button" | "image" | "reset" | "submit" => "button",
+        "checkbox" => "checkbox",
+        "number" => "spinbutton",
+        "radio" => "radio",
+        "range" => "slider",
+        "search" => "searchbox",
+        "color" | "date" | "datetime-local" | "file" | "month" | "time" | "week" => return None,
+        // Text fields, unknown types included
+        _ => "textbox",
+    };
+
+    Some(role)
+}
+
+fn name(node: &Node, role: &str, labels: &Labels, label: Option<&Node>) -> String {
+    let attribute = |name: &str| {
+        node.data
+            .attributes
+            .get(name)
+            .map(|value| collapse(value))
+            .filter(|value| !value.is_empty())
+    };
+
+    let labelled_by = node.data.attributes.get("aria-labelledby").map(|ids| {
+        let texts: Vec<String> = ids
+            .split_whitespace()
+            .filter_map(|id| labels.by_id.get(id))
+            .map(|labelling| text_content(labelling))
+            .collect();
+
+        collapse(&texts.join(" "))
+    });
+
+    if let Some(name) = labelled_by.filter(|name| !name.is_empty()) {
+        return name;
+    }
+
+    if let Some(name) = attribute("aria-label") {
+        return name;
+    }
+
+    let tag_name = node.data.tag_name.as_str();
+    let kind = attribute("type").unwrap_or_default().to_ascii_lowercase();
+
+    let native = match tag_name {
+        "img" | "area" => attribute("alt"),
+        "input" if kind == "image" => attribute("alt"),
+        "input" if ["button", "reset", "submit"].contains(&kind.as_str()) => attribute("value")
+            .or_else(|| match kind.as_str() {
+                "reset" => Some("Reset".to_string()),
+                "submit" => Some("Submit".to_string()),
+                _ => None,
+            }),
+        "input" | "select" | "textarea" => {
+            let for_id = node
+                .data
+                .attributes
+                .get("id")
+                .and_then(|id| labels.by_control.get(id.as_str()));
+
+            for_id
+                .copied()
+                .or(label)
+                .map(|label| collapse(&text_content(label)))
+                .filter(|name| !name.is_empty())
+        }
+        _ => None,
+    };
+
+    if let Some(name) = native {
+        return name;
+    }
+
+    if NAME_FROM_CONTENT.contains(&role) {
+        let content = collapse(&text_content(node));
+        if !content.is_empty() {
+            return content;
+        }
+    }
+
+    attribute("title")
+        .or_else(|| attribute("placeholder"))
+        .unwrap_or_default()
+}
+
+fn properties(node: &Node, role: &str) -> HashMap<String, String> {
+    let attribute = |name: &str| node.data.attributes.get(name);
+    let mut properties = HashMap::new();
+
+    if role == "heading" {
+        if let Some(level) = node.data.tag_name.strip_prefix('h') {
+            properties.insert("level".to_string(), level.to_string());
+        }
+    }
+
+    if ["checkbox", "radio"].contains(&role) && node.data.tag_name == "input" {
+        let checked = attribute("checked").is_some();
+        properties.insert("checked".to_string(), checked.to_string());
+    }
+
+    for state in ["disabled", "required"] {
+        if attribute(state).is_some() {
+            properties.insert(state.to_string(), "true".to_string());
+        }
+    }
+
+    for (name, value) in &node.data.attributes {
+        if let Some(property) = name.strip_prefix("aria-") {
+            if !NAMING_ATTRIBUTES.contains(&name.as_str()) {
+                properties.insert(property.to_string(), value.clone());
+            }
+        }
+    }
+
+    properties
+}
+
+// Text of the content of `node` as it is read, images give their alternative text
+fn text_content(node: &Node) -> String {
+    match node.node_type() {
+        NodeType::Text => node
+            .data
+            .attributes
+            .get("content")
+            .cloned()
+            .unwrap_or_default(),
+        NodeType::Element if is_hidden(node) => String::new(),
+        NodeType::Element if node.data.tag_name == "img" => {
+            node.data.attributes.get("alt").cloned().unwrap_or_default()
+        }
+        NodeType::Element | NodeType::Document => {
+            let texts: Vec<String> = node.children.iter().map(text_content).collect();
+
+            texts.join(" ")
+        }
+        NodeType::Comment | NodeType::Doctype => String::new(),
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::{HTMLParser, ParserOptions};
+
+    // One line per node: its role, name and sorted properties, indented by depth
+    fn outline(node: &AccessibilityNode, depth: usize, lines: &mut Vec<String>) {
+        let mut properties: Vec<String> = node
+            .properties
+            .iter()
+            .map(|(name, value)| format!(" {name}={value}"))
+            .collect();
+        properties.sort();
+
+        lines.push(format!(
+            "{}{} {:?}{}",
+            "  ".repeat(depth),
+            node.role,
+            node.name,
+            properties.concat()
+        ));
+
+        for child in &node.children {
+            outline(child, depth + 1, lines);
+        }
+    }
+
+    fn tree(markup: &str) -> Vec<String> {
+        let options = ParserOptions {
+            document: true,
+            ..Default::default()
+        };
+        let document = HTMLParser::with_options(markup, options).parse().unwrap();
+        let mut lines = Vec::new();
+        outline(&accessibility_tree(&document), 0, &mut lines);
+
+        lines
+    }
+
+    #[test]
+    fn test_roles_and_names() {
+        let lines = tree(
+            r#"<html><head><title>Ignored</title></head><body>
+            <nav aria-label="Main"><ul><li><a href="/">Home <img src="h.png" alt="icon"></a></li></ul></nav>
+            <main><h2>Sign <em>in</em></h2>
+            <div><label for="user">User name</label><input id="user" required></div>
+            <label><input type="checkbox" checked> Remember me</label>
+            <button aria-label="Close" aria-expanded="false">x</button>
+            <img src="spacer.gif" alt=""><p hidden>Hidden</p><span aria-hidden="true">*</span>
+            <div role="heading" aria-level="4" title="Custom"></div>
+            </main></body></html>"#,
+        );
+
+        assert_eq!(
+            lines,
+            [
+                r#"document """#,
+                r#"  navigation "Main""#,
+                r#"    list """#,
+                r#"      listitem """#,
+                r#"        link "Home icon""#,
+                r#"          text "Home""#,
+                r#"          img "icon""#,
+                r#"  main """#,
+                r#"    heading "Sign in" level=2"#,
+                r#"      text "Sign""#,
+                r#"      text "in""#,
+                r#"    text "User name""#,
+                r#"    textbox "User name" required=true"#,
+                r#"    checkbox "Remember me" checked=true"#,
+                r#"    text "Remember me""#,
+                r#"    button "Close" expanded=false"#,
+                r#"      text "x""#,
+                r#"    heading "Custom" level=4"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labelledby_and_presentation() {
+        let lines = tree(
+            r#"<html><body><span id="first">Billing</span><span id="second">address</span>
+            <section aria-labelledby="first second"><table role="presentation"><tr><td>
+            <input type="submit"></td></tr></table></section></body></html>"#,
+        );
+
+        assert_eq!(
+            lines,
+            [
+                r#"document """#,
+                r#"  text "Billing""#,
+                r#"  text "address""#,
+                r#"  region "Billing address""#,
+                r#"    button "Submit""#,
+            ]
+        );
+    }
+}
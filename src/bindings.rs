@@ -1,10 +1,54 @@
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    io::Read,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
-use crate::html::{HTMLParser, Node, NodeData};
-use crate::url::{URLError, URL};
+use crate::a11y::{self, AccessibilityNode};
+use crate::auth::Auth;
+use crate::body::RequestBody;
+use crate::cache::{CacheStats, HttpCache};
+use crate::cookie::{Cookie, CookieJar};
+use crate::crawler::{self, CrawlOptions, Crawler};
+use crate::css::{Declaration, Rule, Stylesheet};
+use crate::download::{Download, Progress};
+use crate::filter::{BlockedError as FilterBlocked, Destination, FilterStats, RequestFilter};
+use crate::html::{
+    HTMLParser, Node, NodeData, NodeRef, NodeType, ParseEvent, ParserOptions, SearchPattern,
+    ShadowRoot, SourcePosition, Span, Step, StreamingParser,
+};
+use crate::images::{self, Image, ImageCache};
+use crate::layout::{self, BoxKind, LayoutBox};
+use crate::page::{self, Link, LoadOptions, Page, Prepare};
+use crate::paint::{self, DisplayCommand};
+use crate::pool::{self, ConnectionPool};
+use crate::proxy::{Proxy, ProxyConfig};
+#[cfg(feature = "javascript")]
+use crate::script;
+use crate::sitemap::{self, SitemapEntry};
+use crate::style;
+use crate::tab::Tab;
+use crate::text;
+use crate::tls::{ClientCert, PemSource, TlsOptions};
+use crate::url::{HttpVersion, NetworkError, RetryPolicy, Timeouts, URLError, URL};
+use crate::whatwg_url::{self, URLRecord};
+use crate::xhtml::{self, XhtmlOptions};
+use crate::xml::{XMLError, XMLParser};
 
+use encoding_rs::Encoding;
 use pyo3::prelude::*;
-use pyo3::{exceptions::PyValueError, pyfunction, PyResult};
+use pyo3::types::{PyBytes, PyDict, PyIterator, PyList, PyString};
+use pyo3::{create_exception, exceptions, exceptions::PyValueError, pyfunction, PyResult};
+
+create_exception!(ewb, ConnectError, exceptions::PyConnectionError);
+create_exception!(ewb, TimeoutError, exceptions::PyTimeoutError);
+create_exception!(ewb, BlockedError, exceptions::PyConnectionError);
 
 #[pyclass]
 #[derive(Clone)]
@@ -13,132 +57,2979 @@ pub struct PyNodeData {
     pub tag_name: String,
     #[pyo3(get)]
     pub attributes: HashMap<String, String>,
+    #[pyo3(get)]
+    pub in_noscript: bool,
+}
+
+#[pyclass]
+pub struct PySearchMatch {
+    #[pyo3(get)]
+    pub node: PyNode,
+    #[pyo3(get)]
+    pub start: usize,
+    #[pyo3(get)]
+    pub end: usize,
+    #[pyo3(get)]
+    pub path: Vec<String>,
 }
 
 #[pyclass]
 #[derive(Clone)]
-pub struct PyNode {
+pub struct PyShadowRoot {
+    #[pyo3(get)]
+    pub mode: String,
     #[pyo3(get)]
     pub children: Vec<PyNode>,
+}
+
+/// A piece of the markup: byte offsets into it (as UTF-8) and the line and column, counted
+/// in characters from 1, where it starts.
+#[pyclass]
+#[derive(Clone)]
+pub struct PySpan {
+    #[pyo3(get)]
+    pub start: usize,
     #[pyo3(get)]
-    pub data: PyNodeData,
+    pub end: usize,
+    #[pyo3(get)]
+    pub line: usize,
+    #[pyo3(get)]
+    pub column: usize,
+}
+
+impl From<Span> for PySpan {
+    fn from(span: Span) -> Self {
+        Self {
+            start: span.start,
+            end: span.end,
+            line: span.line,
+            column: span.column,
+        }
+    }
+}
+
+/// Where a node came from in the markup: its start tag (all of a text run or comment) and
+/// its end tag, `None` when the markup leaves it implied.
+#[pyclass]
+#[derive(Clone)]
+pub struct PySourcePosition {
+    #[pyo3(get)]
+    pub start: PySpan,
+    #[pyo3(get)]
+    pub end: Option<PySpan>,
+}
+
+/// A node of a parsed tree. Every node handed out from a tree shares it, which is what lets
+/// them reach their parent and siblings.
+#[pyclass(module = "ewb")]
+#[derive(Clone)]
+pub struct PyNode {
+    tree: Arc<Node>,
+    path: Vec<Step>,
+}
+
+impl PyNode {
+    fn node(&self) -> &Node {
+        // Paths are only ever taken from the tree they point into
+        self.tree.descendant(&self.path).unwrap()
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::at(&self.tree, self.path.clone()).unwrap()
+    }
+
+    fn handle(&self, node_ref: &NodeRef) -> PyNode {
+        PyNode {
+            tree: self.tree.clone(),
+            path: node_ref.path().to_vec(),
+        }
+    }
+
+    fn child_handles(&self, step: fn(usize) -> Step, count: usize) -> Vec<PyNode> {
+        (0..count)
+            .map(|index| PyNode {
+                tree: self.tree.clone(),
+                path: [self.path.as_slice(), &[step(index)]].concat(),
+            })
+            .collect()
+    }
+
+    // Handles for `nodes`, all of them somewhere below this node
+    fn descendant_handles(&self, nodes: &[&Node]) -> Vec<PyNode> {
+        NodeRef::locate(self.node(), nodes)
+            .iter()
+            .map(|found| PyNode {
+                tree: self.tree.clone(),
+                path: [self.path.as_slice(), found.path()].concat(),
+            })
+            .collect()
+    }
+
+    // Ancestors from the outermost down to the parent, the way style resolution takes them
+    fn ancestor_nodes(&self) -> Vec<&Node> {
+        let mut ancestors: Vec<&Node> = self
+            .node_ref()
+            .ancestors()
+            .iter()
+            .map(|ancestor| ancestor.node())
+            .collect();
+        ancestors.reverse();
+
+        ancestors
+    }
+
+    // The given style sheets, the `<style>` elements of the tree otherwise
+    fn with_stylesheets<R>(
+        &self,
+        stylesheets: Option<Vec<PyRef<PyStylesheet>>>,
+        f: impl FnOnce(&[&Stylesheet]) -> R,
+    ) -> R {
+        match stylesheets {
+            Some(stylesheets) => f(&stylesheets
+                .iter()
+                .map(|stylesheet| &stylesheet.stylesheet)
+                .collect::<Vec<_>>()),
+            None => f(&self.tree.stylesheets().iter().collect::<Vec<_>>()),
+        }
+    }
+
+    // Layout of the node with text measured by the Python `measure` callback, if any
+    fn layout_root(
+        &self,
+        py: Python,
+        width: f64,
+        measure: Option<PyObject>,
+        stylesheets: Option<Vec<PyRef<PyStylesheet>>>,
+    ) -> PyResult<LayoutBox> {
+        // Layout can't be interrupted, the first error of the callback is raised after it
+        let error = RefCell::new(None);
+        let measure_text = |text: &str, font_size: f64| {
+            let Some(measure) = &measure else {
+                return layout::estimate_width(text, font_size);
+            };
+
+            match measure
+                .call1(py, (text, font_size))
+                .and_then(|width| width.extract::<f64>(py))
+            {
+                Ok(width) => width,
+                Err(e) => {
+                    error.borrow_mut().get_or_insert(e);
+                    0.0
+                }
+            }
+        };
+
+        let ancestors = self.ancestor_nodes();
+        let root_box = self.with_stylesheets(stylesheets, |stylesheets| {
+            layout::layout(self.node(), &ancestors, stylesheets, width, &measure_text)
+        });
+
+        if let Some(e) = error.into_inner() {
+            return Err(e);
+        }
+
+        Ok(root_box)
+    }
+
+    // Boxes are laid out from this node, their paths start here
+    fn accessibility_node(&self, node: AccessibilityNode) -> PyAccessibilityNode {
+        PyAccessibilityNode {
+            role: node.role,
+            name: node.name,
+            properties: node.properties,
+            node: PyNode {
+                tree: self.tree.clone(),
+                path: [self.path.as_slice(), &node.path].concat(),
+            },
+            children: node
+                .children
+                .into_iter()
+                .map(|child| self.accessibility_node(child))
+                .collect(),
+        }
+    }
+
+    fn layout_box(&self, layout_box: LayoutBox) -> PyLayoutBox {
+        PyLayoutBox {
+            kind: match layout_box.kind {
+                BoxKind::Block => "block",
+                BoxKind::Line => "line",
+                BoxKind::Text => "text",
+            }
+            .to_string(),
+            x: layout_box.x,
+            y: layout_box.y,
+            width: layout_box.width,
+            height: layout_box.height,
+            text: layout_box.text,
+            style: layout_box.style.as_ref().clone(),
+            node: PyNode {
+                tree: self.tree.clone(),
+                path: [self.path.as_slice(), &layout_box.path].concat(),
+            },
+            children: layout_box
+                .children
+                .into_iter()
+                .map(|child| self.layout_box(child))
+                .collect(),
+        }
+    }
+
+    // Shadow tree content (when requested) followed by the light children
+    fn traversal_children(&self, include_shadow: bool) -> Vec<PyNode> {
+        let mut children = match (&self.node().shadow_root, include_shadow) {
+            (Some(shadow_root), true) => {
+                self.child_handles(Step::Shadow, shadow_root.children.len())
+            }
+            _ => Vec::new(),
+        };
+
+        children.extend(self.child_handles(Step::Child, self.node().children.len()));
+
+        children
+    }
 }
 
 #[pymethods]
 impl PyNode {
+    #[getter]
+    fn children(&self) -> Vec<PyNode> {
+        self.child_handles(Step::Child, self.node().children.len())
+    }
+
+    /// One of "element", "text", "comment", "doctype" or "document"
+    #[getter]
+    fn node_type(&self) -> &'static str {
+        match self.node().node_type() {
+            NodeType::Element => "element",
+            NodeType::Text => "text",
+            NodeType::Comment => "comment",
+            NodeType::Doctype => "doctype",
+            NodeType::Document => "document",
+        }
+    }
+
+    #[getter]
+    fn data(&self) -> PyNodeData {
+        let data = &self.node().data;
+
+        PyNodeData {
+            tag_name: data.tag_name.clone(),
+            attributes: data.attributes.clone(),
+            in_noscript: data.in_noscript,
+        }
+    }
+
+    #[getter]
+    fn shadow_root(&self) -> Option<PyShadowRoot> {
+        let shadow_root = self.node().shadow_root.as_ref()?;
+
+        Some(PyShadowRoot {
+            mode: shadow_root.mode.clone(),
+            children: self.child_handles(Step::Shadow, shadow_root.children.len()),
+        })
+    }
+
+    /// Where the node is in the markup, only for trees parsed with `source_positions`
+    #[getter]
+    fn position(&self) -> Option<PySourcePosition> {
+        let position = self.node().position.as_deref()?;
+
+        Some(PySourcePosition {
+            start: position.start.into(),
+            end: position.end.map(PySpan::from),
+        })
+    }
+
+    /// Byte range of the whole node, `source[start:end]` of the encoded markup is its source
+    #[getter]
+    fn source_span(&self) -> Option<(usize, usize)> {
+        self.node().source_span()
+    }
+
+    /// The enclosing element, `None` for the root and the top level of a shadow tree.
+    fn parent(&self) -> Option<PyNode> {
+        self.node_ref().parent().map(|parent| self.handle(&parent))
+    }
+
+    fn next_sibling(&self) -> Option<PyNode> {
+        self.node_ref()
+            .next_sibling()
+            .map(|sibling| self.handle(&sibling))
+    }
+
+    fn previous_sibling(&self) -> Option<PyNode> {
+        self.node_ref()
+            .previous_sibling()
+            .map(|sibling| self.handle(&sibling))
+    }
+
+    /// Enclosing elements, the closest first.
+    fn ancestors(&self) -> Vec<PyNode> {
+        self.node_ref()
+            .ancestors()
+            .iter()
+            .map(|ancestor| self.handle(ancestor))
+            .collect()
+    }
+
     fn get_inmidiate_text_node(&self) -> Option<PyNode> {
         self.get_inmidiate_node("text")
     }
 
-    fn get_all_nodes(&self) -> Vec<PyNode> {
+    #[pyo3(signature = (include_shadow=false))]
+    fn get_all_nodes(&self, include_shadow: bool) -> Vec<PyNode> {
         let mut res = Vec::new();
 
-        if self.data.tag_name != "text" {
+        if self.node().node_type() == NodeType::Element {
             res.push(self.clone());
         }
 
-        for child in &self.children {
-            res.extend(child.get_all_nodes());
+        for child in self.traversal_children(include_shadow) {
+            res.extend(child.get_all_nodes(include_shadow));
         }
 
         res
     }
 
-    fn get_text_nodes(&self) -> Vec<PyNode> {
-        self.get_nodes("text")
+    #[pyo3(signature = (include_shadow=false))]
+    fn get_text_nodes(&self, include_shadow: bool) -> Vec<PyNode> {
+        self.get_nodes("text", include_shadow)
     }
 
     fn get_inmidiate_node(&self, node_type: &str) -> Option<PyNode> {
-        if self.children.len() > 0 {
-            for child in &self.children {
-                if child.data.tag_name == node_type {
-                    return Some(child.clone());
-                }
-            }
-        }
+        self.children()
+            .into_iter()
+            .find(|child| child.node().data.tag_name == node_type)
+    }
+
+    #[pyo3(signature = (pattern, regex=false))]
+    fn search(&self, pattern: &str, regex: bool) -> PyResult<Vec<PySearchMatch>> {
+        let pattern = if regex {
+            SearchPattern::regex(pattern).map_err(|e| PyValueError::new_err(e.to_string()))?
+        } else {
+            SearchPattern::literal(pattern)
+        };
+
+        let matches = self.node().search(&pattern);
+        let nodes: Vec<&Node> = matches.iter().map(|found| found.node).collect();
+
+        Ok(matches
+            .into_iter()
+            .zip(self.descendant_handles(&nodes))
+            .map(|(found, node)| PySearchMatch {
+                node,
+                start: found.start,
+                end: found.end,
+                path: found.path,
+            })
+            .collect())
+    }
+
+    /// First descendant element matching a CSS selector list.
+    fn query_selector(&self, selectors: &str) -> PyResult<Option<PyNode>> {
+        let found = self
+            .node()
+            .query_selector(selectors)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        Ok(self.descendant_handles(&Vec::from_iter(found)).pop())
+    }
+
+    /// First descendant element with the id `id`.
+    fn get_element_by_id(&self, id: &str) -> Option<PyNode> {
+        let found = self.node().get_element_by_id(id)?;
+
+        self.descendant_handles(&[found]).pop()
+    }
+
+    /// Descendant elements having all the space-separated `class_names`.
+    fn get_elements_by_class_name(&self, class_names: &str) -> Vec<PyNode> {
+        self.descendant_handles(&self.node().get_elements_by_class_name(class_names))
+    }
+
+    /// Descendant elements named `tag_name` in any case, `*` for all of them.
+    fn get_elements_by_tag_name(&self, tag_name: &str) -> Vec<PyNode> {
+        self.descendant_handles(&self.node().get_elements_by_tag_name(tag_name))
+    }
+
+    /// Descendant elements with the attribute `attr`, set to `value` when one is given.
+    #[pyo3(signature = (attr, value=None))]
+    fn find(&self, attr: &str, value: Option<&str>) -> Vec<PyNode> {
+        self.descendant_handles(&self.node().find(attr, value))
+    }
+
+    /// Every descendant element matching a CSS selector list, in document order.
+    fn query_selector_all(&self, selectors: &str) -> PyResult<Vec<PyNode>> {
+        let found = self
+            .node()
+            .query_selector_all(selectors)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        Ok(self.descendant_handles(&found))
+    }
+
+    /// The node and its descendants as HTML markup.
+    fn to_html(&self) -> String {
+        self.node().to_html()
+    }
+
+    /// The markup of the node's content.
+    fn inner_html(&self) -> String {
+        self.node().inner_html()
+    }
+
+    /// Readable text of the node, like `innerText`: whitespace collapsed, line breaks
+    /// between blocks, scripts, styles and the head left out.
+    fn text(&self) -> String {
+        self.node().inner_text()
+    }
 
-        None
+    #[pyo3(signature = (xml_declaration=false))]
+    fn to_xhtml(&self, xml_declaration: bool) -> String {
+        xhtml::to_xhtml(self.node(), &XhtmlOptions { xml_declaration })
     }
 
-    fn get_nodes(&self, node_type: &str) -> Vec<PyNode> {
+    #[pyo3(signature = (node_type, include_shadow=false))]
+    fn get_nodes(&self, node_type: &str, include_shadow: bool) -> Vec<PyNode> {
         let mut res = Vec::new();
 
-        for child in &self.children {
-            if child.data.tag_name == node_type {
-                res.push(child.clone());
+        for child in self.traversal_children(include_shadow) {
+            if child.node().data.tag_name == node_type {
+                res.push(child);
             } else {
-                res.extend(child.get_nodes(node_type));
+                res.extend(child.get_nodes(node_type, include_shadow));
             }
         }
 
         res
     }
-}
 
-impl From<&Node> for PyNode {
-    fn from(value: &Node) -> Self {
-        Self {
-            children: value.children.iter().map(PyNode::from).collect(),
-            data: PyNodeData {
-                tag_name: value.data.tag_name.clone(),
-                attributes: value.data.attributes.clone(),
-            },
+    /// Whether both are the same node of the same tree.
+    fn __eq__(&self, other: &PyNode) -> bool {
+        Arc::ptr_eq(&self.tree, &other.tree) && self.path == other.path
+    }
+
+    /// Consistent with `__eq__`, nodes can be kept in sets and used as dict keys.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        Arc::as_ptr(&self.tree).hash(&mut hasher);
+        self.path.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// The node and everything under it as plain data: `tag_name`, `attributes` and
+    /// `children`, plus `in_noscript`, `shadow_root` (`mode` and `children`) and `position`
+    /// (`start` and `end` spans, when parsed with `source_positions`) when set.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        node_dict(py, self.node())
+    }
+
+    /// `to_dict()` as JSON, with sorted keys so equal trees give the same text.
+    #[pyo3(signature = (indent=None))]
+    fn to_json(&self, py: Python<'_>, indent: Option<usize>) -> PyResult<String> {
+        let options = PyDict::new(py);
+        options.set_item("indent", indent)?;
+        options.set_item("sort_keys", true)?;
+
+        py.import("json")?
+            .call_method("dumps", (self.to_dict(py)?,), Some(&options))?
+            .extract()
+    }
+
+    /// Builds a tree from what `to_dict()` returns, the node returned is its root.
+    #[staticmethod]
+    fn from_dict(data: &Bound<'_, PyDict>) -> PyResult<PyNode> {
+        Ok(PyNode::from(dict_node(data)?))
+    }
+
+    // The whole tree is pickled, so the node still reaches its parent and siblings
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(PyObject, PyObject)> {
+        let restore = py.get_type::<PyNode>().getattr("_restore")?;
+        let path: Vec<(&str, usize)> = self
+            .path
+            .iter()
+            .map(|step| match *step {
+                Step::Child(index) => ("child", index),
+                Step::Shadow(index) => ("shadow", index),
+            })
+            .collect();
+        let args = (node_dict(py, &self.tree)?, path);
+
+        Ok((
+            restore.unbind(),
+            args.into_pyobject(py)?.into_any().unbind(),
+        ))
+    }
+
+    #[staticmethod]
+    fn _restore(tree: &Bound<'_, PyDict>, path: Vec<(String, usize)>) -> PyResult<PyNode> {
+        let path = path
+            .into_iter()
+            .map(|(kind, index)| match kind.as_str() {
+                "child" => Ok(Step::Child(index)),
+                "shadow" => Ok(Step::Shadow(index)),
+                _ => Err(PyValueError::new_err(format!("Invalid step: {}", kind))),
+            })
+            .collect::<PyResult<Vec<Step>>>()?;
+        let tree = Arc::new(dict_node(tree)?);
+
+        if tree.descendant(&path).is_none() {
+            return Err(PyValueError::new_err("The path is not in the tree"));
         }
+
+        Ok(PyNode { tree, path })
+    }
+
+    /// Property values of the node after the cascade and inheritance. `stylesheets` default
+    /// to the `<style>` elements of its tree.
+    #[pyo3(signature = (stylesheets=None))]
+    fn computed_style(
+        &self,
+        stylesheets: Option<Vec<PyRef<PyStylesheet>>>,
+    ) -> HashMap<String, String> {
+        let ancestors = self.ancestor_nodes();
+
+        self.with_stylesheets(stylesheets, |stylesheets| {
+            style::computed_style(stylesheets, self.node(), &ancestors)
+        })
+    }
+
+    /// Lays the node out as a block `width` pixels wide. `measure(text, font_size)` gives
+    /// the width of some text in pixels, it is roughly estimated when not given.
+    #[pyo3(signature = (width, measure=None, stylesheets=None))]
+    fn layout(
+        &self,
+        py: Python,
+        width: f64,
+        measure: Option<PyObject>,
+        stylesheets: Option<Vec<PyRef<PyStylesheet>>>,
+    ) -> PyResult<PyLayoutBox> {
+        let root_box = self.layout_root(py, width, measure, stylesheets)?;
+
+        Ok(self.layout_box(root_box))
+    }
+
+    /// Drawing commands for the node laid out `width` pixels wide, for a viewport scrolled
+    /// `scroll` pixels down and `height` pixels high (unbounded when not given). Their
+    /// coordinates are relative to the viewport, the ones outside of it are left out.
+    #[pyo3(signature = (width, scroll=0.0, height=None, measure=None, stylesheets=None))]
+    fn display_list(
+        &self,
+        py: Python,
+        width: f64,
+        scroll: f64,
+        height: Option<f64>,
+        measure: Option<PyObject>,
+        stylesheets: Option<Vec<PyRef<PyStylesheet>>>,
+    ) -> PyResult<Vec<PyDisplayCommand>> {
+        let root_box = self.layout_root(py, width, measure, stylesheets)?;
+
+        Ok(paint::display_list(&root_box, scroll, height)
+            .into_iter()
+            .map(PyDisplayCommand::from)
+            .collect())
+    }
+
+    /// Parsed `<style>` elements below the node, in document order.
+    fn stylesheets(&self) -> Vec<PyStylesheet> {
+        self.node()
+            .stylesheets()
+            .into_iter()
+            .map(|stylesheet| PyStylesheet { stylesheet })
+            .collect()
+    }
+
+    /// What assistive technology gets from the node: roles, accessible names and states.
+    fn accessibility_tree(&self) -> PyAccessibilityNode {
+        self.accessibility_node(a11y::accessibility_tree(self.node()))
     }
 }
 
-impl Into<Node> for &PyNode {
-    fn into(self) -> Node {
-        Node {
-            children: self.children.iter().map(|node| node.into()).collect(),
-            data: NodeData {
-                tag_name: self.data.tag_name.clone(),
-                attributes: self.data.attributes.clone(),
-            },
+#[pyclass]
+#[derive(Clone)]
+pub struct PyAccessibilityNode {
+    /// ARIA role, "text" for runs of text
+    #[pyo3(get)]
+    pub role: String,
+    #[pyo3(get)]
+    pub name: String,
+    /// States and properties like "level" or "checked", `aria-*` attributes without prefix
+    #[pyo3(get)]
+    pub properties: HashMap<String, String>,
+    #[pyo3(get)]
+    pub node: PyNode,
+    #[pyo3(get)]
+    pub children: Vec<PyAccessibilityNode>,
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyLayoutBox {
+    /// One of "block", "line" or "text"
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub x: f64,
+    #[pyo3(get)]
+    pub y: f64,
+    #[pyo3(get)]
+    pub width: f64,
+    #[pyo3(get)]
+    pub height: f64,
+    /// The word of a text box
+    #[pyo3(get)]
+    pub text: Option<String>,
+    #[pyo3(get)]
+    pub style: HashMap<String, String>,
+    /// Anonymous blocks and lines have the node of the block containing them
+    #[pyo3(get)]
+    pub node: PyNode,
+    #[pyo3(get)]
+    pub children: Vec<PyLayoutBox>,
+}
+
+#[pyclass]
+pub struct PyDrawRect {
+    #[pyo3(get)]
+    pub x: f64,
+    #[pyo3(get)]
+    pub y: f64,
+    #[pyo3(get)]
+    pub width: f64,
+    #[pyo3(get)]
+    pub height: f64,
+    #[pyo3(get)]
+    pub color: String,
+}
+
+/// A word to draw with its top left corner at `x`, `y`.
+#[pyclass]
+pub struct PyDrawText {
+    #[pyo3(get)]
+    pub x: f64,
+    #[pyo3(get)]
+    pub y: f64,
+    #[pyo3(get)]
+    pub text: String,
+    #[pyo3(get)]
+    pub color: String,
+    #[pyo3(get)]
+    pub font_size: f64,
+    #[pyo3(get)]
+    pub font_weight: String,
+    #[pyo3(get)]
+    pub font_style: String,
+}
+
+#[derive(IntoPyObject)]
+pub enum PyDisplayCommand {
+    Rect(PyDrawRect),
+    Text(PyDrawText),
+}
+
+impl From<DisplayCommand> for PyDisplayCommand {
+    fn from(value: DisplayCommand) -> Self {
+        match value {
+            DisplayCommand::DrawRect {
+                x,
+                y,
+                width,
+                height,
+                color,
+            } => PyDisplayCommand::Rect(PyDrawRect {
+                x,
+                y,
+                width,
+                height,
+                color,
+            }),
+            DisplayCommand::DrawText {
+                x,
+                y,
+                text,
+                color,
+                font_size,
+                font_weight,
+                font_style,
+            } => PyDisplayCommand::Text(PyDrawText {
+                x,
+                y,
+                text,
+                color,
+                font_size,
+                font_weight,
+                font_style,
+            }),
         }
     }
 }
 
-#[pyfunction]
-pub fn request(url: &str) -> PyResult<String> {
-    let mut url_intent = URL::new(url.to_string());
+#[pyclass]
+pub struct PyStylesheet {
+    stylesheet: Stylesheet,
+}
 
-    match &mut url_intent {
-        Ok(url) => {
-            if let Ok(response) = url.request() {
-                Ok(response.clone())
-            } else {
-                Err(PyValueError::new_err("Error: unable to send request"))
-            }
+#[pymethods]
+impl PyStylesheet {
+    /// Parses style sheet text, what can't be understood is left out.
+    #[new]
+    fn new(css: &str) -> Self {
+        Self {
+            stylesheet: Stylesheet::parse(css),
         }
-        Err(error) => {
-            // potential issue we downcast to something else
-            if let Some(url_error) = error.downcast_ref::<URLError>() {
-                Err(PyValueError::new_err(url_error.message.clone()))
-            } else {
-                Err(PyValueError::new_err(
-                    "Error: unable to create URL instance",
-                ))
-            }
+    }
+
+    #[getter]
+    fn rules(&self) -> Vec<PyRule> {
+        self.stylesheet.rules.iter().map(PyRule::from).collect()
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyRule {
+    #[pyo3(get)]
+    pub selector: String,
+    /// (ids, classes and attributes, types) for each selector of the list
+    #[pyo3(get)]
+    pub specificity: Vec<(u32, u32, u32)>,
+    #[pyo3(get)]
+    pub declarations: Vec<PyDeclaration>,
+}
+
+impl From<&Rule> for PyRule {
+    fn from(value: &Rule) -> Self {
+        Self {
+            selector: value.selector_text.clone(),
+            specificity: value.specificities(),
+            declarations: value.declarations.iter().map(PyDeclaration::from).collect(),
         }
     }
 }
 
-#[pyfunction]
-pub fn load(body: &str) -> PyResult<PyNode> {
-    let mut parser = HTMLParser::new(body);
-    let root = parser.parse().unwrap();
+#[pyclass]
+#[derive(Clone)]
+pub struct PyDeclaration {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub value: String,
+    #[pyo3(get)]
+    pub important: bool,
+}
 
-    Ok(PyNode::from(&root))
+impl From<&Declaration> for PyDeclaration {
+    fn from(value: &Declaration) -> Self {
+        Self {
+            name: value.name.clone(),
+            value: value.value.clone(),
+            important: value.important,
+        }
+    }
 }
 
-#[pyfunction]
-pub fn find_text_nodes(pynode: &PyNode) -> PyResult<Vec<PyNode>> {
-    let node: Node = pynode.into();
-    let text_nodes = node.find_text_nodes();
+#[pyclass]
+pub struct PySitemapEntry {
+    #[pyo3(get)]
+    pub loc: String,
+    #[pyo3(get)]
+    pub lastmod: Option<String>,
+    #[pyo3(get)]
+    pub changefreq: Option<String>,
+    #[pyo3(get)]
+    pub priority: Option<f32>,
+}
 
-    Ok(text_nodes
-        .iter()
-        .map(|n| PyNode::from(n.to_owned()))
-        .collect())
+impl From<SitemapEntry> for PySitemapEntry {
+    fn from(value: SitemapEntry) -> Self {
+        Self {
+            loc: value.loc,
+            lastmod: value.lastmod,
+            changefreq: value.changefreq,
+            priority: value.priority,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyPage {
+    #[pyo3(get)]
+    pub url: String,
+    #[pyo3(get)]
+    pub mime_type: String,
+    /// Name of the encoding the text was decoded from, e.g. "windows-1252"
+    #[pyo3(get)]
+    pub encoding: String,
+    /// One of "document", "text" or "download"
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub document: Option<PyNode>,
+    #[pyo3(get)]
+    pub text: Option<String>,
+    #[pyo3(get)]
+    pub redirects: Vec<String>,
+    pub body: Vec<u8>,
+}
+
+#[pymethods]
+impl PyPage {
+    #[getter]
+    fn body<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.body)
+    }
+
+    /// URLs of the a/area, img, link and script elements, absolute and in document order.
+    fn links(&self) -> Vec<PyLink> {
+        match &self.document {
+            Some(document) => page::links(document.node(), &self.url)
+                .into_iter()
+                .map(PyLink::from)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyLink {
+    /// One of "anchor", "image", "link" or "script"
+    #[pyo3(get)]
+    pub kind: String,
+    #[pyo3(get)]
+    pub url: String,
+}
+
+/// Decoded pixels of an image.
+#[pyclass]
+pub struct PyImage {
+    image: Arc<Image>,
+}
+
+#[pymethods]
+impl PyImage {
+    #[getter]
+    fn width(&self) -> u32 {
+        self.image.width
+    }
+
+    #[getter]
+    fn height(&self) -> u32 {
+        self.image.height
+    }
+
+    /// RGBA, 4 bytes per pixel row after row.
+    #[getter]
+    fn pixels<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.image.pixels)
+    }
+}
+
+/// How a download ended.
+#[pyclass]
+pub struct PyDownload {
+    /// Size of the file
+    #[pyo3(get)]
+    pub size: u64,
+    /// Full size announced by the server
+    #[pyo3(get)]
+    pub total: Option<u64>,
+    /// The file was completed from where an earlier download stopped
+    #[pyo3(get)]
+    pub resumed: bool,
+}
+
+impl From<Download> for PyDownload {
+    fn from(value: Download) -> Self {
+        Self {
+            size: value.size,
+            total: value.total,
+            resumed: value.resumed,
+        }
+    }
+}
+
+// Downloads through `prepare`, giving `progress` the bytes written so far and the total
+fn download_to(
+    py: Python<'_>,
+    url: &str,
+    path: PathBuf,
+    progress: Option<PyObject>,
+    resume: bool,
+    prepare: Option<&Prepare>,
+) -> PyResult<PyDownload> {
+    // An exception raised by the callback cancels the download and is raised again here
+    let raised = Arc::new(Mutex::new(None));
+    let progress = progress.map(|callback| {
+        let raised = Arc::clone(&raised);
+        Box::new(move |written: u64, total: Option<u64>| {
+            Python::with_gil(|py| callback.call1(py, (written, total)).map(drop)).map_err(|error| {
+                *raised.lock().unwrap() = Some(error);
+                io::Error::other("Download cancelled by the progress callback")
+            })
+        }) as Progress
+    });
+
+    let result = py.allow_threads(|| {
+        crate::download::download(url, &path, resume, prepare, progress)
+            .map_err(|error| url_error(error, "Error: unable to download"))
+    });
+
+    let raised = raised.lock().unwrap().take();
+    match raised {
+        Some(error) => Err(error),
+        None => result.map(PyDownload::from),
+    }
+}
+
+impl From<Link> for PyLink {
+    fn from(value: Link) -> Self {
+        Self {
+            kind: value.kind.as_str().to_string(),
+            url: value.url,
+        }
+    }
+}
+
+/// Response headers, looked up case-insensitively.
+#[pyclass]
+#[derive(Clone)]
+pub struct PyHeaders {
+    /// Lowercase names, Set-Cookie has an entry per value
+    headers: Vec<(String, String)>,
+}
+
+#[pymethods]
+impl PyHeaders {
+    /// Repeated headers come back joined with ", ", see `get_all`.
+    #[pyo3(signature = (name, default=None))]
+    fn get(&self, name: &str, default: Option<String>) -> Option<String> {
+        let values = self.get_all(name);
+
+        match values.is_empty() {
+            true => default,
+            false => Some(values.join(", ")),
+        }
+    }
+
+    fn get_all(&self, name: &str) -> Vec<String> {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.headers.iter().map(|(key, _)| key.clone()).collect();
+        keys.dedup();
+        keys
+    }
+
+    fn items(&self) -> Vec<(String, String)> {
+        self.headers.clone()
+    }
+
+    fn __getitem__(&self, name: &str) -> PyResult<String> {
+        self.get(name, None)
+            .ok_or_else(|| exceptions::PyKeyError::new_err(name.to_string()))
+    }
+
+    fn __contains__(&self, name: &str) -> bool {
+        self.headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    fn __len__(&self) -> usize {
+        self.keys().len()
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, self.keys())?.try_iter()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("PyHeaders({:?})", self.headers)
+    }
+}
+
+#[pyclass]
+pub struct PyResponse {
+    /// Final URL, after redirects
+    #[pyo3(get)]
+    pub url: String,
+    #[pyo3(get)]
+    pub status: usize,
+    #[pyo3(get)]
+    pub reason: String,
+    #[pyo3(get)]
+    pub version: String,
+    #[pyo3(get)]
+    pub headers: PyHeaders,
+    #[pyo3(get)]
+    pub redirects: Vec<String>,
+    pub body: Vec<u8>,
+    pub encoding: &'static Encoding,
+}
+
+#[pymethods]
+impl PyResponse {
+    #[getter]
+    fn body<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.body)
+    }
+
+    /// Name of the encoding `text` decodes the body from, from the Content-Type charset or
+    /// a `<meta>` declaration.
+    #[getter]
+    fn encoding(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    /// Body transcoded to a string, invalid sequences are replaced.
+    #[getter]
+    fn text(&self) -> String {
+        self.encoding.decode(&self.body).0.into_owned()
+    }
+
+    /// Whether the status is a 2xx one.
+    #[getter]
+    fn ok(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<PyResponse [{}] {}>", self.status, self.url)
+    }
+}
+
+impl From<&URL> for PyResponse {
+    fn from(value: &URL) -> Self {
+        Self {
+            url: value.url().to_string(),
+            status: value.status(),
+            reason: value.reason().to_string(),
+            version: value.version().to_string(),
+            headers: PyHeaders {
+                headers: value.headers(),
+            },
+            redirects: value.redirects().to_vec(),
+            body: value.body().to_vec(),
+            encoding: value.encoding(),
+        }
+    }
+}
+
+/// A parsed URL with a `URLSearchParams`-like query API.
+#[pyclass]
+pub struct PyURL {
+    record: URLRecord,
+}
+
+#[pymethods]
+impl PyURL {
+    #[new]
+    #[pyo3(signature = (url, base=None))]
+    fn new(url: &str, base: Option<&str>) -> PyResult<Self> {
+        let base = base
+            .map(|base| URLRecord::parse(base, None))
+            .transpose()
+            .map_err(|error| PyValueError::new_err(error.message))?;
+        let record = URLRecord::parse(url, base.as_ref())
+            .map_err(|error| PyValueError::new_err(error.message))?;
+
+        Ok(Self { record })
+    }
+
+    #[getter]
+    fn href(&self) -> String {
+        self.record.href()
+    }
+
+    #[getter]
+    fn scheme(&self) -> String {
+        self.record.scheme.clone()
+    }
+
+    #[getter]
+    fn host(&self) -> String {
+        self.record.host_str()
+    }
+
+    /// `None` when it is the scheme's default port.
+    #[getter]
+    fn port(&self) -> Option<u16> {
+        self.record.port
+    }
+
+    #[getter]
+    fn path(&self) -> String {
+        self.record.pathname()
+    }
+
+    /// Still percent-encoded, see `params`.
+    #[getter]
+    fn query(&self) -> Option<String> {
+        self.record.query.clone()
+    }
+
+    #[getter]
+    fn fragment(&self) -> Option<String> {
+        self.record.fragment.clone()
+    }
+
+    #[getter]
+    fn origin(&self) -> String {
+        self.record.origin()
+    }
+
+    /// Decoded query parameters, in order.
+    fn params(&self) -> Vec<(String, String)> {
+        self.record.search_params()
+    }
+
+    fn get_param(&self, name: &str) -> Option<String> {
+        self.record.search_param(name)
+    }
+
+    fn get_all_params(&self, name: &str) -> Vec<String> {
+        self.record
+            .search_params()
+            .into_iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Replaces every `name` parameter with a single one.
+    fn set_param(&mut self, name: &str, value: &str) {
+        self.record.set_search_param(name, value);
+    }
+
+    fn append_param(&mut self, name: &str, value: &str) {
+        self.record.append_search_param(name, value);
+    }
+
+    fn delete_param(&mut self, name: &str) {
+        self.record.delete_search_param(name);
+    }
+
+    /// Resolves `relative` against this URL.
+    fn join(&self, relative: &str) -> PyResult<Self> {
+        let record = URLRecord::parse(relative, Some(&self.record))
+            .map_err(|error| PyValueError::new_err(error.message))?;
+
+        Ok(Self { record })
+    }
+
+    fn __str__(&self) -> String {
+        self.href()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("PyURL({:?})", self.href())
+    }
+}
+
+/// Python generator over a running crawl, see `crawl`.
+#[pyclass]
+pub struct PyCrawler {
+    crawler: Mutex<Crawler>,
+}
+
+#[pymethods]
+impl PyCrawler {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> Option<PyPage> {
+        // Fetching can take a while, other Python threads keep running meanwhile
+        let page = py.allow_threads(|| self.crawler.lock().unwrap().next());
+
+        page.as_ref().map(PyPage::from)
+    }
+}
+
+impl From<&Page> for PyPage {
+    fn from(value: &Page) -> Self {
+        Self {
+            url: value.url.clone(),
+            mime_type: value.mime_type.clone(),
+            encoding: value.encoding.name().to_string(),
+            kind: value.kind.as_str().to_string(),
+            document: value.document.clone().map(PyNode::from),
+            text: value.text(),
+            redirects: value.redirects.clone(),
+            body: value.body.clone(),
+        }
+    }
+}
+
+impl From<Node> for PyNode {
+    fn from(value: Node) -> Self {
+        Self {
+            tree: Arc::new(value),
+            path: Vec::new(),
+        }
+    }
+}
+
+// Plain data of `node` and everything under it, see `PyNode.to_dict`
+fn node_dict<'py>(py: Python<'py>, node: &Node) -> PyResult<Bound<'py, PyDict>> {
+    let children = |children: &[Node]| {
+        children
+            .iter()
+            .map(|child| node_dict(py, child))
+            .collect::<PyResult<Vec<_>>>()
+    };
+
+    let dict = PyDict::new(py);
+    dict.set_item("tag_name", &node.data.tag_name)?;
+    dict.set_item("attributes", &node.data.attributes)?;
+    dict.set_item("children", children(&node.children)?)?;
+
+    if node.data.in_noscript {
+        dict.set_item("in_noscript", true)?;
+    }
+
+    if let Some(position) = &node.position {
+        let span_dict = |span: &Span| -> PyResult<Bound<'py, PyDict>> {
+            let dict = PyDict::new(py);
+            dict.set_item("start", span.start)?;
+            dict.set_item("end", span.end)?;
+            dict.set_item("line", span.line)?;
+            dict.set_item("column", span.column)?;
+            Ok(dict)
+        };
+
+        let position_dict = PyDict::new(py);
+        position_dict.set_item("start", span_dict(&position.start)?)?;
+        position_dict.set_item("end", position.end.as_ref().map(span_dict).transpose()?)?;
+        dict.set_item("position", position_dict)?;
+    }
+
+    if let Some(shadow_root) = &node.shadow_root {
+        let shadow = PyDict::new(py);
+        shadow.set_item("mode", &shadow_root.mode)?;
+        shadow.set_item("children", children(&shadow_root.children)?)?;
+        dict.set_item("shadow_root", shadow)?;
+    }
+
+    Ok(dict)
+}
+
+// The tree `node_dict` turned into `dict`, `attributes` and `children` may be left out
+fn dict_node(dict: &Bound<'_, PyDict>) -> PyResult<Node> {
+    let children = |dict: &Bound<'_, PyDict>| -> PyResult<Vec<Node>> {
+        match dict.get_item("children")? {
+            Some(children) => children
+                .extract::<Vec<Bound<'_, PyDict>>>()?
+                .iter()
+                .map(dict_node)
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    };
+
+    let tag_name: String = dict
+        .get_item("tag_name")?
+        .ok_or_else(|| PyValueError::new_err("A node needs a tag_name"))?
+        .extract()?;
+    let attributes = match dict.get_item("attributes")? {
+        Some(attributes) => attributes.extract()?,
+        None => HashMap::new(),
+    };
+
+    // What serializing them reads
+    let required = match tag_name.as_str() {
+        "text" | "#comment" => Some("content"),
+        "#doctype" => Some("name"),
+        _ => None,
+    };
+    if let Some(name) = required.filter(|name| !attributes.contains_key(*name)) {
+        return Err(PyValueError::new_err(format!(
+            "A {} node needs a {} attribute",
+            tag_name, name
+        )));
+    }
+    let in_noscript = match dict.get_item("in_noscript")? {
+        Some(in_noscript) => in_noscript.extract()?,
+        None => false,
+    };
+
+    let data = NodeData {
+        tag_name,
+        attributes,
+        in_noscript,
+    };
+    let mut node = Node::new(data, children(dict)?);
+
+    if let Some(position) = dict.get_item("position")? {
+        if !position.is_none() {
+            let span = |span: Bound<'_, PyAny>| -> PyResult<Span> {
+                Ok(Span {
+                    start: span.get_item("start")?.extract()?,
+                    end: span.get_item("end")?.extract()?,
+                    line: span.get_item("line")?.extract()?,
+                    column: span.get_item("column")?.extract()?,
+                })
+            };
+            let end = position.get_item("end")?;
+
+            node.position = Some(Box::new(SourcePosition {
+                start: span(position.get_item("start")?)?,
+                end: match end.is_none() {
+                    true => None,
+                    false => Some(span(end)?),
+                },
+            }));
+        }
+    }
+
+    if let Some(shadow) = dict.get_item("shadow_root")? {
+        if !shadow.is_none() {
+            let shadow = shadow.downcast::<PyDict>()?;
+            node.shadow_root = Some(ShadowRoot {
+                mode: shadow
+                    .get_item("mode")?
+                    .map_or(Ok("open".to_string()), |mode| mode.extract())?,
+                children: children(shadow)?,
+            });
+        }
+    }
+
+    Ok(node)
+}
+
+/// Adapts a Python file-like object (anything with `read(n)`) to `Read`.
+struct PyFileReader {
+    file: PyObject,
+    // Text mode files hand back `n` characters which may be more than `n` bytes
+    leftover: Vec<u8>,
+}
+
+impl Read for PyFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.leftover.is_empty() {
+            self.leftover = Python::with_gil(|py| -> PyResult<Vec<u8>> {
+                let chunk = self.file.call_method1(py, "read", (buf.len(),))?;
+                let chunk = chunk.bind(py);
+
+                if let Ok(bytes) = chunk.downcast::<PyBytes>() {
+                    Ok(bytes.as_bytes().to_vec())
+                } else {
+                    Ok(chunk.extract::<String>()?.into_bytes())
+                }
+            })
+            .map_err(|error| io::Error::other(error.to_string()))?;
+        }
+
+        let size = self.leftover.len().min(buf.len());
+        buf[..size].copy_from_slice(&self.leftover[..size]);
+        self.leftover.drain(..size);
+
+        Ok(size)
+    }
+}
+
+// Seekable files tell us how much is left so we can send a Content-Length instead of chunks
+fn remaining_length(file: &Bound<'_, PyAny>) -> Option<u64> {
+    if !file
+        .call_method0("seekable")
+        .and_then(|seekable| seekable.extract::<bool>())
+        .unwrap_or(false)
+    {
+        return None;
+    }
+
+    let position: u64 = file.call_method0("tell").ok()?.extract().ok()?;
+    let end: u64 = file.call_method1("seek", (0, 2)).ok()?.extract().ok()?;
+    file.call_method1("seek", (position,)).ok()?;
+
+    end.checked_sub(position)
+}
+
+/// Accepts bytes / str content, a file-like object or an `os.PathLike` to stream from disk.
+fn request_body(body: &Bound<'_, PyAny>) -> PyResult<RequestBody> {
+    if let Ok(bytes) = body.downcast::<PyBytes>() {
+        return Ok(RequestBody::Bytes(bytes.as_bytes().to_vec()));
+    }
+
+    if let Ok(text) = body.downcast::<PyString>() {
+        return Ok(RequestBody::Bytes(text.to_str()?.as_bytes().to_vec()));
+    }
+
+    if body.hasattr("read")? {
+        let length = remaining_length(body);
+        let reader = PyFileReader {
+            file: body.clone().unbind(),
+            leftover: Vec::new(),
+        };
+
+        return Ok(RequestBody::from_reader(reader, length));
+    }
+
+    let path: PathBuf = body.extract()?;
+    RequestBody::from_file(path).map_err(|error| PyValueError::new_err(error.to_string()))
+}
+
+fn parse_http_version(http_version: Option<&str>) -> PyResult<Option<HttpVersion>> {
+    http_version
+        .map(|http_version| http_version.parse::<HttpVersion>())
+        .transpose()
+        .map_err(|error| PyValueError::new_err(error.message))
+}
+
+/// Per-request keyword arguments shared by `request` and `PySession.request`.
+struct RequestOptions<'a, 'py> {
+    http_version: Option<HttpVersion>,
+    method: Option<&'a str>,
+    headers: Option<&'a Bound<'py, PyAny>>,
+    body: Option<&'a Bound<'py, PyAny>>,
+    max_redirects: Option<usize>,
+    /// Seconds, for every read and write
+    timeout: Option<f64>,
+    connect_timeout: Option<f64>,
+    retries: Option<usize>,
+    /// Replaces the session's (or environment's) proxies
+    proxy: Option<&'a str>,
+    /// Replaces the session's TLS options
+    tls: Option<TlsOptions>,
+    /// Replaces credentials from the URL's userinfo
+    auth: Option<Auth>,
+}
+
+/// Accepts a mapping or an iterable of `(name, value)` pairs.
+fn request_headers(headers: &Bound<'_, PyAny>) -> PyResult<Vec<(String, String)>> {
+    if let Ok(headers) = headers.downcast::<PyDict>() {
+        return headers
+            .iter()
+            .map(|(name, value)| Ok((name.extract()?, value.extract()?)))
+            .collect();
+    }
+
+    headers.extract()
+}
+
+fn url_error(error: Box<dyn std::error::Error>, fallback: &str) -> PyErr {
+    match error.downcast_ref::<NetworkError>() {
+        Some(NetworkError::Connect(message)) => return ConnectError::new_err(message.clone()),
+        Some(NetworkError::Timeout(message)) => return TimeoutError::new_err(message.clone()),
+        None => {}
+    }
+
+    if let Some(blocked) = error.downcast_ref::<FilterBlocked>() {
+        return BlockedError::new_err(blocked.to_string());
+    }
+
+    match error.downcast_ref::<URLError>() {
+        Some(url_error) => PyValueError::new_err(url_error.message.clone()),
+        None => PyValueError::new_err(fallback.to_string()),
+    }
+}
+
+// Sets up the request while the GIL is held, Python objects can't be touched after that
+fn prepare_request(
+    url: &str,
+    options: &RequestOptions,
+    session: Option<&PySession>,
+) -> PyResult<URL> {
+    let mut url = URL::new(url.to_string())
+        .map_err(|error| url_error(error, "Error: unable to create URL instance"))?;
+
+    if let Some(http_version) = options.http_version {
+        url.set_http_version(http_version);
+    }
+
+    if let Some(body) = options.body {
+        url.set_body(request_body(body)?);
+    }
+
+    if let Some(method) = options.method {
+        url.set_method(method)
+            .map_err(|error| url_error(error, "Error: invalid method"))?;
+    }
+
+    if let Some(headers) = options.headers {
+        for (name, value) in request_headers(headers)? {
+            url.set_header(&name, &value)
+                .map_err(|error| url_error(error, "Error: invalid header"))?;
+        }
+    }
+
+    if let Some(max_redirects) = options.max_redirects {
+        url.set_max_redirects(max_redirects);
+    }
+
+    let mut timeouts = Timeouts::default();
+    if let Some(timeout) = options.timeout {
+        timeouts.read = Some(duration(timeout)?);
+        timeouts.write = Some(duration(timeout)?);
+    }
+    if let Some(connect_timeout) = options.connect_timeout {
+        timeouts.connect = Some(duration(connect_timeout)?);
+    }
+    url.set_timeouts(timeouts);
+
+    if let Some(retries) = options.retries {
+        url.set_retry_policy(RetryPolicy {
+            retries,
+            ..Default::default()
+        });
+    }
+
+    let proxies = match options.proxy {
+        Some(proxy) => Some(Arc::new(proxy_config(proxy)?)),
+        None => session.map(|session| Arc::clone(&session.proxies)),
+    };
+    if let Some(proxies) = proxies {
+        url.set_proxies(proxies);
+    }
+
+    let tls = match &options.tls {
+        Some(tls) => Some(Arc::new(tls.clone())),
+        None => session.map(|session| Arc::clone(&session.tls)),
+    };
+    if let Some(tls) = tls {
+        url.set_tls(tls);
+    }
+
+    if let Some(auth) = &options.auth {
+        url.set_auth(auth.clone());
+    }
+
+    if let Some(session) = session {
+        session.attach(&mut url);
+    }
+
+    Ok(url)
+}
+
+fn perform_request(url: &mut URL) -> PyResult<String> {
+    match url.request() {
+        Ok(response) => Ok(response.clone()),
+        Err(error) => Err(url_error(error, "Error: unable to send request")),
+    }
+}
+
+fn perform_fetch(url: &mut URL) -> PyResult<PyResponse> {
+    match url.fetch() {
+        Ok(()) => Ok(PyResponse::from(&*url)),
+        Err(error) => Err(url_error(error, "Error: unable to send request")),
+    }
+}
+
+fn send_fetch(
+    py: Python<'_>,
+    url: &str,
+    options: RequestOptions,
+    session: Option<&PySession>,
+) -> PyResult<PyResponse> {
+    let mut url = prepare_request(url, &options, session)?;
+
+    py.allow_threads(|| perform_fetch(&mut url))
+}
+
+fn send_request(
+    py: Python<'_>,
+    url: &str,
+    options: RequestOptions,
+    session: Option<&PySession>,
+) -> PyResult<String> {
+    let mut url = prepare_request(url, &options, session)?;
+
+    py.allow_threads(|| perform_request(&mut url))
+}
+
+/// Fetches `urls` on up to `max_concurrency` threads. Results keep the order of `urls`,
+/// failed requests give their exception instead of a body.
+fn fetch_all(
+    py: Python<'_>,
+    urls: Vec<String>,
+    max_concurrency: usize,
+    options: RequestOptions,
+    session: Option<&PySession>,
+) -> PyResult<Vec<PyObject>> {
+    if max_concurrency == 0 {
+        return Err(PyValueError::new_err("max_concurrency must be at least 1"));
+    }
+
+    let requests: Vec<PyResult<URL>> = urls
+        .iter()
+        .map(|url| prepare_request(url, &options, session))
+        .collect();
+    let count = requests.len();
+    let queue = Mutex::new(requests.into_iter().enumerate());
+    let results: Mutex<Vec<Option<PyResult<String>>>> =
+        Mutex::new((0..count).map(|_| None).collect());
+
+    py.allow_threads(|| {
+        thread::scope(|scope| {
+            for _ in 0..max_concurrency.min(count) {
+                scope.spawn(|| loop {
+                    let Some((index, request)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let result = request.and_then(|mut url| perform_request(&mut url));
+
+                    results.lock().unwrap()[index] = Some(result);
+                });
+            }
+        })
+    });
+
+    Ok(results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| match result.expect("every request is performed") {
+            Ok(body) => PyString::new(py, &body).into_any().unbind(),
+            Err(error) => error.into_value(py).into_any(),
+        })
+        .collect())
+}
+
+/// A proxy URL used for every request, an empty string connects directly.
+fn proxy_config(proxy: &str) -> PyResult<ProxyConfig> {
+    if proxy.is_empty() {
+        return Ok(ProxyConfig::default());
+    }
+
+    Proxy::parse(proxy)
+        .map(ProxyConfig::all)
+        .map_err(|error| url_error(error, "Error: invalid proxy"))
+}
+
+/// A `(username, password)` tuple for Basic authentication or a Bearer token.
+fn request_auth(auth: &Bound<'_, PyAny>) -> PyResult<Auth> {
+    if let Ok((username, password)) = auth.extract::<(String, String)>() {
+        return Ok(Auth::Basic { username, password });
+    }
+
+    auth.extract::<String>().map(Auth::Bearer).map_err(|_| {
+        PyValueError::new_err("auth must be a (username, password) tuple or a bearer token")
+    })
+}
+
+/// TLS options from keyword arguments, `None` when they are all left out. Certificates are
+/// loaded right away so mistakes are reported before anything is sent.
+fn tls_options(
+    ca_bundle: Option<&Bound<'_, PyAny>>,
+    client_cert: Option<&Bound<'_, PyAny>>,
+    client_key: Option<&Bound<'_, PyAny>>,
+    danger_accept_invalid_certs: bool,
+) -> PyResult<Option<TlsOptions>> {
+    let client_cert = match (client_cert, client_key) {
+        (Some(certificates), key) => Some(ClientCert {
+            certificates: pem_source(certificates)?,
+            // The key is often kept in the same file as the certificate
+            key: pem_source(key.unwrap_or(certificates))?,
+        }),
+        (None, Some(_)) => return Err(PyValueError::new_err("client_key needs a client_cert")),
+        (None, None) => None,
+    };
+
+    let options = TlsOptions {
+        ca_bundle: ca_bundle.map(pem_source).transpose()?,
+        client_cert,
+        danger_accept_invalid_certs,
+    };
+
+    if options == TlsOptions::default() {
+        return Ok(None);
+    }
+
+    options
+        .client_config()
+        .map_err(|error| url_error(error, "Error: invalid TLS options"))?;
+
+    Ok(Some(options))
+}
+
+/// PEM text (a `str` or `bytes` holding a `-----BEGIN` line) or the path of a PEM file.
+fn pem_source(value: &Bound<'_, PyAny>) -> PyResult<PemSource> {
+    if let Ok(bytes) = value.downcast::<PyBytes>() {
+        let text = String::from_utf8(bytes.as_bytes().to_vec())
+            .map_err(|_| PyValueError::new_err("PEM data must be ASCII"))?;
+        return Ok(PemSource::Text(text));
+    }
+
+    if let Ok(text) = value.extract::<String>() {
+        if text.contains("-----BEGIN") {
+            return Ok(PemSource::Text(text));
+        }
+    }
+
+    let path: PathBuf = value.extract()?;
+
+    Ok(PemSource::File(path))
+}
+
+// Socket timeouts of zero are rejected by the standard library
+fn duration(seconds: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| PyValueError::new_err("Timeouts must be positive numbers of seconds"))
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    url,
+    http_version=None,
+    body=None,
+    max_redirects=None,
+    method=None,
+    headers=None,
+    timeout=None,
+    connect_timeout=None,
+    retries=None,
+    proxy=None,
+    ca_bundle=None,
+    client_cert=None,
+    client_key=None,
+    danger_accept_invalid_certs=false,
+    auth=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn request(
+    py: Python<'_>,
+    url: &str,
+    http_version: Option<&str>,
+    body: Option<&Bound<'_, PyAny>>,
+    max_redirects: Option<usize>,
+    method: Option<&str>,
+    headers: Option<&Bound<'_, PyAny>>,
+    timeout: Option<f64>,
+    connect_timeout: Option<f64>,
+    retries: Option<usize>,
+    proxy: Option<&str>,
+    ca_bundle: Option<&Bound<'_, PyAny>>,
+    client_cert: Option<&Bound<'_, PyAny>>,
+    client_key: Option<&Bound<'_, PyAny>>,
+    danger_accept_invalid_certs: bool,
+    auth: Option<&Bound<'_, PyAny>>,
+) -> PyResult<String> {
+    let options = RequestOptions {
+        http_version: parse_http_version(http_version)?,
+        method,
+        headers,
+        body,
+        max_redirects,
+        timeout,
+        connect_timeout,
+        retries,
+        proxy,
+        tls: tls_options(
+            ca_bundle,
+            client_cert,
+            client_key,
+            danger_accept_invalid_certs,
+        )?,
+        auth: auth.map(request_auth).transpose()?,
+    };
+
+    send_request(py, url, options, None)
+}
+
+/// Like `request`, handing back the whole response instead of the body.
+#[pyfunction]
+#[pyo3(signature = (
+    url,
+    http_version=None,
+    body=None,
+    max_redirects=None,
+    method=None,
+    headers=None,
+    timeout=None,
+    connect_timeout=None,
+    retries=None,
+    proxy=None,
+    ca_bundle=None,
+    client_cert=None,
+    client_key=None,
+    danger_accept_invalid_certs=false,
+    auth=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn fetch(
+    py: Python<'_>,
+    url: &str,
+    http_version: Option<&str>,
+    body: Option<&Bound<'_, PyAny>>,
+    max_redirects: Option<usize>,
+    method: Option<&str>,
+    headers: Option<&Bound<'_, PyAny>>,
+    timeout: Option<f64>,
+    connect_timeout: Option<f64>,
+    retries: Option<usize>,
+    proxy: Option<&str>,
+    ca_bundle: Option<&Bound<'_, PyAny>>,
+    client_cert: Option<&Bound<'_, PyAny>>,
+    client_key: Option<&Bound<'_, PyAny>>,
+    danger_accept_invalid_certs: bool,
+    auth: Option<&Bound<'_, PyAny>>,
+) -> PyResult<PyResponse> {
+    let options = RequestOptions {
+        http_version: parse_http_version(http_version)?,
+        method,
+        headers,
+        body,
+        max_redirects,
+        timeout,
+        connect_timeout,
+        retries,
+        proxy,
+        tls: tls_options(
+            ca_bundle,
+            client_cert,
+            client_key,
+            danger_accept_invalid_certs,
+        )?,
+        auth: auth.map(request_auth).transpose()?,
+    };
+
+    send_fetch(py, url, options, None)
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    urls,
+    max_concurrency=8,
+    http_version=None,
+    max_redirects=None,
+    method=None,
+    headers=None,
+    timeout=None,
+    connect_timeout=None,
+    retries=None,
+    proxy=None,
+    ca_bundle=None,
+    client_cert=None,
+    client_key=None,
+    danger_accept_invalid_certs=false,
+    auth=None,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn fetch_many(
+    py: Python<'_>,
+    urls: Vec<String>,
+    max_concurrency: usize,
+    http_version: Option<&str>,
+    max_redirects: Option<usize>,
+    method: Option<&str>,
+    headers: Option<&Bound<'_, PyAny>>,
+    timeout: Option<f64>,
+    connect_timeout: Option<f64>,
+    retries: Option<usize>,
+    proxy: Option<&str>,
+    ca_bundle: Option<&Bound<'_, PyAny>>,
+    client_cert: Option<&Bound<'_, PyAny>>,
+    client_key: Option<&Bound<'_, PyAny>>,
+    danger_accept_invalid_certs: bool,
+    auth: Option<&Bound<'_, PyAny>>,
+) -> PyResult<Vec<PyObject>> {
+    let options = RequestOptions {
+        http_version: parse_http_version(http_version)?,
+        method,
+        headers,
+        body: None,
+        max_redirects,
+        timeout,
+        connect_timeout,
+        retries,
+        proxy,
+        tls: tls_options(
+            ca_bundle,
+            client_cert,
+            client_key,
+            danger_accept_invalid_certs,
+        )?,
+        auth: auth.map(request_auth).transpose()?,
+    };
+
+    fetch_all(py, urls, max_concurrency, options, None)
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct PyCookie {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub value: String,
+    #[pyo3(get)]
+    pub domain: String,
+    #[pyo3(get)]
+    pub path: String,
+    /// Unix timestamp, `None` for session cookies
+    #[pyo3(get)]
+    pub expires: Option<u64>,
+    #[pyo3(get)]
+    pub secure: bool,
+    #[pyo3(get)]
+    pub http_only: bool,
+    #[pyo3(get)]
+    pub host_only: bool,
+}
+
+impl From<Cookie> for PyCookie {
+    fn from(value: Cookie) -> Self {
+        Self {
+            name: value.name,
+            value: value.value,
+            domain: value.domain,
+            path: value.path,
+            expires: value.expires,
+            secure: value.secure,
+            http_only: value.http_only,
+            host_only: value.host_only,
+        }
+    }
+}
+
+/// Cookies shared by the requests of the sessions it is attached to.
+#[pyclass]
+pub struct PyCookieJar {
+    jar: Arc<CookieJar>,
+}
+
+#[pymethods]
+impl PyCookieJar {
+    #[new]
+    fn new() -> Self {
+        Self {
+            jar: Arc::new(CookieJar::new()),
+        }
+    }
+
+    #[getter]
+    fn cookies(&self) -> Vec<PyCookie> {
+        self.jar.cookies().into_iter().map(PyCookie::from).collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.jar.cookies().len()
+    }
+
+    /// Adds a cookie by hand, e.g. a session token obtained elsewhere. It is only sent to
+    /// `domain` itself unless `subdomains` is set.
+    #[pyo3(signature = (name, value, domain, path="/", subdomains=false, secure=false, expires=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn set(
+        &self,
+        name: &str,
+        value: &str,
+        domain: &str,
+        path: &str,
+        subdomains: bool,
+        secure: bool,
+        expires: Option<u64>,
+    ) {
+        self.jar.insert(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: domain.trim_start_matches('.').to_lowercase(),
+            path: path.to_string(),
+            expires,
+            secure,
+            http_only: false,
+            host_only: !subdomains,
+        });
+    }
+
+    fn clear(&self) {
+        self.jar.clear();
+    }
+
+    /// Netscape `cookies.txt` format, as read by curl and wget.
+    fn dumps(&self) -> String {
+        self.jar.to_netscape()
+    }
+
+    #[staticmethod]
+    fn loads(source: &str) -> PyResult<Self> {
+        match CookieJar::from_netscape(source) {
+            Ok(jar) => Ok(Self { jar: Arc::new(jar) }),
+            Err(error) => Err(PyValueError::new_err(error.to_string())),
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyCacheStats {
+    #[pyo3(get)]
+    pub hits: usize,
+    #[pyo3(get)]
+    pub revalidated: usize,
+    #[pyo3(get)]
+    pub misses: usize,
+}
+
+impl From<CacheStats> for PyCacheStats {
+    fn from(value: CacheStats) -> Self {
+        Self {
+            hits: value.hits,
+            revalidated: value.revalidated,
+            misses: value.misses,
+        }
+    }
+}
+
+#[pyclass]
+pub struct PyFilterStats {
+    /// Requests checked while filtering was enabled
+    #[pyo3(get)]
+    pub checked: usize,
+    #[pyo3(get)]
+    pub blocked: usize,
+}
+
+impl From<FilterStats> for PyFilterStats {
+    fn from(value: FilterStats) -> Self {
+        Self {
+            checked: value.checked,
+            blocked: value.blocked,
+        }
+    }
+}
+
+/// Keeps connections alive between requests, so repeated requests to the same host skip
+/// the TCP and TLS handshakes, and keeps the cookies they set.
+#[pyclass]
+#[derive(Clone)]
+pub struct PySession {
+    pool: Arc<ConnectionPool>,
+    cookie_jar: Arc<CookieJar>,
+    cache: Option<Arc<HttpCache>>,
+    http_version: Option<HttpVersion>,
+    proxies: Arc<ProxyConfig>,
+    tls: Arc<TlsOptions>,
+    images: Arc<ImageCache>,
+    filter: Arc<RequestFilter>,
+}
+
+#[pymethods]
+impl PySession {
+    #[new]
+    /// `cache` keeps responses in memory, `cache_dir` on disk as well. Without `proxy` the
+    /// HTTP_PROXY, HTTPS_PROXY and NO_PROXY environment variables are used. `ca_bundle`,
+    /// `client_cert` and `client_key` take PEM text or a path.
+    #[pyo3(signature = (http_version=None, idle_timeout=pool::DEFAULT_IDLE_TIMEOUT.as_secs_f64(), cookie_jar=None, cache=false, cache_dir=None, proxy=None, ca_bundle=None, client_cert=None, client_key=None, danger_accept_invalid_certs=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        http_version: Option<&str>,
+        idle_timeout: f64,
+        cookie_jar: Option<PyRef<'_, PyCookieJar>>,
+        cache: bool,
+        cache_dir: Option<PathBuf>,
+        proxy: Option<&str>,
+        ca_bundle: Option<&Bound<'_, PyAny>>,
+        client_cert: Option<&Bound<'_, PyAny>>,
+        client_key: Option<&Bound<'_, PyAny>>,
+        danger_accept_invalid_certs: bool,
+    ) -> PyResult<Self> {
+        let idle_timeout = Duration::try_from_secs_f64(idle_timeout)
+            .map_err(|_| PyValueError::new_err("idle_timeout must be a positive number"))?;
+
+        let cache = match cache_dir {
+            Some(cache_dir) => Some(
+                HttpCache::with_directory(cache_dir)
+                    .map_err(|error| PyValueError::new_err(error.to_string()))?,
+            ),
+            None => cache.then(HttpCache::new),
+        };
+
+        Ok(Self {
+            pool: Arc::new(ConnectionPool::new(idle_timeout)),
+            cookie_jar: cookie_jar.map_or_else(
+                || Arc::new(CookieJar::new()),
+                |cookie_jar| Arc::clone(&cookie_jar.jar),
+            ),
+            cache: cache.map(Arc::new),
+            http_version: parse_http_version(http_version)?,
+            proxies: Arc::new(match proxy {
+                Some(proxy) => proxy_config(proxy)?,
+                None => ProxyConfig::from_env(),
+            }),
+            tls: Arc::new(
+                tls_options(
+                    ca_bundle,
+                    client_cert,
+                    client_key,
+                    danger_accept_invalid_certs,
+                )?
+                .unwrap_or_default(),
+            ),
+            images: Arc::new(ImageCache::new(images::DEFAULT_CACHE_CAPACITY)),
+            filter: Arc::new(RequestFilter::new()),
+        })
+    }
+
+    /// The jar is shared, changes made through it apply to the session.
+    #[getter]
+    fn cookies(&self) -> PyCookieJar {
+        PyCookieJar {
+            jar: Arc::clone(&self.cookie_jar),
+        }
+    }
+
+    #[pyo3(signature = (
+        url,
+        body=None,
+        max_redirects=None,
+        method=None,
+        headers=None,
+        timeout=None,
+        connect_timeout=None,
+        retries=None,
+        auth=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn request(
+        &self,
+        py: Python<'_>,
+        url: &str,
+        body: Option<&Bound<'_, PyAny>>,
+        max_redirects: Option<usize>,
+        method: Option<&str>,
+        headers: Option<&Bound<'_, PyAny>>,
+        timeout: Option<f64>,
+        connect_timeout: Option<f64>,
+        retries: Option<usize>,
+        auth: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<String> {
+        let options = RequestOptions {
+            http_version: self.http_version,
+            method,
+            headers,
+            body,
+            max_redirects,
+            timeout,
+            connect_timeout,
+            retries,
+            proxy: None,
+            tls: None,
+            auth: auth.map(request_auth).transpose()?,
+        };
+
+        send_request(py, url, options, Some(self))
+    }
+
+    #[pyo3(signature = (
+        url,
+        body=None,
+        max_redirects=None,
+        method=None,
+        headers=None,
+        timeout=None,
+        connect_timeout=None,
+        retries=None,
+        auth=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn fetch(
+        &self,
+        py: Python<'_>,
+        url: &str,
+        body: Option<&Bound<'_, PyAny>>,
+        max_redirects: Option<usize>,
+        method: Option<&str>,
+        headers: Option<&Bound<'_, PyAny>>,
+        timeout: Option<f64>,
+        connect_timeout: Option<f64>,
+        retries: Option<usize>,
+        auth: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<PyResponse> {
+        let options = RequestOptions {
+            http_version: self.http_version,
+            method,
+            headers,
+            body,
+            max_redirects,
+            timeout,
+            connect_timeout,
+            retries,
+            proxy: None,
+            tls: None,
+            auth: auth.map(request_auth).transpose()?,
+        };
+
+        send_fetch(py, url, options, Some(self))
+    }
+
+    /// Like `fetch_many`, with the session's connections, cookies and cache.
+    #[pyo3(signature = (
+        urls,
+        max_concurrency=8,
+        max_redirects=None,
+        method=None,
+        headers=None,
+        timeout=None,
+        connect_timeout=None,
+        retries=None,
+        auth=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn fetch_many(
+        &self,
+        py: Python<'_>,
+        urls: Vec<String>,
+        max_concurrency: usize,
+        max_redirects: Option<usize>,
+        method: Option<&str>,
+        headers: Option<&Bound<'_, PyAny>>,
+        timeout: Option<f64>,
+        connect_timeout: Option<f64>,
+        retries: Option<usize>,
+        auth: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Vec<PyObject>> {
+        let options = RequestOptions {
+            http_version: self.http_version,
+            method,
+            headers,
+            body: None,
+            max_redirects,
+            timeout,
+            connect_timeout,
+            retries,
+            proxy: None,
+            tls: None,
+            auth: auth.map(request_auth).transpose()?,
+        };
+
+        fetch_all(py, urls, max_concurrency, options, Some(self))
+    }
+
+    /// `None` when the session was created without a cache.
+    #[getter]
+    fn cache_stats(&self) -> Option<PyCacheStats> {
+        self.cache
+            .as_ref()
+            .map(|cache| PyCacheStats::from(cache.stats()))
+    }
+
+    /// Adds adblock-style rules (`||ads.example^`, `@@` exceptions, `$document`...) or hosts
+    /// file lines, one per line. Requests of the session matching them raise BlockedError,
+    /// page loads only for rules with the `document` option. Returns how many rules were
+    /// used, comments and unsupported rules are skipped.
+    fn add_filters(&self, rules: &str) -> usize {
+        self.filter.add_rules(rules)
+    }
+
+    fn clear_filters(&self) {
+        self.filter.clear();
+    }
+
+    /// Whether the filters apply, they are kept while turned off.
+    #[getter]
+    fn filtering(&self) -> bool {
+        self.filter.is_enabled()
+    }
+
+    #[setter]
+    fn set_filtering(&self, filtering: bool) {
+        self.filter.set_enabled(filtering);
+    }
+
+    #[getter]
+    fn filter_stats(&self) -> PyFilterStats {
+        PyFilterStats::from(self.filter.stats())
+    }
+
+    /// Whether the filters block `url`, loaded as a page with `document`, turned on or not.
+    #[pyo3(signature = (url, document=false))]
+    fn is_blocked(&self, url: &str, document: bool) -> bool {
+        let destination = match document {
+            true => Destination::Document,
+            false => Destination::Subresource,
+        };
+
+        self.filter.blocks(url, destination)
+    }
+
+    /// Empties the response cache and the decoded images.
+    fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        self.images.clear();
+    }
+
+    /// Fetches and decodes a PNG, JPEG or GIF through the session, decoded images are kept
+    /// for the next time.
+    fn fetch_image(&self, py: Python<'_>, url: &str) -> PyResult<PyImage> {
+        let prepare = self.prepare();
+        let image = py.allow_threads(|| {
+            self.images
+                .load(url, Some(&prepare))
+                .map_err(|error| url_error(error, "Error: unable to load image"))
+        })?;
+
+        Ok(PyImage { image })
+    }
+
+    /// Streams `url` into the file at `path` through the session's connections and cookies,
+    /// see `download()`.
+    #[pyo3(signature = (url, path, progress=None, resume=true))]
+    fn download(
+        &self,
+        py: Python<'_>,
+        url: &str,
+        path: PathBuf,
+        progress: Option<PyObject>,
+        resume: bool,
+    ) -> PyResult<PyDownload> {
+        let prepare = self.prepare();
+
+        download_to(py, url, path, progress, resume, Some(&prepare))
+    }
+
+    /// Closes the idle connections, the session can still be used afterwards.
+    fn close(&self) {
+        self.pool.clear();
+    }
+}
+
+impl PySession {
+    // Has `url` go through the session's connections, cookies, filters and cache
+    fn attach(&self, url: &mut URL) {
+        url.set_pool(Arc::clone(&self.pool));
+        url.set_cookie_jar(Arc::clone(&self.cookie_jar));
+        url.set_filter(Arc::clone(&self.filter));
+
+        if let Some(cache) = &self.cache {
+            url.set_cache(Arc::clone(cache));
+        }
+    }
+
+    // Sets up the requests of page loads like the session's own
+    fn prepare(&self) -> Prepare {
+        let session = self.clone();
+
+        Arc::new(move |url: &mut URL| {
+            session.attach(url);
+            url.set_proxies(Arc::clone(&session.proxies));
+            url.set_tls(Arc::clone(&session.tls));
+
+            if let Some(http_version) = session.http_version {
+                url.set_http_version(http_version);
+            }
+        })
+    }
+}
+
+#[pyclass]
+pub struct PyHistoryEntry {
+    #[pyo3(get)]
+    pub url: String,
+    #[pyo3(get)]
+    pub title: Option<String>,
+}
+
+/// Browses like a browser tab: pages are loaded through a session and links are followed
+/// from the current one.
+#[pyclass]
+pub struct PyTab {
+    tab: Tab,
+    session: PySession,
+    // The pages of the history converted once, so every access hands out the same document
+    pages: Vec<Py<PyPage>>,
+}
+
+#[pymethods]
+impl PyTab {
+    /// Without `session` the tab gets one of its own.
+    #[new]
+    #[pyo3(signature = (session=None, max_refresh_hops=None, max_redirects=None))]
+    fn new(
+        session: Option<PyRef<'_, PySession>>,
+        max_refresh_hops: Option<usize>,
+        max_redirects: Option<usize>,
+    ) -> PyResult<Self> {
+        let session = match session {
+            Some(session) => session.clone(),
+            None => PySession::new(
+                None,
+                pool::DEFAULT_IDLE_TIMEOUT.as_secs_f64(),
+                None,
+                false,
+                None,
+                None,
+                None,
+                None,
+                None,
+                false,
+            )?,
+        };
+
+        let defaults = LoadOptions::default();
+        let options = LoadOptions {
+            max_refresh_hops: max_refresh_hops.unwrap_or(defaults.max_refresh_hops),
+            max_redirects: max_redirects.unwrap_or(defaults.max_redirects),
+            prepare: Some(session.prepare()),
+        };
+
+        Ok(Self {
+            tab: Tab::new(options),
+            session,
+            pages: Vec::new(),
+        })
+    }
+
+    #[getter]
+    fn session(&self) -> PySession {
+        self.session.clone()
+    }
+
+    /// `None` before the first navigation.
+    #[getter]
+    fn current_url(&self) -> Option<String> {
+        self.tab.page().map(|page| page.url.clone())
+    }
+
+    #[getter]
+    fn page(&self, py: Python<'_>) -> Option<Py<PyPage>> {
+        self.current_page().map(|page| page.clone_ref(py))
+    }
+
+    /// The parsed current page, `None` when it isn't an HTML document.
+    #[getter]
+    fn document(&self, py: Python<'_>) -> Option<PyNode> {
+        self.current_page()?.borrow(py).document.clone()
+    }
+
+    /// Visited pages, oldest first.
+    fn history(&self) -> Vec<PyHistoryEntry> {
+        self.tab
+            .history()
+            .iter()
+            .map(|page| PyHistoryEntry {
+                url: page.url.clone(),
+                title: page.title(),
+            })
+            .collect()
+    }
+
+    /// Position of the current page in `history()`.
+    #[getter]
+    fn history_index(&self) -> usize {
+        self.tab.history_index()
+    }
+
+    #[getter]
+    fn can_go_back(&self) -> bool {
+        self.tab.history_index() > 0
+    }
+
+    #[getter]
+    fn can_go_forward(&self) -> bool {
+        self.tab.history_index() + 1 < self.tab.history().len()
+    }
+
+    /// Shows the previous page again as it was loaded and returns its document. Nothing
+    /// happens at the start of the history.
+    fn back(&mut self, py: Python<'_>) -> Option<PyNode> {
+        self.tab.back()?;
+
+        self.document(py)
+    }
+
+    /// Shows the next page again as it was loaded and returns its document. Nothing happens
+    /// at the end of the history.
+    fn forward(&mut self, py: Python<'_>) -> Option<PyNode> {
+        self.tab.forward()?;
+
+        self.document(py)
+    }
+
+    /// Loads `url` (relative to the current page) and returns its document.
+    fn navigate(&mut self, py: Python<'_>, url: &str) -> PyResult<Option<PyNode>> {
+        let tab = &mut self.tab;
+        let page = py.allow_threads(|| {
+            tab.navigate(url)
+                .map(PyPage::from)
+                .map_err(|error| url_error(error, "Error: unable to load page"))
+        });
+
+        self.show(py, page)
+    }
+
+    /// Navigates to where a link (an `a` or `area` element with an `href`) points, like
+    /// clicking it.
+    fn follow(&mut self, py: Python<'_>, link: &PyNode) -> PyResult<Option<PyNode>> {
+        let link = link.node();
+        let tab = &mut self.tab;
+        let page = py.allow_threads(|| {
+            tab.follow(link)
+                .map(PyPage::from)
+                .map_err(|error| url_error(error, "Error: unable to load page"))
+        });
+
+        self.show(py, page)
+    }
+}
+
+impl PyTab {
+    fn current_page(&self) -> Option<&Py<PyPage>> {
+        self.pages.get(self.tab.history_index())
+    }
+
+    // Adds `page`, which the tab just navigated to, after the current one
+    fn show(&mut self, py: Python<'_>, page: PyResult<PyPage>) -> PyResult<Option<PyNode>> {
+        let page = page?;
+        let document = page.document.clone();
+
+        self.pages.truncate(self.tab.history_index());
+        self.pages.push(Py::new(py, page)?);
+
+        Ok(document)
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (url, max_refresh_hops=None, max_redirects=None))]
+pub fn load_page(
+    py: Python<'_>,
+    url: &str,
+    max_refresh_hops: Option<usize>,
+    max_redirects: Option<usize>,
+) -> PyResult<PyPage> {
+    let defaults = LoadOptions::default();
+    let options = LoadOptions {
+        max_refresh_hops: max_refresh_hops.unwrap_or(defaults.max_refresh_hops),
+        max_redirects: max_redirects.unwrap_or(defaults.max_redirects),
+        ..defaults
+    };
+    let page = py.allow_threads(|| {
+        page::load_page_with(url, &options)
+            .map_err(|error| url_error(error, "Error: unable to load page"))
+    })?;
+
+    Ok(PyPage::from(&page))
+}
+
+/// Decodes a PNG, JPEG or GIF (its first frame) held in memory.
+#[pyfunction]
+pub fn decode_image(py: Python<'_>, data: &[u8]) -> PyResult<PyImage> {
+    let image = py.allow_threads(|| {
+        images::decode(data).map_err(|error| url_error(error, "Error: unable to decode image"))
+    })?;
+
+    Ok(PyImage {
+        image: Arc::new(image),
+    })
+}
+
+/// Streams `url` into the file at `path` without holding the body in memory. `progress` is
+/// called with the bytes in the file so far and the total size (None when unknown), an
+/// exception it raises cancels the download. With `resume` the bytes already in the file
+/// are kept and only the rest is asked for with a Range request.
+#[pyfunction]
+#[pyo3(signature = (url, path, progress=None, resume=true))]
+pub fn download(
+    py: Python<'_>,
+    url: &str,
+    path: PathBuf,
+    progress: Option<PyObject>,
+    resume: bool,
+) -> PyResult<PyDownload> {
+    download_to(py, url, path, progress, resume, None)
+}
+
+#[pyfunction]
+pub fn fetch_sitemap(url: &str) -> PyResult<Vec<PySitemapEntry>> {
+    match sitemap::fetch_sitemap(url) {
+        Ok(entries) => Ok(entries.into_iter().map(PySitemapEntry::from).collect()),
+        Err(error) => {
+            if let Some(url_error) = error.downcast_ref::<URLError>() {
+                return Err(PyValueError::new_err(url_error.message.clone()));
+            }
+
+            if let Some(xml_error) = error.downcast_ref::<XMLError>() {
+                return Err(PyValueError::new_err(xml_error.to_string()));
+            }
+
+            Err(PyValueError::new_err("Error: unable to fetch sitemap"))
+        }
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (start_url, max_depth=2, max_pages=100, same_origin=true, concurrency=4, delay=0.5, respect_robots=true))]
+pub fn crawl(
+    start_url: &str,
+    max_depth: usize,
+    max_pages: usize,
+    same_origin: bool,
+    concurrency: usize,
+    delay: f64,
+    respect_robots: bool,
+) -> PyResult<PyCrawler> {
+    let options = CrawlOptions {
+        max_depth,
+        max_pages,
+        same_origin,
+        concurrency,
+        delay: Duration::try_from_secs_f64(delay)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        respect_robots,
+    };
+
+    match crawler::crawl(start_url, options) {
+        Ok(crawler) => Ok(PyCrawler {
+            crawler: Mutex::new(crawler),
+        }),
+        Err(error) => match error.downcast_ref::<URLError>() {
+            Some(url_error) => Err(PyValueError::new_err(url_error.message.clone())),
+            None => Err(PyValueError::new_err("Error: unable to start crawl")),
+        },
+    }
+}
+
+/// What the scripts of a document did to it, logged and threw.
+#[cfg(feature = "javascript")]
+#[pyclass]
+pub struct PyScriptResult {
+    #[pyo3(get)]
+    pub document: PyNode,
+    #[pyo3(get)]
+    pub console: Vec<String>,
+    #[pyo3(get)]
+    pub errors: Vec<String>,
+}
+
+/// Runs the inline scripts of `document` (only with the `javascript` feature). The node
+/// isn't changed, the result holds the changed copy.
+#[cfg(feature = "javascript")]
+#[pyfunction]
+pub fn run_scripts(py: Python<'_>, document: &PyNode) -> PyScriptResult {
+    let mut changed = document.node().clone();
+    let outcome = py.allow_threads(|| script::run_scripts(&mut changed));
+
+    PyScriptResult {
+        document: PyNode::from(changed),
+        console: outcome.console,
+        errors: outcome.errors,
+    }
+}
+
+/// Parses `body` as strict XML, raising on any well-formedness error.
+#[pyfunction]
+pub fn load_xml(body: &str) -> PyResult<PyNode> {
+    match XMLParser::new(body).parse() {
+        Ok(root) => Ok(PyNode::from(root)),
+        Err(error) => Err(PyValueError::new_err(error.to_string())),
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (body, noscript=None, declarative_shadow_dom=true, decode_entities=true, document=false, comments=false, source_positions=false, options=None))]
+#[allow(clippy::too_many_arguments)]
+pub fn load(
+    body: &str,
+    noscript: Option<&str>,
+    declarative_shadow_dom: bool,
+    decode_entities: bool,
+    document: bool,
+    comments: bool,
+    source_positions: bool,
+    options: Option<PyParserOptions>,
+) -> PyResult<PyNode> {
+    // `options` takes the place of the other arguments
+    let options = match options {
+        Some(options) => options,
+        None => PyParserOptions::new(
+            noscript,
+            declarative_shadow_dom,
+            decode_entities,
+            document,
+            comments,
+            Vec::new(),
+            source_positions,
+        )?,
+    };
+
+    let mut parser = HTMLParser::with_options(body, options.parser_options()?);
+    let root = parser
+        .parse()
+        .ok_or_else(|| PyValueError::new_err("No element found in the markup"))?;
+
+    Ok(PyNode::from(root))
+}
+
+/// Goes through `body` calling the `on_start_tag(tag, attrs)`, `on_end_tag(tag)`,
+/// `on_text(text)` and `on_comment(text)` methods of `handler` (the ones it has) in source
+/// order, without building a tree. An exception raised by one of them stops parsing.
+#[pyfunction]
+#[pyo3(signature = (body, handler, decode_entities=true))]
+pub fn parse_events(body: &str, handler: &Bound<'_, PyAny>, decode_entities: bool) -> PyResult<()> {
+    let on_start_tag = handler_method(handler, "on_start_tag")?;
+    let on_end_tag = handler_method(handler, "on_end_tag")?;
+    let on_text = handler_method(handler, "on_text")?;
+    let on_comment = handler_method(handler, "on_comment")?;
+
+    let options = ParserOptions {
+        decode_entities,
+        comments: true,
+        ..Default::default()
+    };
+
+    HTMLParser::with_options(body, options).events(|event| {
+        match event {
+            ParseEvent::StartTag {
+                name, attributes, ..
+            } => {
+                if let Some(method) = &on_start_tag {
+                    method.call1((name, attributes))?;
+                }
+            }
+            ParseEvent::EndTag(name) => {
+                if let Some(method) = &on_end_tag {
+                    method.call1((name,))?;
+                }
+            }
+            ParseEvent::Text(text) => {
+                if let Some(method) = &on_text {
+                    method.call1((text,))?;
+                }
+            }
+            ParseEvent::Comment(text) => {
+                if let Some(method) = &on_comment {
+                    method.call1((text,))?;
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn handler_method<'py>(
+    handler: &Bound<'py, PyAny>,
+    name: &str,
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    match handler.hasattr(name)? {
+        true => Ok(Some(handler.getattr(name)?)),
+        false => Ok(None),
+    }
+}
+
+/// Parser settings that can be kept and reused, the arguments `load` also takes one by one.
+#[pyclass]
+#[derive(Clone)]
+pub struct PyParserOptions {
+    /// One of "parse", "flag" or "skip"
+    #[pyo3(get, set)]
+    noscript: String,
+    #[pyo3(get, set)]
+    declarative_shadow_dom: bool,
+    #[pyo3(get, set)]
+    decode_entities: bool,
+    #[pyo3(get, set)]
+    document: bool,
+    #[pyo3(get, set)]
+    comments: bool,
+    /// Tags to treat as void on top of the HTML ones
+    #[pyo3(get, set)]
+    void_elements: Vec<String>,
+    /// Record where each node is in the markup, see `PyNode.position`
+    #[pyo3(get, set)]
+    source_positions: bool,
+}
+
+#[pymethods]
+impl PyParserOptions {
+    #[new]
+    #[pyo3(signature = (noscript=None, declarative_shadow_dom=true, decode_entities=true, document=false, comments=false, void_elements=Vec::new(), source_positions=false))]
+    fn new(
+        noscript: Option<&str>,
+        declarative_shadow_dom: bool,
+        decode_entities: bool,
+        document: bool,
+        comments: bool,
+        void_elements: Vec<String>,
+        source_positions: bool,
+    ) -> PyResult<Self> {
+        let options = Self {
+            noscript: noscript.unwrap_or("parse").to_string(),
+            declarative_shadow_dom,
+            decode_entities,
+            document,
+            comments,
+            void_elements,
+            source_positions,
+        };
+        options.parser_options()?;
+
+        Ok(options)
+    }
+}
+
+impl PyParserOptions {
+    fn parser_options(&self) -> PyResult<ParserOptions> {
+        Ok(ParserOptions {
+            noscript: self.noscript.parse().map_err(PyValueError::new_err)?,
+            declarative_shadow_dom: self.declarative_shadow_dom,
+            decode_entities: self.decode_entities,
+            document: self.document,
+            comments: self.comments,
+            void_elements: self.void_elements.clone(),
+            source_positions: self.source_positions,
+        })
+    }
+}
+
+/// Parses markup handed over in pieces, `finish()` returns what `load` would for the
+/// whole of it.
+#[pyclass]
+pub struct PyStreamingParser {
+    parser: Option<StreamingParser>,
+}
+
+#[pymethods]
+impl PyStreamingParser {
+    #[new]
+    #[pyo3(signature = (noscript=None, declarative_shadow_dom=true, decode_entities=true, document=false, comments=false, source_positions=false, options=None))]
+    fn new(
+        noscript: Option<&str>,
+        declarative_shadow_dom: bool,
+        decode_entities: bool,
+        document: bool,
+        comments: bool,
+        source_positions: bool,
+        options: Option<PyParserOptions>,
+    ) -> PyResult<Self> {
+        let options = match options {
+            Some(options) => options,
+            None => PyParserOptions::new(
+                noscript,
+                declarative_shadow_dom,
+                decode_entities,
+                document,
+                comments,
+                Vec::new(),
+                source_positions,
+            )?,
+        };
+
+        Ok(Self {
+            parser: Some(StreamingParser::new(options.parser_options()?)),
+        })
+    }
+
+    fn feed(&mut self, chunk: &str) -> PyResult<()> {
+        self.parser()?.feed(chunk);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> PyResult<PyNode> {
+        self.parser()?;
+
+        let root = self
+            .parser
+            .take()
+            .and_then(StreamingParser::finish)
+            .ok_or_else(|| PyValueError::new_err("No element found in the markup"))?;
+
+        Ok(PyNode::from(root))
+    }
+}
+
+impl PyStreamingParser {
+    fn parser(&mut self) -> PyResult<&mut StreamingParser> {
+        self.parser
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("The parser is already finished"))
+    }
+}
+
+#[pyfunction]
+#[allow(deprecated)]
+pub fn find_text_nodes(pynode: &PyNode) -> PyResult<Vec<PyNode>> {
+    let text_nodes = pynode.node().find_text_nodes();
+
+    Ok(pynode.descendant_handles(&text_nodes))
+}
+
+/// Resolves `relative` against the absolute URL `base`.
+#[pyfunction]
+pub fn join(base: &str, relative: &str) -> PyResult<String> {
+    URL::join(base, relative).map_err(|error| PyValueError::new_err(error.to_string()))
+}
+
+#[pyfunction]
+pub fn percent_encode(text: &str) -> String {
+    whatwg_url::percent_encode(text)
+}
+
+#[pyfunction]
+pub fn percent_decode(text: &str) -> String {
+    whatwg_url::percent_decode_str(text)
+}
+
+#[pyfunction]
+pub fn grapheme_len(text: &str) -> usize {
+    text::grapheme_len(text)
+}
+
+#[pyfunction]
+pub fn display_width(text: &str) -> usize {
+    text::display_width(text)
+}
+
+#[pyfunction]
+#[pyo3(signature = (text, max_width, ellipsis=text::ELLIPSIS))]
+pub fn truncate(text: &str, max_width: usize, ellipsis: &str) -> String {
+    text::truncate(text, max_width, ellipsis)
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::types::IntoPyDict;
+
+    use super::*;
+
+    #[test]
+    fn test_dict_node_rejects_incomplete_nodes() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let node = |pairs: Vec<(&str, &str)>| dict_node(&pairs.into_py_dict(py).unwrap());
+
+            for tag_name in ["text", "#comment", "#doctype"] {
+                let error = node(vec![("tag_name", tag_name)]).unwrap_err();
+                assert!(error.is_instance_of::<PyValueError>(py));
+            }
+
+            let text = PyDict::new(py);
+            text.set_item("tag_name", "text").unwrap();
+            text.set_item("attributes", [("content", "x")].into_py_dict(py).unwrap())
+                .unwrap();
+            assert_eq!(dict_node(&text).unwrap().to_html(), "x");
+            assert_eq!(node(vec![("tag_name", "p")]).unwrap().to_html(), "<p></p>");
+        });
+    }
+
+    #[test]
+    fn test_equal_nodes_hash_alike() {
+        let root = PyNode::from(
+            HTMLParser::new("<div><p>a</p><p>a</p></div>")
+                .parse()
+                .unwrap(),
+        );
+        let (first, again) = (&root.children()[0], &root.children()[0]);
+        let second = &root.children()[1];
+
+        assert!(first.__eq__(again));
+        assert_eq!(first.__hash__(), again.__hash__());
+        assert!(!first.__eq__(second));
+        assert_ne!(first.__hash__(), second.__hash__());
+    }
 }
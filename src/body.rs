@@ -0,0 +1,261 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufRead, Read, Write},
+    path::Path,
+};
+
+use crate::url::URLError;
+
+type Err = Box<dyn Error>;
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Request payload, either fully in memory or streamed from a reader so large uploads
+/// never have to be buffered.
+pub enum RequestBody {
+    Bytes(Vec<u8>),
+    Stream {
+        reader: Box<dyn Read + Send>,
+        // When unknown the body is sent with chunked transfer-encoding
+        length: Option<u64>,
+    },
+}
+
+impl RequestBody {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Err> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+
+        Ok(Self::from_reader(file, Some(length)))
+    }
+
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, length: Option<u64>) -> Self {
+        Self::Stream {
+            reader: Box::new(reader),
+            length,
+        }
+    }
+
+    pub fn length(&self) -> Option<u64> {
+        match self {
+            RequestBody::Bytes(bytes) => Some(bytes.len() as u64),
+            RequestBody::Stream { length, .. } => *length,
+        }
+    }
+
+    pub fn reader(&mut self) -> Box<dyn Read + '_> {
+        match self {
+            RequestBody::Bytes(bytes) => Box::new(bytes.as_slice()),
+            RequestBody::Stream { reader, length } => match length {
+                Some(length) => Box::new(reader.take(*length)),
+                None => Box::new(reader),
+            },
+        }
+    }
+
+    /// Writes the body as is when its length is known, as a chunked stream otherwise. A
+    /// reader running out before the length is an error, the request would be cut short.
+    pub fn write_to<W: Write>(&mut self, writer: &mut W) -> Result<(), Err> {
+        if let Some(length) = self.length() {
+            let copied = io::copy(&mut self.reader(), writer)?;
+            if copied < length {
+                return Err(body_error(&format!(
+                    "Request body ended after {} of {} bytes",
+                    copied, length
+                )));
+            }
+            return Ok(());
+        }
+
+        let mut reader = self.reader();
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+
+            write!(writer, "{:x}\r\n", read)?;
+            writer.write_all(&buffer[..read])?;
+            write!(writer, "\r\n")?;
+
+            // Zero sized chunk is the last one
+            if read == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a chunked message body, the data of each chunk is passed to `on_chunk` as soon as
+/// it is read (large chunks in pieces) and an error from it stops reading. Trailer fields
+/// are read and dropped.
+pub fn read_chunked<R: BufRead>(
+    reader: &mut R,
+    on_chunk: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> Result<(), Err> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let mut size_line = String::new();
+        if reader.read_line(&mut size_line)? == 0 {
+            return Err(body_error(
+                "Connection closed in the middle of a chunked body",
+            ));
+        }
+
+        // Chunk extensions follow the size after a ';'
+        let size = size_line.split(';').next().unwrap_or("").trim();
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| body_error(&format!("Invalid chunk size: {}", size)))?;
+
+        if size == 0 {
+            break;
+        }
+
+        // The size comes from the server, the chunk is never held whole
+        let mut remaining = size;
+        while remaining > 0 {
+            let wanted = remaining.min(CHUNK_SIZE as u64) as usize;
+            let read = reader.read(&mut buffer[..wanted])?;
+            if read == 0 {
+                return Err(body_error("Connection closed in the middle of a chunk"));
+            }
+
+            on_chunk(&buffer[..read])?;
+            remaining -= read as u64;
+        }
+
+        let mut line_end = [0u8; 2];
+        reader.read_exact(&mut line_end)?;
+        if &line_end != b"\r\n" {
+            return Err(body_error("Chunk data is not followed by CRLF"));
+        }
+    }
+
+    loop {
+        let mut trailer = String::new();
+        // Some servers hang up right after the last chunk
+        if reader.read_line(&mut trailer)? == 0 || trailer.trim().is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+fn body_error(message: &str) -> Err {
+    Box::new(URLError {
+        message: message.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_known_length_is_written_raw() {
+        let mut body = RequestBody::Bytes(b"name=ewb".to_vec());
+        let mut written = Vec::new();
+        body.write_to(&mut written).unwrap();
+
+        assert_eq!(body.length(), Some(8));
+        assert_eq!(written, b"name=ewb");
+    }
+
+    #[test]
+    fn test_unknown_length_is_chunked() {
+        let mut body = RequestBody::from_reader(Cursor::new(b"hello world".to_vec()), None);
+        let mut written = Vec::new();
+        body.write_to(&mut written).unwrap();
+
+        assert_eq!(written, b"b\r\nhello world\r\n0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_stream_stops_at_length() {
+        let mut body = RequestBody::from_reader(Cursor::new(b"hello world".to_vec()), Some(5));
+        let mut written = Vec::new();
+        body.write_to(&mut written).unwrap();
+
+        assert_eq!(written, b"hello");
+    }
+
+    #[test]
+    fn test_short_stream_is_an_error() {
+        let mut body = RequestBody::from_reader(Cursor::new(b"hello".to_vec()), Some(8));
+        let error = body.write_to(&mut Vec::new()).unwrap_err();
+
+        assert_eq!(error.to_string(), "Request body ended after 5 of 8 bytes");
+    }
+
+    #[test]
+    fn test_read_chunked() {
+        let mut reader =
+            Cursor::new(b"5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nExpires: 0\r\n\r\nnext".to_vec());
+        let mut body = Vec::new();
+        let mut chunks = Vec::new();
+        read_chunked(&mut reader, &mut |chunk| {
+            body.extend_from_slice(chunk);
+            chunks.push(chunk.to_vec());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(body, b"hello world");
+        assert_eq!(chunks, [b"hello".to_vec(), b" world".to_vec()]);
+        // Whatever follows the message is left alone
+        assert_eq!(reader.position(), 44);
+    }
+
+    #[test]
+    fn test_read_chunked_errors() {
+        assert!(read_chunked(&mut Cursor::new(b"zz\r\n".to_vec()), &mut |_| Ok(())).is_err());
+        assert!(read_chunked(&mut Cursor::new(b"5\r\nhel".to_vec()), &mut |_| Ok(())).is_err());
+        assert!(read_chunked(
+            &mut Cursor::new(b"2\r\nhello\r\n".to_vec()),
+            &mut |_| Ok(())
+        )
+        .is_err());
+        assert!(read_chunked(&mut Cursor::new(b"2\r\nhi\r\n".to_vec()), &mut |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_read_chunked_huge_size() {
+        let mut received = 0;
+        let error = read_chunked(
+            &mut Cursor::new(b"ffffffffffffffff\r\nhello".to_vec()),
+            &mut |data| {
+                received += data.len();
+                Ok(())
+            },
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Connection closed in the middle of a chunk"
+        );
+        assert_eq!(received, 5);
+
+        let error = read_chunked(
+            &mut Cursor::new(b"10000000000000000\r\n".to_vec()),
+            &mut |_| Ok(()),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid chunk size: 10000000000000000");
+    }
+
+    #[test]
+    fn test_from_file() {
+        let path = std::env::temp_dir().join("ewb_request_body.txt");
+        std::fs::write(&path, "file contents").unwrap();
+
+        let body = RequestBody::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(body.length(), Some(13));
+    }
+}
@@ -0,0 +1,429 @@
+// Private HTTP cache (https://httpwg.org/specs/rfc9111.html)
+
+use std::{collections::HashMap, error::Error, fs, path::PathBuf, sync::Mutex};
+
+use crate::date::{parse_date, unix_now};
+use crate::url::URLError;
+
+type Err = Box<dyn Error>;
+
+// Status codes cacheable by default (RFC 9110 section 15.1)
+const HEURISTICALLY_CACHEABLE: [usize; 8] = [200, 203, 204, 300, 301, 308, 404, 410];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Served from the cache without contacting the server
+    pub hits: usize,
+    /// Stale entries the server confirmed with a 304
+    pub revalidated: usize,
+    pub misses: usize,
+}
+
+pub enum Lookup {
+    Hit,
+    Revalidated,
+    Miss,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    pub version: String,
+    pub status: usize,
+    pub explanation: String,
+    /// Lowercase names, content codings already removed from the body
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    // When the response was received, seconds since the Unix epoch
+    stored_at: u64,
+}
+
+impl CachedResponse {
+    pub fn new(
+        version: String,
+        status: usize,
+        explanation: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Self {
+        Self {
+            version,
+            status,
+            explanation,
+            headers,
+            body,
+            stored_at: unix_now(),
+        }
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.current_age() < self.freshness_lifetime()
+    }
+
+    /// Conditional request headers asking the server whether this copy is still current.
+    pub fn validators(&self) -> Vec<(String, String)> {
+        let mut validators = Vec::new();
+
+        if let Some(etag) = self.headers.get("etag") {
+            validators.push(("If-None-Match".to_string(), etag.clone()));
+        }
+
+        if let Some(last_modified) = self.headers.get("last-modified") {
+            validators.push(("If-Modified-Since".to_string(), last_modified.clone()));
+        }
+
+        validators
+    }
+
+    /// Applies the headers of a 304 answering our revalidation, the copy is fresh again.
+    pub fn refresh(&mut self, headers: &HashMap<String, String>) {
+        for (name, value) in headers {
+            // These describe the (empty) 304 body, not ours
+            if !["content-length", "content-encoding", "transfer-encoding"].contains(&name.as_str())
+            {
+                self.headers.insert(name.clone(), value.clone());
+            }
+        }
+
+        self.stored_at = unix_now();
+    }
+
+    fn directives(&self) -> HashMap<String, Option<String>> {
+        cache_control(self.headers.get("cache-control").map_or("", |value| value))
+    }
+
+    fn current_age(&self) -> u64 {
+        let age = self
+            .headers
+            .get("age")
+            .and_then(|age| age.parse().ok())
+            .unwrap_or(0);
+
+        age + unix_now().saturating_sub(self.stored_at)
+    }
+
+    // RFC 9111 section 4.2.1, falling back to the 10% heuristic of section 4.2.2
+    fn freshness_lifetime(&self) -> u64 {
+        let directives = self.directives();
+
+        if directives.contains_key("no-cache") {
+            return 0;
+        }
+
+        if let Some(max_age) = directives.get("max-age") {
+            return max_age
+                .as_ref()
+                .and_then(|max_age| max_age.parse().ok())
+                .unwrap_or(0);
+        }
+
+        let date = self
+            .headers
+            .get("date")
+            .and_then(|date| parse_date(date))
+            .unwrap_or(self.stored_at);
+
+        if let Some(expires) = self.headers.get("expires") {
+            // Invalid dates such as "0" mean already expired
+            return parse_date(expires).map_or(0, |expires| expires.saturating_sub(date));
+        }
+
+        self.headers
+            .get("last-modified")
+            .and_then(|last_modified| parse_date(last_modified))
+            .map_or(0, |last_modified| date.saturating_sub(last_modified) / 10)
+    }
+
+    fn is_storable(&self) -> bool {
+        let directives = self.directives();
+        let vary_all = self
+            .headers
+            .get("vary")
+            .is_some_and(|vary| vary.trim() == "*");
+
+        HEURISTICALLY_CACHEABLE.contains(&self.status)
+            && !directives.contains_key("no-store")
+            && !vary_all
+            // Useless when it can be neither served nor revalidated
+            && (self.freshness_lifetime() > 0 || !self.validators().is_empty())
+    }
+
+    fn serialize(&self, url: &str) -> Vec<u8> {
+        let mut output = format!(
+            "{}\n{} {} {} {}\n",
+            url, self.version, self.status, self.stored_at, self.explanation
+        );
+
+        for (name, value) in &self.headers {
+            output.push_str(&format!("{}: {}\n", name, value));
+        }
+        output.push('\n');
+
+        let mut output = output.into_bytes();
+        output.extend_from_slice(&self.body);
+        output
+    }
+
+    // Hands back the URL the entry belongs to alongside it
+    fn deserialize(data: &[u8]) -> Result<(String, Self), Err> {
+        let invalid = || -> Err {
+            Box::new(URLError {
+                message: "Invalid cache entry".to_string(),
+            })
+        };
+
+        let separator = data
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .ok_or_else(invalid)?;
+        let head = std::str::from_utf8(&data[..separator])?;
+        let mut lines = head.lines();
+
+        let url = lines.next().ok_or_else(invalid)?.to_string();
+        // The explanation may be empty or contain spaces, it comes last
+        let status_line: Vec<&str> = lines.next().ok_or_else(invalid)?.splitn(4, ' ').collect();
+        let [version, status, stored_at, explanation] = status_line[..] else {
+            return Err(invalid());
+        };
+
+        let mut headers = HashMap::new();
+        for line in lines {
+            let (name, value) = line.split_once(": ").ok_or_else(invalid)?;
+            headers.insert(name.to_string(), value.to_string());
+        }
+
+        Ok((
+            url,
+            Self {
+                version: version.to_string(),
+                status: status.parse()?,
+                explanation: explanation.to_string(),
+                headers,
+                body: data[separator + 2..].to_vec(),
+                stored_at: stored_at.parse()?,
+            },
+        ))
+    }
+}
+
+/// Responses to GET requests keyed by URL, kept in memory and optionally written to a
+/// directory so they survive the process.
+#[derive(Default)]
+pub struct HttpCache {
+    entries: Mutex<HashMap<String, CachedResponse>>,
+    directory: Option<PathBuf>,
+    stats: Mutex<CacheStats>,
+}
+
+impl HttpCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_directory(directory: PathBuf) -> Result<Self, Err> {
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory: Some(directory),
+            ..Default::default()
+        })
+    }
+
+    pub fn lookup(&self, url: &str) -> Option<CachedResponse> {
+        if let Some(entry) = self.entries.lock().unwrap().get(url) {
+            return Some(entry.clone());
+        }
+
+        // Entries written by an earlier process
+        let data = fs::read(self.entry_path(url)?).ok()?;
+        let (stored_url, entry) = CachedResponse::deserialize(&data).ok()?;
+
+        // Different URL with the same hash
+        if stored_url != url {
+            return None;
+        }
+
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), entry.clone());
+
+        Some(entry)
+    }
+
+    /// Keeps `response` when the cache is allowed to, dropping any older copy otherwise.
+    pub fn store(&self, url: &str, response: CachedResponse) {
+        let path = self.entry_path(url);
+
+        if !response.is_storable() {
+            self.entries.lock().unwrap().remove(url);
+            if let Some(path) = path {
+                let _ = fs::remove_file(path);
+            }
+            return;
+        }
+
+        // The disk copy is best effort, the in-memory one is enough for this process
+        if let Some(path) = path {
+            let _ = fs::write(path, response.serialize(url));
+        }
+
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), response);
+    }
+
+    pub fn count(&self, lookup: Lookup) {
+        let mut stats = self.stats.lock().unwrap();
+
+        match lookup {
+            Lookup::Hit => stats.hits += 1,
+            Lookup::Revalidated => stats.revalidated += 1,
+            Lookup::Miss => stats.misses += 1,
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+
+        if let Some(directory) = &self.directory {
+            for entry in fs::read_dir(directory).into_iter().flatten().flatten() {
+                if entry
+                    .path()
+                    .extension()
+                    .is_some_and(|extension| extension == "cache")
+                {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+    }
+
+    fn entry_path(&self, url: &str) -> Option<PathBuf> {
+        let directory = self.directory.as_ref()?;
+
+        Some(directory.join(format!("{:016x}.cache", fnv1a(url.as_bytes()))))
+    }
+}
+
+// Directive names are case-insensitive, values may be quoted
+fn cache_control(value: &str) -> HashMap<String, Option<String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_lowercase(),
+                Some(value.trim().trim_matches('"').to_string()),
+            ),
+            None => (directive.to_lowercase(), None),
+        })
+        .collect()
+}
+
+// Stable across Rust releases unlike `DefaultHasher`, file names must not change
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&str, &str)]) -> CachedResponse {
+        CachedResponse::new(
+            "HTTP/1.1".to_string(),
+            200,
+            "OK".to_string(),
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            b"cached".to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_freshness() {
+        assert!(response(&[("cache-control", "max-age=60")]).is_fresh());
+        assert!(!response(&[("cache-control", "max-age=60"), ("age", "60")]).is_fresh());
+        assert!(!response(&[("cache-control", "no-cache, max-age=60")]).is_fresh());
+        assert!(response(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("expires", "Sun, 06 Nov 1994 08:50:37 GMT"),
+        ])
+        .is_fresh());
+        assert!(!response(&[("expires", "0")]).is_fresh());
+        // A tenth of the time since the last modification
+        assert!(response(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("last-modified", "Sun, 06 Nov 1994 07:49:37 GMT"),
+        ])
+        .is_fresh());
+    }
+
+    #[test]
+    fn test_store_respects_no_store() {
+        let cache = HttpCache::new();
+
+        cache.store(
+            "http://a/",
+            response(&[("cache-control", "no-store, max-age=60")]),
+        );
+        cache.store("http://b/", response(&[]));
+        cache.store("http://c/", response(&[("etag", "\"v1\"")]));
+
+        assert!(cache.lookup("http://a/").is_none());
+        assert!(cache.lookup("http://b/").is_none());
+        assert_eq!(
+            cache.lookup("http://c/").unwrap().validators(),
+            vec![("If-None-Match".to_string(), "\"v1\"".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_refresh_after_304() {
+        let mut cached = response(&[("etag", "\"v1\""), ("content-length", "6")]);
+        let headers = HashMap::from([
+            ("cache-control".to_string(), "max-age=60".to_string()),
+            ("content-length".to_string(), "0".to_string()),
+        ]);
+
+        assert!(!cached.is_fresh());
+        cached.refresh(&headers);
+
+        assert!(cached.is_fresh());
+        assert_eq!(cached.headers["content-length"], "6");
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let directory = std::env::temp_dir().join("ewb_http_cache");
+        let _ = fs::remove_dir_all(&directory);
+        let url = "http://example.com/page";
+
+        let cache = HttpCache::with_directory(directory.clone()).unwrap();
+        cache.store(url, response(&[("cache-control", "max-age=60")]));
+
+        // A new cache reads the entry back from disk
+        let cache = HttpCache::with_directory(directory.clone()).unwrap();
+        let entry = cache.lookup(url).unwrap();
+
+        assert_eq!(entry.body, b"cached");
+        assert!(entry.is_fresh());
+        assert!(cache.lookup("http://example.com/other").is_none());
+
+        cache.clear();
+        assert!(HttpCache::with_directory(directory)
+            .unwrap()
+            .lookup(url)
+            .is_none());
+    }
+}
@@ -0,0 +1,222 @@
+// Character encoding of fetched bodies (https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding)
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+// The prescan only looks at this many leading bytes
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Picks the encoding of a body: a byte order mark wins, then the Content-Type charset,
+/// then a `<meta>` declaration near the start. Undeclared bodies are UTF-8 when they are
+/// valid UTF-8 and windows-1252 (what ISO-8859-1 labels map to) otherwise.
+pub fn detect(content_type: Option<&str>, body: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return encoding;
+    }
+
+    if let Some(encoding) = content_type.and_then(from_content_type) {
+        return encoding;
+    }
+
+    if let Some(encoding) = prescan(&body[..body.len().min(PRESCAN_LENGTH)]) {
+        return encoding;
+    }
+
+    match std::str::from_utf8(body) {
+        Ok(_) => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+/// Encoding named by the `charset` parameter of a Content-Type value.
+pub fn from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+
+        Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes())
+    })
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+
+        if rest.starts_with(b"<!--") {
+            position += rest
+                .windows(3)
+                .position(|window| window == b"-->")
+                .map_or(rest.len(), |end| end + 3);
+        } else if is_meta_tag(rest) {
+            let (attributes, length) = attributes(&rest[5..]);
+
+            if let Some(encoding) = meta_encoding(&attributes) {
+                return Some(encoding);
+            }
+
+            position += 5 + length;
+        } else {
+            position += 1;
+        }
+    }
+
+    None
+}
+
+fn is_meta_tag(bytes: &[u8]) -> bool {
+    bytes.len() > 5
+        && bytes[..5].eq_ignore_ascii_case(b"<meta")
+        && (bytes[5].is_ascii_whitespace() || bytes[5] == b'/')
+}
+
+// Attributes of a tag up to its closing `>` (names lowercased), and how many bytes they took
+fn attributes(bytes: &[u8]) -> (Vec<(String, String)>, usize) {
+    let mut attributes = Vec::new();
+    let mut position = 0;
+
+    let skip_while = |position: &mut usize, skip: &dyn Fn(u8) -> bool| {
+        while bytes.get(*position).is_some_and(|byte| skip(*byte)) {
+            *position += 1;
+        }
+    };
+    let text = |start: usize, end: usize| String::from_utf8_lossy(&bytes[start..end]).to_string();
+
+    loop {
+        skip_while(&mut position, &|byte| {
+            byte.is_ascii_whitespace() || byte == b'/'
+        });
+
+        match bytes.get(position) {
+            None => break,
+            Some(b'>') => {
+                position += 1;
+                break;
+            }
+            _ => {}
+        }
+
+        let start = position;
+        skip_while(&mut position, &|byte| {
+            !byte.is_ascii_whitespace() && !b"/>=".contains(&byte)
+        });
+        let name = text(start, position).to_ascii_lowercase();
+        skip_while(&mut position, &|byte| byte.is_ascii_whitespace());
+
+        let mut value = String::new();
+
+        if bytes.get(position) == Some(&b'=') {
+            position += 1;
+            skip_while(&mut position, &|byte| byte.is_ascii_whitespace());
+
+            match bytes.get(position).copied() {
+                Some(quote @ (b'"' | b'\'')) => {
+                    let start = position + 1;
+                    position = start;
+                    skip_while(&mut position, &|byte| byte != quote);
+                    value = text(start, position);
+                    position = (position + 1).min(bytes.len());
+                }
+                _ => {
+                    let start = position;
+                    skip_while(&mut position, &|byte| {
+                        !byte.is_ascii_whitespace() && byte != b'>'
+                    });
+                    value = text(start, position);
+                }
+            }
+        }
+
+        attributes.push((name, value));
+    }
+
+    (attributes, position)
+}
+
+fn meta_encoding(attributes: &[(String, String)]) -> Option<&'static Encoding> {
+    // Repeated attributes are ignored, the first one counts
+    let attribute = |name: &str| {
+        attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let encoding = match attribute("charset") {
+        Some(charset) => Encoding::for_label(charset.trim().as_bytes()),
+        None if attribute("http-equiv")
+            .is_some_and(|value| value.eq_ignore_ascii_case("content-type")) =>
+        {
+            attribute("content").and_then(from_content_type)
+        }
+        None => None,
+    }?;
+
+    // A document we could read the declaration of as ASCII can't be UTF-16
+    Some(match encoding {
+        encoding if encoding == UTF_16LE || encoding == UTF_16BE => UTF_8,
+        encoding if encoding == X_USER_DEFINED => WINDOWS_1252,
+        encoding => encoding,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::GBK;
+
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(None, b"\xEF\xBB\xBFhi"), UTF_8);
+        assert_eq!(
+            detect(Some("text/html; charset=utf-8"), b"\xFF\xFEh\x00"),
+            UTF_16LE
+        );
+        assert_eq!(detect(Some("text/html; Charset=\"GBK\""), b""), GBK);
+        assert_eq!(
+            detect(Some("text/html; charset=ISO-8859-1"), b""),
+            WINDOWS_1252
+        );
+        assert_eq!(
+            detect(Some("text/html; charset=bogus"), b"caf\xE9"),
+            WINDOWS_1252
+        );
+        assert_eq!(detect(Some("text/html"), "café".as_bytes()), UTF_8);
+
+        assert_eq!(detect(None, b"<html><META charset='gb2312'>"), GBK);
+        assert_eq!(
+            detect(
+                None,
+                b"<meta name=x><meta http-equiv=Content-Type content=\"text/html; charset=windows-1251\">"
+            ),
+            encoding_rs::WINDOWS_1251
+        );
+        assert_eq!(detect(None, b"<meta charset=\"utf-16\">"), UTF_8);
+        // Declarations inside comments don't count, the header beats the markup
+        assert_eq!(detect(None, b"<!-- <meta charset=gbk> -->"), UTF_8);
+        assert_eq!(
+            detect(Some("text/html;charset=utf-8"), b"<meta charset=gbk>"),
+            UTF_8
+        );
+    }
+
+    #[test]
+    fn test_transcode() {
+        let latin1 = b"<p>caf\xE9</p>";
+        let (text, _, errors) =
+            detect(Some("text/html; charset=iso-8859-1"), latin1).decode(latin1);
+        assert_eq!((text.as_ref(), errors), ("<p>café</p>", false));
+
+        let gbk = b"<meta charset=gbk><p>\xC4\xE3\xBA\xC3</p>";
+        let (text, _, errors) = detect(None, gbk).decode(gbk);
+        assert_eq!(
+            (text.as_ref(), errors),
+            ("<meta charset=gbk><p>你好</p>", false)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+// Content-Encoding decoding (https://httpwg.org/specs/rfc9110.html#field.content-encoding)
+
+use std::{error::Error, io::Read};
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+
+use crate::url::URLError;
+
+type Err = Box<dyn Error>;
+
+/// Value for the Accept-Encoding request header, only what we can decode is advertised.
+pub fn accept_encoding() -> &'static str {
+    if cfg!(feature = "brotli") {
+        "gzip, deflate, br"
+    } else {
+        "gzip, deflate"
+    }
+}
+
+/// Undoes the codings listed in a Content-Encoding header. They were applied in the
+/// order listed, so they are removed last to first.
+pub fn decode(content_encoding: &str, body: Vec<u8>) -> Result<Vec<u8>, Err> {
+    content_encoding
+        .split(',')
+        .map(|coding| coding.trim().to_lowercase())
+        .filter(|coding| !coding.is_empty())
+        .rev()
+        .try_fold(body, |body, coding| decode_one(&coding, body))
+}
+
+fn decode_one(coding: &str, body: Vec<u8>) -> Result<Vec<u8>, Err> {
+    let mut decoded = Vec::new();
+
+    match coding {
+        "identity" => return Ok(body),
+        "gzip" | "x-gzip" => {
+            MultiGzDecoder::new(body.as_slice()).read_to_end(&mut decoded)?;
+        }
+        // Meant to be zlib wrapped, but plenty of servers send a raw deflate stream
+        "deflate" => {
+            if is_zlib_header(&body) {
+                ZlibDecoder::new(body.as_slice()).read_to_end(&mut decoded)?;
+            } else {
+                DeflateDecoder::new(body.as_slice()).read_to_end(&mut decoded)?;
+            }
+        }
+        #[cfg(feature = "brotli")]
+        "br" => {
+            brotli_decompressor::Decompressor::new(body.as_slice(), 4096)
+                .read_to_end(&mut decoded)?;
+        }
+        coding => {
+            return Err(Box::new(URLError {
+                message: format!("Unsupported content encoding: {}", coding),
+            }))
+        }
+    }
+
+    Ok(decoded)
+}
+
+// CMF/FLG pair of a zlib stream: deflate method and a header checksum divisible by 31
+fn is_zlib_header(body: &[u8]) -> bool {
+    body.len() >= 2
+        && body[0] & 0x0f == 8
+        && (u16::from(body[0]) << 8 | u16::from(body[1])) % 31 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{
+        write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+        Compression,
+    };
+
+    use super::*;
+
+    const BODY: &[u8] = b"<html><p>compressed</p></html>";
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decode_gzip() {
+        assert_eq!(decode("gzip", gzip(BODY)).unwrap(), BODY);
+        assert_eq!(decode("X-Gzip", gzip(BODY)).unwrap(), BODY);
+    }
+
+    #[test]
+    fn test_decode_deflate_zlib_and_raw() {
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(BODY).unwrap();
+        let mut raw = DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(BODY).unwrap();
+
+        assert_eq!(decode("deflate", zlib.finish().unwrap()).unwrap(), BODY);
+        assert_eq!(decode("deflate", raw.finish().unwrap()).unwrap(), BODY);
+    }
+
+    #[test]
+    fn test_decode_stacked_codings() {
+        let twice = gzip(&gzip(BODY));
+
+        assert_eq!(decode("gzip, gzip", twice).unwrap(), BODY);
+        assert_eq!(decode("identity", BODY.to_vec()).unwrap(), BODY);
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_decode_brotli() {
+        let encoded = [
+            11, 7, 128, 60, 104, 116, 109, 108, 62, 98, 114, 60, 47, 104, 116, 109, 108, 62, 3,
+        ];
+
+        assert_eq!(decode("br", encoded.to_vec()).unwrap(), b"<html>br</html>");
+    }
+
+    #[test]
+    fn test_unknown_coding() {
+        assert!(decode("compress", BODY.to_vec()).is_err());
+        assert!(decode("gzip", BODY.to_vec()).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+// HTTP state management (https://httpwg.org/specs/rfc6265.html)
+//
+// Public suffixes are not known to us, so a site can set cookies for a whole registrable
+// suffix such as `co.uk`. Good enough for scraping, not for a real browser.
+
+use std::{error::Error, sync::Mutex};
+
+use crate::date::{parse_date, unix_now};
+use crate::url::URLError;
+use crate::whatwg_url::{Host, URLRecord};
+
+type Err = Box<dyn Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Without leading dot, see `host_only`
+    pub domain: String,
+    pub path: String,
+    /// Seconds since the Unix epoch, `None` for session cookies
+    pub expires: Option<u64>,
+    pub secure: bool,
+    pub http_only: bool,
+    /// Set without a Domain attribute, only sent back to the exact host
+    pub host_only: bool,
+}
+
+impl Cookie {
+    /// Parses a Set-Cookie header value received from `url`, `None` when it has to be ignored.
+    pub fn parse(set_cookie: &str, url: &URLRecord) -> Option<Self> {
+        let mut parts = set_cookie.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let (name, value) = (name.trim(), value.trim());
+
+        if name.is_empty() {
+            return None;
+        }
+
+        let host = url.host_str();
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: host.clone(),
+            path: default_path(&url.pathname()),
+            expires: None,
+            secure: false,
+            http_only: false,
+            host_only: true,
+        };
+        let mut max_age = None;
+        let mut expires = None;
+
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+
+            match key.trim().to_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_lowercase();
+
+                    if !domain_matches(&host, &domain, url) {
+                        return None;
+                    }
+
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                // Max-Age wins over Expires regardless of their order
+                "max-age" => max_age = value.parse::<i64>().ok().or(max_age),
+                "expires" => expires = parse_date(value).or(expires),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+
+        cookie.expires = match max_age {
+            Some(max_age) => Some(unix_now().saturating_add_signed(max_age.max(0))),
+            None => expires,
+        };
+
+        // Only secure origins may set secure cookies (RFC 6265bis)
+        if cookie.secure && url.scheme != "https" {
+            return None;
+        }
+
+        // Cookie name prefixes, https://httpwg.org/http-extensions/draft-ietf-httpbis-rfc6265bis.html#name-cookie-name-prefixes
+        if name.starts_with("__Secure-") && !cookie.secure {
+            return None;
+        }
+        if name.starts_with("__Host-")
+            && (!cookie.secure || !cookie.host_only || cookie.path != "/")
+        {
+            return None;
+        }
+
+        Some(cookie)
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, url: &URLRecord) -> bool {
+        let host = url.host_str();
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain, url)
+        };
+
+        domain_ok
+            && path_matches(&url.pathname(), &self.path)
+            && (!self.secure || url.scheme == "https")
+    }
+}
+
+/// Cookies shared by every request it is attached to.
+#[derive(Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the cookie of a Set-Cookie header received from `url`. An expiry in the past
+    /// removes the cookie instead.
+    pub fn store(&self, set_cookie: &str, url: &URLRecord) {
+        if let Some(cookie) = Cookie::parse(set_cookie, url) {
+            self.insert(cookie);
+        }
+    }
+
+    pub fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.cookies.lock().unwrap();
+
+        cookies.retain(|stored| {
+            (&stored.name, &stored.domain, &stored.path)
+                != (&cookie.name, &cookie.domain, &cookie.path)
+        });
+
+        if !cookie.is_expired(unix_now()) {
+            cookies.push(cookie);
+        }
+    }
+
+    /// Value for the Cookie header of a request to `url`, longer paths first.
+    pub fn header_for(&self, url: &URLRecord) -> Option<String> {
+        let now = unix_now();
+        let mut cookies = self.cookies.lock().unwrap();
+
+        cookies.retain(|cookie| !cookie.is_expired(now));
+
+        // The sort is stable so cookies with the same path keep their creation order
+        let mut matching: Vec<&Cookie> = cookies
+            .iter()
+            .filter(|cookie| cookie.matches(url))
+            .collect();
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+
+        if matching.is_empty() {
+            return None;
+        }
+
+        Some(
+            matching
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<String>>()
+                .join("; "),
+        )
+    }
+
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let now = unix_now();
+
+        self.cookies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Serializes the jar in the Netscape `cookies.txt` format understood by curl and wget.
+    pub fn to_netscape(&self) -> String {
+        let mut output = String::from("# Netscape HTTP Cookie File\n");
+
+        for cookie in self.cookies() {
+            let domain = if cookie.host_only {
+                cookie.domain.clone()
+            } else {
+                format!(".{}", cookie.domain)
+            };
+
+            output.push_str(&format!(
+                "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                domain,
+                netscape_bool(!cookie.host_only),
+                cookie.path,
+                netscape_bool(cookie.secure),
+                cookie.expires.unwrap_or(0),
+                cookie.name,
+                cookie.value,
+            ));
+        }
+
+        output
+    }
+
+    /// Loads cookies from the Netscape `cookies.txt` format, see `to_netscape`.
+    pub fn from_netscape(source: &str) -> Result<Self, Err> {
+        let jar = Self::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, include_subdomains, path, secure, expires, name, value] = fields[..]
+            else {
+                return Err(Box::new(URLError {
+                    message: format!("Invalid cookie on line {}", index + 1),
+                }));
+            };
+
+            jar.insert(Cookie {
+                name: name.to_string(),
+                value: value.to_string(),
+                domain: domain.trim_start_matches('.').to_lowercase(),
+                path: path.to_string(),
+                expires: expires.parse().ok().filter(|expires| *expires != 0),
+                secure: secure == "TRUE",
+                http_only,
+                host_only: include_subdomains != "TRUE",
+            });
+        }
+
+        Ok(jar)
+    }
+}
+
+fn netscape_bool(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+// IP addresses only ever match themselves
+fn domain_matches(host: &str, domain: &str, url: &URLRecord) -> bool {
+    if host == domain {
+        return true;
+    }
+
+    matches!(url.host, Some(Host::Domain(_)))
+        && host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// Directory of the request path (RFC 6265 section 5.1.4)
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(last) => path[..last].to_string(),
+    }
+}
+
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> URLRecord {
+        URLRecord::parse(url, None).unwrap()
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let cookie = Cookie::parse(
+            "id=a3fWa; Domain=.Example.com; Path=/docs; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Secure; HttpOnly",
+            &url("https://www.example.com/login"),
+        )
+        .unwrap();
+
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.host_only);
+        assert_eq!(cookie.path, "/docs");
+        assert_eq!(cookie.expires, Some(1445412480));
+        assert!(cookie.secure && cookie.http_only);
+    }
+
+    #[test]
+    fn test_rejected_cookies() {
+        let page = url("http://www.example.com/a/b");
+
+        assert!(Cookie::parse("id=1; Domain=other.com", &page).is_none());
+        assert!(Cookie::parse("id=1; Secure", &page).is_none());
+        assert!(
+            Cookie::parse("__Host-id=1; Secure; Path=/", &url("https://example.com/")).is_some()
+        );
+        assert!(Cookie::parse(
+            "__Host-id=1; Secure; Domain=example.com",
+            &url("https://example.com/")
+        )
+        .is_none());
+        assert!(Cookie::parse("no-equals-sign", &page).is_none());
+        assert_eq!(Cookie::parse("id=1", &page).unwrap().path, "/a");
+    }
+
+    #[test]
+    fn test_header_for() {
+        let jar = CookieJar::new();
+        let login = url("https://example.com/account/login");
+
+        jar.store("session=abc; Path=/", &login);
+        jar.store("pref=dark; Path=/account; Domain=example.com", &login);
+        jar.store("token=xyz; Secure", &login);
+
+        assert_eq!(
+            jar.header_for(&url("https://example.com/account/settings")),
+            Some("pref=dark; token=xyz; session=abc".to_string())
+        );
+        assert_eq!(jar.header_for(&url("http://sub.example.com/")), None);
+        assert_eq!(
+            jar.header_for(&url("http://sub.example.com/account")),
+            Some("pref=dark".to_string())
+        );
+        assert_eq!(
+            jar.header_for(&url("https://example.com/accounts")),
+            Some("session=abc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expired_cookie_is_removed() {
+        let jar = CookieJar::new();
+        let page = url("http://example.com/");
+
+        jar.store("id=1; Max-Age=3600", &page);
+        assert_eq!(jar.cookies().len(), 1);
+
+        jar.store("id=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT", &page);
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn test_netscape_round_trip() {
+        let jar = CookieJar::new();
+        jar.store(
+            "a=1; Domain=example.com; Max-Age=60; HttpOnly",
+            &url("https://example.com/x/y"),
+        );
+        jar.store("b=2", &url("https://example.com/x/y"));
+
+        let serialized = jar.to_netscape();
+        let loaded = CookieJar::from_netscape(&serialized).unwrap();
+
+        assert!(serialized.contains("#HttpOnly_.example.com\tTRUE\t/x\tFALSE\t"));
+        assert!(serialized.contains("example.com\tFALSE\t/x\tFALSE\t0\tb\t2\n"));
+        assert_eq!(loaded.cookies(), jar.cookies());
+        assert!(CookieJar::from_netscape("example.com\tFALSE\n").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::page::{self, LinkKind, Page};
+use crate::robots::{Robots, USER_AGENT_TOKEN};
+use crate::url::URL;
+
+type Err = Box<dyn Error>;
+
+pub struct CrawlOptions {
+    /// Links are followed this many times from the start page, 0 only fetches the start page
+    pub max_depth: usize,
+    pub max_pages: usize,
+    /// Only follow links with the same origin as the start page
+    pub same_origin: bool,
+    /// Number of pages fetched in parallel
+    pub concurrency: usize,
+    /// Minimum time between two requests to the same origin, robots.txt may raise it
+    pub delay: Duration,
+    pub respect_robots: bool,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 2,
+            max_pages: 100,
+            same_origin: true,
+            concurrency: 4,
+            delay: Duration::from_millis(500),
+            respect_robots: true,
+        }
+    }
+}
+
+struct Job {
+    url: String,
+    depth: usize,
+}
+
+enum Outcome {
+    Fetched { page: Box<Page>, depth: usize },
+    // Disallowed by robots.txt or failed to load
+    Skipped,
+}
+
+// State shared by the fetching threads
+struct Shared {
+    delay: Duration,
+    respect_robots: bool,
+    robots: Mutex<HashMap<String, Arc<Robots>>>,
+    // Earliest time the next request to each origin may start
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+/// Breadth-first crawl yielding pages as they are fetched. Pages that fail to load or that
+/// robots.txt disallows are skipped.
+pub struct Crawler {
+    options: CrawlOptions,
+    origin: String,
+    queue: VecDeque<Job>,
+    seen: HashSet<String>,
+    in_flight: usize,
+    yielded: usize,
+    jobs: Option<Sender<Job>>,
+    outcomes: Receiver<Outcome>,
+}
+
+pub fn crawl(start_url: &str, options: CrawlOptions) -> Result<Crawler, Err> {
+    let start = URL::new(start_url.to_string())?;
+    let start_url = normalize(&start);
+
+    let (jobs, job_receiver) = mpsc::channel::<Job>();
+    let (outcome_sender, outcomes) = mpsc::channel();
+    let job_receiver = Arc::new(Mutex::new(job_receiver));
+    let shared = Arc::new(Shared {
+        delay: options.delay,
+        respect_robots: options.respect_robots,
+        robots: Mutex::new(HashMap::new()),
+        next_request: Mutex::new(HashMap::new()),
+    });
+
+    for _ in 0..options.concurrency.max(1) {
+        let job_receiver = Arc::clone(&job_receiver);
+        let outcome_sender = outcome_sender.clone();
+        let shared = Arc::clone(&shared);
+
+        thread::spawn(move || loop {
+            // The lock is only held while waiting for the next job
+            let job = job_receiver.lock().unwrap().recv();
+
+            // The crawler was dropped
+            let Ok(job) = job else {
+                break;
+            };
+
+            if outcome_sender.send(shared.fetch(job)).is_err() {
+                break;
+            }
+        });
+    }
+
+    Ok(Crawler {
+        options,
+        origin: start.origin(),
+        queue: VecDeque::from([Job {
+            url: start_url.clone(),
+            depth: 0,
+        }]),
+        seen: HashSet::from([start_url]),
+        in_flight: 0,
+        yielded: 0,
+        jobs: Some(jobs),
+        outcomes,
+    })
+}
+
+impl Iterator for Crawler {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        loop {
+            while self.in_flight + self.yielded < self.options.max_pages {
+                let Some(job) = self.queue.pop_front() else {
+                    break;
+                };
+
+                self.jobs.as_ref()?.send(job).ok()?;
+                self.in_flight += 1;
+            }
+
+            if self.in_flight == 0 {
+                // Nothing left to fetch, let the threads go
+                self.jobs = None;
+                return None;
+            }
+
+            let outcome = self.outcomes.recv().ok()?;
+            self.in_flight -= 1;
+
+            if let Outcome::Fetched { page, depth } = outcome {
+                if depth < self.options.max_depth {
+                    self.enqueue_links(&page, depth + 1);
+                }
+
+                self.yielded += 1;
+                return Some(*page);
+            }
+        }
+    }
+}
+
+impl Crawler {
+    fn enqueue_links(&mut self, page: &Page, depth: usize) {
+        let anchors = page
+            .links()
+            .into_iter()
+            .filter(|link| link.kind == LinkKind::Anchor);
+
+        for anchor in anchors {
+            let Ok(link) = URL::new(anchor.url) else {
+                continue;
+            };
+
+            if !["http", "https"].contains(&link.scheme()) {
+                continue;
+            }
+
+            if self.options.same_origin && link.origin() != self.origin {
+                continue;
+            }
+
+            let url = normalize(&link);
+
+            if self.seen.insert(url.clone()) {
+                self.queue.push_back(Job { url, depth });
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn fetch(&self, job: Job) -> Outcome {
+        let Ok(url) = URL::new(job.url.clone()) else {
+            return Outcome::Skipped;
+        };
+        let origin = url.origin();
+
+        let robots = self.robots(&url);
+        if !robots.is_allowed(USER_AGENT_TOKEN, url.path()) {
+            return Outcome::Skipped;
+        }
+
+        let delay = robots
+            .crawl_delay(USER_AGENT_TOKEN)
+            .map_or(self.delay, |crawl_delay| crawl_delay.max(self.delay));
+        self.wait_turn(&origin, delay);
+
+        match page::load_page(&job.url) {
+            Ok(page) => Outcome::Fetched {
+                page: Box::new(page),
+                depth: job.depth,
+            },
+            Err(_) => Outcome::Skipped,
+        }
+    }
+
+    fn robots(&self, url: &URL) -> Arc<Robots> {
+        if !self.respect_robots {
+            return Arc::new(Robots::default());
+        }
+
+        let origin = url.origin();
+
+        if let Some(robots) = self.robots.lock().unwrap().get(&origin) {
+            return Arc::clone(robots);
+        }
+
+        // Another thread may be fetching it as well, whoever finishes first wins
+        let robots = Arc::new(fetch_robots(url));
+
+        Arc::clone(self.robots.lock().unwrap().entry(origin).or_insert(robots))
+    }
+
+    fn wait_turn(&self, origin: &str, delay: Duration) {
+        let now = Instant::now();
+
+        let start = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let start = next_request
+                .get(origin)
+                .map_or(now, |next| (*next).max(now));
+
+            next_request.insert(origin.to_string(), start + delay);
+            start
+        };
+
+        thread::sleep(start - now);
+    }
+}
+
+// 4xx means there are no rules, anything else that isn't a 2xx is treated as a full ban
+fn fetch_robots(url: &URL) -> Robots {
+    let Ok(mut robots_url) = URL::new(url.resolve("/robots.txt")) else {
+        return Robots::disallow_all();
+    };
+
+    if robots_url.fetch().is_err() {
+        return Robots::disallow_all();
+    }
+
+    match robots_url.status() {
+        200..=299 => Robots::parse(&String::from_utf8_lossy(robots_url.body())),
+        400..=499 => Robots::default(),
+        _ => Robots::disallow_all(),
+    }
+}
+
+// Fragments point into the same document, they are dropped for deduplication
+fn normalize(url: &URL) -> String {
+    url.url().split('#').next().unwrap_or("").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_routes;
+
+    fn options(max_depth: usize, max_pages: usize) -> CrawlOptions {
+        CrawlOptions {
+            max_depth,
+            max_pages,
+            delay: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    fn crawled_paths(origin: &str, options: CrawlOptions) -> Vec<String> {
+        let mut paths: Vec<String> = crawl(&format!("{}/", origin), options)
+            .unwrap()
+            .map(|page| page.url.replace(origin, ""))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_crawl_follows_links_within_depth() {
+        let origin = serve_routes(vec![
+            (
+                "/",
+                r#"<html><a href="/a">a</a><a href="b#top">b</a><a href="https://elsewhere.example/">x</a></html>"#,
+            ),
+            (
+                "/a",
+                r#"<html><a href="/">home</a><a href="/deep">deep</a></html>"#,
+            ),
+            ("/b", "<html><p>b</p></html>"),
+            ("/deep", "<html><p>deep</p></html>"),
+        ]);
+
+        assert_eq!(
+            crawled_paths(&origin, options(1, 10)),
+            vec!["/", "/a", "/b"]
+        );
+        assert_eq!(
+            crawled_paths(&origin, options(2, 10)),
+            vec!["/", "/a", "/b", "/deep"]
+        );
+    }
+
+    #[test]
+    fn test_crawl_respects_max_pages() {
+        let origin = serve_routes(vec![(
+            "/",
+            r#"<html><a href="/a">a</a><a href="/b">b</a><a href="/c">c</a></html>"#,
+        )]);
+
+        assert_eq!(crawled_paths(&origin, options(1, 2)).len(), 2);
+    }
+
+    #[test]
+    fn test_crawl_respects_robots() {
+        let origin = serve_routes(vec![
+            ("/robots.txt", "User-agent: *\nDisallow: /private"),
+            (
+                "/",
+                r#"<html><a href="/private/page">p</a><a href="/public">p</a></html>"#,
+            ),
+            ("/private/page", "<html></html>"),
+            ("/public", "<html></html>"),
+        ]);
+
+        assert_eq!(crawled_paths(&origin, options(1, 10)), vec!["/", "/public"]);
+    }
+}
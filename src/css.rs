@@ -0,0 +1,306 @@
+// CSS style sheets (https://www.w3.org/TR/css-syntax-3/): style rules and their declarations.
+// At-rules are skipped, and so are rules whose selector isn't supported, the way browsers
+// drop rules they can't make sense of.
+
+use crate::selector::{SelectorList, Specificity};
+
+#[derive(Debug, Default)]
+pub struct Stylesheet {
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    /// The selector list as written, comments removed
+    pub selector_text: String,
+    pub selectors: SelectorList,
+    pub declarations: Vec<Declaration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    /// Lowercase, custom properties (`--name`) keep their case
+    pub name: String,
+    /// Without the `!important` flag
+    pub value: String,
+    pub important: bool,
+}
+
+impl Stylesheet {
+    /// Parses a style sheet, like the content of a `<style>` element. Parsing never fails,
+    /// what can't be understood is left out.
+    pub fn parse(source: &str) -> Self {
+        let source = strip_comments(source);
+        let mut rules = Vec::new();
+        let mut rest = source.as_str();
+
+        loop {
+            rest = rest.trim_start();
+
+            // Leftovers of hiding style sheets from ancient browsers
+            if let Some(after) = rest.strip_prefix("<!--").or(rest.strip_prefix("-->")) {
+                rest = after;
+                continue;
+            }
+
+            if rest.is_empty() {
+                break;
+            }
+
+            if rest.starts_with('@') {
+                rest = skip_at_rule(rest);
+                continue;
+            }
+
+            let Some(block_start) = find_unnested(rest, &['{']) else {
+                break;
+            };
+            let prelude = rest[..block_start].trim();
+            let block = &rest[block_start + 1..];
+            let block_end = find_unnested(block, &['}']).unwrap_or(block.len());
+
+            if let Ok(selectors) = SelectorList::parse(prelude) {
+                rules.push(Rule {
+                    selector_text: prelude.to_string(),
+                    selectors,
+                    declarations: parse_declarations(&block[..block_end]),
+                });
+            }
+
+            rest = block.get(block_end + 1..).unwrap_or("");
+        }
+
+        Self { rules }
+    }
+}
+
+impl Rule {
+    /// Specificity of each selector in the list, in order.
+    pub fn specificities(&self) -> Vec<Specificity> {
+        self.selectors.specificities()
+    }
+}
+
+/// Parses the content of a declaration block, invalid declarations are dropped.
+pub fn parse_declarations(block: &str) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    let mut rest = block;
+
+    while !rest.is_empty() {
+        let end = find_unnested(rest, &[';']).unwrap_or(rest.len());
+
+        if let Some(declaration) = parse_declaration(&rest[..end]) {
+            declarations.push(declaration);
+        }
+
+        rest = rest.get(end + 1..).unwrap_or("");
+    }
+
+    declarations
+}
+
+fn parse_declaration(source: &str) -> Option<Declaration> {
+    let (name, value) = source.split_once(':')?;
+    let name = name.trim();
+
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let mut value = value.trim();
+    let mut important = false;
+
+    if let Some(bang) = value.rfind('!') {
+        if value[bang + 1..].trim().eq_ignore_ascii_case("important") {
+            value = value[..bang].trim_end();
+            important = true;
+        }
+    }
+
+    let custom_property = name.starts_with("--");
+
+    if value.is_empty() && !custom_property {
+        return None;
+    }
+
+    Some(Declaration {
+        name: match custom_property {
+            true => name.to_string(),
+            false => name.to_ascii_lowercase(),
+        },
+        value: value.to_string(),
+        important,
+    })
+}
+
+// What follows an at-rule, which ends at its `;` or with its block
+fn skip_at_rule(source: &str) -> &str {
+    let Some(end) = find_unnested(source, &[';', '{']) else {
+        return "";
+    };
+
+    if &source[end..end + 1] == ";" {
+        return &source[end + 1..];
+    }
+
+    let block = &source[end + 1..];
+
+    find_unnested(block, &['}']).map_or("", |block_end| &block[block_end + 1..])
+}
+
+// Byte offset of the first of `stops` that is outside of strings and brackets
+fn find_unnested(text: &str, stops: &[char]) -> Option<usize> {
+    let mut chars = text.char_indices();
+    let mut quote = None;
+    let mut depth = 0usize;
+
+    while let Some((index, char)) = chars.next() {
+        match (quote, char) {
+            (_, '\\') => {
+                chars.next();
+            }
+            (Some(open), char) if char == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(char),
+            (None, char) if depth == 0 && stops.contains(&char) => return Some(index),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut quote = None;
+
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (None, '/') if chars.peek() == Some(&'*') => {
+                chars.next();
+
+                let mut previous = None;
+                for char in chars.by_ref() {
+                    if previous == Some('*') && char == '/' {
+                        break;
+                    }
+                    previous = Some(char);
+                }
+
+                // A comment separates tokens, `a/**/b` is not `ab`
+                stripped.push(' ');
+                continue;
+            }
+            (_, '\\') => {
+                stripped.push(char);
+                if let Some(escaped) = chars.next() {
+                    stripped.push(escaped);
+                }
+                continue;
+            }
+            (Some(open), char) if char == open => quote = None,
+            (None, '"' | '\'') => quote = Some(char),
+            _ => {}
+        }
+
+        stripped.push(char);
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declaration(name: &str, value: &str, important: bool) -> Declaration {
+        Declaration {
+            name: name.to_string(),
+            value: value.to_string(),
+            important,
+        }
+    }
+
+    #[test]
+    fn test_parse_stylesheet() {
+        let css = r#"
+            @charset "utf-8";
+            @import url("print.css") print;
+            /* page { color: red } */
+            body, .main > p { margin: 0 auto; COLOR: #333 !important; }
+            @media (max-width: 600px) { body { margin: 0 } }
+            a[href^="http:"] { background: url(data:image/png;base64,AAA=); content: "a;b}" }
+            a:hover { color: red }
+            #nav li{--Gap:4px;padding:1px 2px}
+        "#;
+        let stylesheet = Stylesheet::parse(css);
+        let selectors: Vec<&str> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| rule.selector_text.as_str())
+            .collect();
+
+        // The :hover rule is dropped, pseudo-classes aren't supported
+        assert_eq!(
+            selectors,
+            ["body, .main > p", "a[href^=\"http:\"]", "#nav li"]
+        );
+        assert_eq!(
+            stylesheet.rules[0].declarations,
+            [
+                declaration("margin", "0 auto", false),
+                declaration("color", "#333", true)
+            ]
+        );
+        assert_eq!(
+            stylesheet.rules[1].declarations,
+            [
+                declaration("background", "url(data:image/png;base64,AAA=)", false),
+                declaration("content", "\"a;b}\"", false)
+            ]
+        );
+        assert_eq!(
+            stylesheet.rules[2].declarations,
+            [
+                declaration("--Gap", "4px", false),
+                declaration("padding", "1px 2px", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_specificity() {
+        let stylesheet = Stylesheet::parse(
+            "body, .main > p, #nav li.active, a[href], * {} ul ol + li { color: red }",
+        );
+        let specificities: Vec<Vec<Specificity>> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| rule.specificities())
+            .collect();
+
+        assert_eq!(
+            specificities,
+            [
+                vec![(0, 0, 1), (0, 1, 1), (1, 1, 1), (0, 1, 1), (0, 0, 0)],
+                vec![(0, 0, 3)]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_declarations() {
+        assert_eq!(
+            parse_declarations(
+                " color ; : red; font size: 2px; width:; --empty:; ;top : 0 ! IMPORTANT"
+            ),
+            [
+                declaration("--empty", "", false),
+                declaration("top", "0", true)
+            ]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+// data: URLs (https://fetch.spec.whatwg.org/#data-urls)
+
+use std::error::Error;
+
+use crate::url::URLError;
+use crate::whatwg_url::percent_decode;
+
+type Err = Box<dyn Error>;
+
+const DEFAULT_MIME_TYPE: &str = "text/plain;charset=US-ASCII";
+
+#[derive(Debug, PartialEq)]
+pub struct DataURL {
+    pub mime_type: String,
+    pub body: Vec<u8>,
+}
+
+impl DataURL {
+    /// Runs the Fetch Standard's "data: URL processor" on a serialized URL.
+    pub fn parse(url: &str) -> Result<Self, Err> {
+        let input = url
+            .get(..5)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+            .map(|_| &url[5..])
+            .ok_or_else(|| err("Not a data: URL"))?;
+
+        // Fragments are not part of the payload
+        let input = input.split('#').next().unwrap_or("");
+        let (mime_type, data) = input
+            .split_once(',')
+            .ok_or_else(|| err("Missing comma in data: URL"))?;
+
+        let mut mime_type = mime_type.trim_matches(is_ascii_whitespace).to_string();
+        let mut body = percent_decode(data);
+
+        if let Some(stripped) = strip_base64(&mime_type) {
+            mime_type = stripped;
+            body = forgiving_base64_decode(&body)?;
+        }
+
+        if mime_type.starts_with(';') {
+            mime_type.insert_str(0, "text/plain");
+        }
+
+        let essence = mime_type.split(';').next().unwrap_or("").trim();
+        let valid = essence
+            .split_once('/')
+            .is_some_and(|(kind, subtype)| is_token(kind) && is_token(subtype));
+
+        if !valid {
+            mime_type = DEFAULT_MIME_TYPE.to_string();
+        }
+
+        Ok(Self { mime_type, body })
+    }
+}
+
+// `;base64` at the end, with optional spaces before the keyword
+fn strip_base64(mime_type: &str) -> Option<String> {
+    let split = mime_type.len().checked_sub(6)?;
+
+    if !mime_type.is_char_boundary(split) || !mime_type[split..].eq_ignore_ascii_case("base64") {
+        return None;
+    }
+
+    mime_type[..split]
+        .trim_end_matches(' ')
+        .strip_suffix(';')
+        .map(|rest| rest.trim_end_matches(is_ascii_whitespace).to_string())
+}
+
+// https://infra.spec.whatwg.org/#forgiving-base64-decode
+fn forgiving_base64_decode(input: &[u8]) -> Result<Vec<u8>, Err> {
+    let mut input: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|byte| !is_ascii_whitespace(*byte as char))
+        .collect();
+
+    if input.len().is_multiple_of(4) {
+        for _ in 0..2 {
+            if input.last() == Some(&b'=') {
+                input.pop();
+            }
+        }
+    }
+
+    if input.len() % 4 == 1 {
+        return Err(err("Invalid base64 in data: URL"));
+    }
+
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in input {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(err("Invalid base64 in data: URL")),
+        };
+
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+
+    Ok(output)
+}
+
+fn is_ascii_whitespace(char: char) -> bool {
+    matches!(char, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+// HTTP token code points, what a MIME type and subtype are made of
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(char))
+}
+
+fn err(message: &str) -> Err {
+    Box::new(URLError {
+        message: message.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> (String, Vec<u8>) {
+        let data = DataURL::parse(url).unwrap();
+        (data.mime_type, data.body)
+    }
+
+    #[test]
+    fn test_plain_and_percent_encoded() {
+        assert_eq!(
+            parse("data:,Hello%2C%20World!"),
+            (DEFAULT_MIME_TYPE.to_string(), b"Hello, World!".to_vec())
+        );
+        assert_eq!(
+            parse("data:text/html,<h1>hi</h1>#top"),
+            ("text/html".to_string(), b"<h1>hi</h1>".to_vec())
+        );
+        assert_eq!(
+            parse("data:;charset=utf-8,%FF"),
+            ("text/plain;charset=utf-8".to_string(), vec![0xFF])
+        );
+        assert_eq!(parse("data:text,x").0, DEFAULT_MIME_TYPE);
+        assert!(DataURL::parse("data:text/plain").is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(
+            parse("data:text/plain;base64,SGVsbG8="),
+            ("text/plain".to_string(), b"Hello".to_vec())
+        );
+        assert_eq!(
+            parse("data:;BASE64,W%20A"),
+            (DEFAULT_MIME_TYPE.to_string(), b"X".to_vec())
+        );
+        assert_eq!(parse("data:text/plain ;base64,WA").1, b"X");
+        // Not the base64 keyword, the payload is kept as is
+        assert_eq!(parse("data:text/plain;x=base64,WA").1, b"WA");
+        assert!(DataURL::parse("data:;base64,WA=").is_err());
+        assert!(DataURL::parse("data:;base64,W").is_err());
+        assert!(DataURL::parse("data:;base64,W!==").is_err());
+    }
+}
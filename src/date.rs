@@ -0,0 +1,98 @@
+// Dates as found in HTTP headers
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Parses a cookie date (RFC 6265 section 5.1.1) into seconds since the Unix epoch. The
+/// algorithm doesn't care about the order of the parts, so it reads every HTTP-date format
+/// as well.
+pub fn parse_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    let tokens = value
+        .split(|char: char| !(char.is_ascii_alphanumeric() || char == ':'))
+        .filter(|token| !token.is_empty());
+
+    for token in tokens {
+        let digits = token.len() - token.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+        if time.is_none() && token.contains(':') {
+            let parts: Option<Vec<u64>> = token.split(':').map(|part| part.parse().ok()).collect();
+
+            if let Some([hour, minute, second]) = parts.as_deref() {
+                time = Some((*hour, *minute, *second));
+                continue;
+            }
+        }
+
+        if day.is_none() && (1..=2).contains(&digits) {
+            day = token[..digits].parse::<u64>().ok();
+        } else if month.is_none() && digits == 0 && token.len() >= 3 {
+            let prefix = token[..3].to_lowercase();
+            month = MONTHS
+                .iter()
+                .position(|month| *month == prefix)
+                .map(|index| index as u64 + 1);
+        } else if year.is_none() && (2..=4).contains(&digits) {
+            year = token[..digits].parse::<u64>().ok();
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+
+    // Two digit years
+    if year < 70 {
+        year += 2000;
+    } else if year < 100 {
+        year += 1900;
+    }
+
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day)?;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+// Days since 1970-01-01 (http://howardhinnant.github.io/date_algorithms.html#days_from_civil)
+fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    (era * 146097 + day_of_era).checked_sub(719468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784111777));
+        assert_eq!(
+            parse_date("Sunday, 06-Nov-94 08:49:37 GMT"),
+            Some(784111777)
+        );
+        assert_eq!(parse_date("Sun Nov  6 08:49:37 1994"), Some(784111777));
+        assert_eq!(parse_date("not a date"), None);
+    }
+}
@@ -0,0 +1,320 @@
+// Downloads to disk: bodies are written to the file as they arrive instead of being kept in
+// memory, an interrupted download picks up where it stopped with a Range request
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::page::Prepare;
+use crate::url::{BodyEvent, URLError, URL};
+
+type Err = Box<dyn Error>;
+
+/// Told the bytes in the file so far and the full size, when known, each time some more
+/// are written. An error cancels the download with it.
+pub type Progress = Box<dyn FnMut(u64, Option<u64>) -> io::Result<()> + Send>;
+
+/// How a download ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Download {
+    /// Size of the file
+    pub size: u64,
+    /// Full size announced by the server
+    pub total: Option<u64>,
+    /// Bytes already in the file were kept and the rest appended
+    pub resumed: bool,
+}
+
+// The file the body being received goes to
+struct Writer {
+    path: PathBuf,
+    // Missing for bodies of error responses
+    file: Option<File>,
+    written: u64,
+    total: Option<u64>,
+    resumed: bool,
+    progress: Option<Progress>,
+}
+
+impl Writer {
+    fn start(&mut self, status: usize, headers: &HashMap<String, String>) -> io::Result<()> {
+        self.file = None;
+        self.resumed = false;
+
+        match status {
+            206 => {
+                let (start, total) = headers
+                    .get("content-range")
+                    .and_then(|range| content_range(range))
+                    .ok_or_else(|| io::Error::other("Invalid Content-Range header"))?;
+
+                // The ranges asked for always run to the end, from at most the file size
+                let mut file = OpenOptions::new().write(true).open(&self.path)?;
+                if start > file.metadata()?.len() {
+                    return Err(io::Error::other("Content-Range starts past the file end"));
+                }
+
+                file.set_len(start)?;
+                file.seek(SeekFrom::End(0))?;
+
+                self.file = Some(file);
+                self.written = start;
+                self.total = total;
+                self.resumed = start > 0;
+            }
+            200..=299 => {
+                self.file = Some(File::create(&self.path)?);
+                self.written = 0;
+                self.total = headers
+                    .get("content-length")
+                    .and_then(|length| length.parse().ok());
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+
+        file.write_all(data)?;
+        self.written += data.len() as u64;
+
+        match self.progress.as_mut() {
+            Some(progress) => progress(self.written, self.total),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Downloads `url` into the file at `path`, `prepare` sets up the request like for pages.
+///
+/// With `resume` the bytes already in the file are kept and only the rest is asked for,
+/// servers ignoring the Range get the file written again from the start. A failed download
+/// leaves what was received in the file, to be resumed.
+pub fn download(
+    url: &str,
+    path: &Path,
+    resume: bool,
+    prepare: Option<&Prepare>,
+    progress: Option<Progress>,
+) -> Result<Download, Err> {
+    let offset = match resume {
+        true => fs::metadata(path).map_or(0, |metadata| metadata.len()),
+        false => 0,
+    };
+
+    let mut url = URL::new(url.to_string())?;
+    if let Some(prepare) = prepare {
+        prepare(&mut url);
+    }
+
+    // Ranges are of the encoded body, which could only be decoded as a whole
+    url.set_header("Accept-Encoding", "identity")?;
+    if offset > 0 {
+        url.set_header("Range", &format!("bytes={}-", offset))?;
+    }
+
+    let writer = Arc::new(Mutex::new(Writer {
+        path: path.to_path_buf(),
+        file: None,
+        written: 0,
+        total: None,
+        resumed: false,
+        progress,
+    }));
+
+    let observed = Arc::clone(&writer);
+    url.set_keep_body(false);
+    url.set_body_observer(Box::new(move |event| {
+        let mut writer = observed.lock().unwrap();
+        match event {
+            BodyEvent::Start {
+                status, headers, ..
+            } => writer.start(status, headers),
+            BodyEvent::Data(data) => writer.write(data),
+        }
+    }));
+
+    url.fetch()?;
+
+    let status = url.status();
+    // Nothing left to send, the file was complete
+    if status == 416 && offset > 0 {
+        let total = url
+            .headers()
+            .into_iter()
+            .find(|(name, _)| name == "content-range")
+            .and_then(|(_, range)| range.strip_prefix("bytes */")?.parse().ok());
+
+        if total == Some(offset) {
+            return Ok(Download {
+                size: offset,
+                total,
+                resumed: true,
+            });
+        }
+    }
+
+    if !(200..300).contains(&status) {
+        return Err(Box::new(URLError {
+            message: format!("Unable to download: {} {}", status, url.reason()),
+        }));
+    }
+
+    let mut writer = writer.lock().unwrap();
+    match writer.file.take() {
+        Some(file) => file.sync_all()?,
+        // No body at all (204), the file is still created
+        None => drop(File::create(path)?),
+    }
+
+    Ok(Download {
+        size: writer.written,
+        total: writer.total,
+        resumed: writer.resumed,
+    })
+}
+
+// First byte and complete length of `bytes <first>-<last>/<length>`
+fn content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, length) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (first, _) = range.split_once('-')?;
+
+    let length = match length {
+        "*" => None,
+        length => Some(length.parse().ok()?),
+    };
+
+    Some((first.parse().ok()?, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{answer, listen};
+    use std::thread;
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    // Serves BODY once, honouring a Range header, and returns the request it got
+    fn serve_once() -> (String, thread::JoinHandle<String>) {
+        let (listener, port) = listen();
+        let url = format!("http://127.0.0.1:{}/file.bin", port);
+
+        let server = thread::spawn(move || {
+            answer(&listener, |request| {
+                let range = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Range: bytes="))
+                    .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+                let head = match range {
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n",
+                        start,
+                        BODY.len() - 1,
+                        BODY.len(),
+                        BODY.len() - start
+                    ),
+                    None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n", BODY.len()),
+                };
+
+                let mut response = head.into_bytes();
+                response.extend_from_slice(b"Connection: close\r\n\r\n");
+                response.extend_from_slice(&BODY[range.unwrap_or(0)..]);
+                response
+            })
+        });
+
+        (url, server)
+    }
+
+    type Calls = Arc<Mutex<Vec<(u64, Option<u64>)>>>;
+
+    fn recorder() -> (Calls, Progress) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&calls);
+        let progress: Progress = Box::new(move |written, total| {
+            recorded.lock().unwrap().push((written, total));
+            Ok(())
+        });
+
+        (calls, progress)
+    }
+
+    #[test]
+    fn test_download() {
+        let path = std::env::temp_dir().join("ewb_download.bin");
+        let (url, server) = serve_once();
+        let (calls, progress) = recorder();
+
+        let download = download(&url, &path, false, None, Some(progress)).unwrap();
+
+        assert!(!server.join().unwrap().contains("Range:"));
+        assert_eq!(
+            download,
+            Download {
+                size: BODY.len() as u64,
+                total: Some(BODY.len() as u64),
+                resumed: false,
+            }
+        );
+        assert_eq!(fs::read(&path).unwrap(), BODY);
+        assert_eq!(
+            calls.lock().unwrap().last(),
+            Some(&(BODY.len() as u64, Some(BODY.len() as u64)))
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resume_download() {
+        let path = std::env::temp_dir().join("ewb_download_resumed.bin");
+        fs::write(&path, &BODY[..10]).unwrap();
+        let (url, server) = serve_once();
+        let (calls, progress) = recorder();
+
+        let download = download(&url, &path, true, None, Some(progress)).unwrap();
+
+        assert!(server.join().unwrap().contains("Range: bytes=10-\r\n"));
+        assert!(download.resumed);
+        assert_eq!(download.size, BODY.len() as u64);
+        assert_eq!(fs::read(&path).unwrap(), BODY);
+        assert!(calls
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|&(written, _)| written > 10));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_progress_error_cancels() {
+        let path = std::env::temp_dir().join("ewb_download_cancelled.bin");
+        let (url, _server) = serve_once();
+        let progress: Progress = Box::new(|_, _| Err(io::Error::other("Cancelled")));
+
+        let error = download(&url, &path, false, None, Some(progress)).unwrap_err();
+
+        assert_eq!(error.to_string(), "Cancelled");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_content_range() {
+        assert_eq!(content_range("bytes 10-35/36"), Some((10, Some(36))));
+        assert_eq!(content_range("bytes 0-9/*"), Some((0, None)));
+        assert_eq!(content_range("bytes */36"), None);
+    }
+}
@@ -0,0 +1,463 @@
+// Request filtering with adblock-style rules
+// (https://help.adblockplus.org/hc/en-us/articles/360062733293): `||` host anchors, `|`
+// anchors, `*` wildcards, `^` separators, `/regex/`, `@@` exceptions and the `document` and
+// `match-case` options. Lines of hosts files (`0.0.0.0 ads.example`) block their host.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, RwLock,
+    },
+};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::whatwg_url::URLRecord;
+
+// Options of resource types, they are not told apart from each other
+const SUBRESOURCE_TYPES: [&str; 12] = [
+    "font",
+    "image",
+    "media",
+    "object",
+    "other",
+    "ping",
+    "script",
+    "stylesheet",
+    "subdocument",
+    "websocket",
+    "xhr",
+    "xmlhttprequest",
+];
+
+// Names hosts files map to themselves
+const LOCAL_HOSTS: [&str; 5] = [
+    "0.0.0.0",
+    "broadcasthost",
+    "local",
+    "localhost",
+    "localhost.localdomain",
+];
+
+/// What a request is for, rules apply to subresources unless they have the `document`
+/// option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    /// A page loaded as a document
+    Document,
+    /// Anything else: images, downloads, plain requests
+    Subresource,
+}
+
+/// A request refused because a rule matches its URL.
+#[derive(Debug)]
+pub struct BlockedError {
+    pub url: String,
+}
+
+impl Display for BlockedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Request to {} was blocked by a filter", self.url)
+    }
+}
+
+impl Error for BlockedError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterStats {
+    /// Requests checked while filtering was enabled
+    pub checked: usize,
+    pub blocked: usize,
+}
+
+// Destinations a rule applies to
+#[derive(Debug, Clone, Copy, Default)]
+struct Targets {
+    document: bool,
+    subresource: bool,
+}
+
+impl Targets {
+    fn includes(self, destination: Destination) -> bool {
+        match destination {
+            Destination::Document => self.document,
+            Destination::Subresource => self.subresource,
+        }
+    }
+
+    fn merge(&mut self, other: Targets) {
+        self.document |= other.document;
+        self.subresource |= other.subresource;
+    }
+}
+
+#[derive(Default)]
+struct Rules {
+    // `||host^` rules, matching the host and its subdomains
+    hosts: HashMap<String, Targets>,
+    patterns: Vec<(Regex, Targets)>,
+}
+
+impl Rules {
+    fn matches(&self, url: &str, host: &str, destination: Destination) -> bool {
+        let mut domain = host;
+        loop {
+            if let Some(targets) = self.hosts.get(domain) {
+                if targets.includes(destination) {
+                    return true;
+                }
+            }
+
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => break,
+            }
+        }
+
+        self.patterns
+            .iter()
+            .any(|(regex, targets)| targets.includes(destination) && regex.is_match(url))
+    }
+
+    fn add(&mut self, pattern: Pattern, targets: Targets) {
+        match pattern {
+            Pattern::Host(host) => self.hosts.entry(host).or_default().merge(targets),
+            Pattern::Regex(regex) => self.patterns.push((regex, targets)),
+        }
+    }
+}
+
+enum Pattern {
+    Host(String),
+    Regex(Regex),
+}
+
+#[derive(Default)]
+struct Filters {
+    blocking: Rules,
+    exceptions: Rules,
+}
+
+/// Rules requests are checked against before they are sent, shared by the requests of a
+/// session. Filtering can be turned off without dropping the rules.
+pub struct RequestFilter {
+    filters: RwLock<Filters>,
+    enabled: AtomicBool,
+    stats: Mutex<FilterStats>,
+}
+
+impl Default for RequestFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestFilter {
+    pub fn new() -> Self {
+        Self {
+            filters: RwLock::new(Filters::default()),
+            enabled: AtomicBool::new(true),
+            stats: Mutex::new(FilterStats::default()),
+        }
+    }
+
+    /// Adds the rules of `list`, one per line, and returns how many were used. Comments,
+    /// element hiding rules and rules with options other than resource types, `document`
+    /// and `match-case` are skipped.
+    pub fn add_rules(&self, list: &str) -> usize {
+        let mut filters = self.filters.write().unwrap();
+        let mut added = 0;
+
+        for line in list.lines() {
+            let Some((pattern, targets, exception)) = parse_rule(line) else {
+                continue;
+            };
+
+            match exception {
+                true => filters.exceptions.add(pattern, targets),
+                false => filters.blocking.add(pattern, targets),
+            }
+            added += 1;
+        }
+
+        added
+    }
+
+    pub fn clear(&self) {
+        *self.filters.write().unwrap() = Filters::default();
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Whether a blocking rule matches `url` and no exception does, enabled or not.
+    pub fn blocks(&self, url: &str, destination: Destination) -> bool {
+        let host = URLRecord::parse(url, None)
+            .map(|record| record.host_str().to_ascii_lowercase())
+            .unwrap_or_default();
+        let filters = self.filters.read().unwrap();
+
+        filters.blocking.matches(url, &host, destination)
+            && !filters.exceptions.matches(url, &host, destination)
+    }
+
+    /// Checks a request about to be sent and counts it, `true` when it is to be refused.
+    pub fn check(&self, url: &str, destination: Destination) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+
+        let blocked = self.blocks(url, destination);
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.checked += 1;
+        if blocked {
+            stats.blocked += 1;
+        }
+
+        blocked
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        *self.stats.lock().unwrap()
+    }
+}
+
+// The pattern of a rule, what it applies to and whether it is an exception
+fn parse_rule(line: &str) -> Option<(Pattern, Targets, bool)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with(['!', '[', '#']) {
+        return None;
+    }
+
+    // Adblock rules have no spaces
+    if line.contains(char::is_whitespace) {
+        let targets = Targets {
+            document: true,
+            subresource: true,
+        };
+        return Some((Pattern::Host(hosts_file_entry(line)?), targets, false));
+    }
+
+    // Element hiding
+    if ["##", "#@#", "#?#", "#$#"]
+        .iter()
+        .any(|marker| line.contains(marker))
+    {
+        return None;
+    }
+
+    let (rule, exception) = match line.strip_prefix("@@") {
+        Some(rule) => (rule, true),
+        None => (line, false),
+    };
+
+    let (pattern, options) = match rule.rsplit_once('$') {
+        Some((pattern, options)) if !is_regex(rule) || pattern.ends_with('/') => {
+            (pattern, Some(options))
+        }
+        _ => (rule, None),
+    };
+
+    let mut targets = Targets {
+        document: false,
+        subresource: true,
+    };
+    let mut match_case = false;
+
+    for option in options.into_iter().flat_map(|options| options.split(',')) {
+        match option.trim().to_ascii_lowercase().as_str() {
+            "document" | "doc" => {
+                targets = Targets {
+                    document: true,
+                    subresource: false,
+                }
+            }
+            "match-case" => match_case = true,
+            option if SUBRESOURCE_TYPES.contains(&option) => {}
+            _ => return None,
+        }
+    }
+
+    if pattern.is_empty() {
+        return None;
+    }
+
+    if let Some(host) = pattern
+        .strip_prefix("||")
+        .and_then(|rest| rest.strip_suffix('^'))
+        .filter(|host| is_host(host))
+    {
+        return Some((Pattern::Host(host.to_ascii_lowercase()), targets, exception));
+    }
+
+    let source = match is_regex(pattern) {
+        true => pattern[1..pattern.len() - 1].to_string(),
+        false => to_regex(pattern),
+    };
+    let regex = RegexBuilder::new(&source)
+        .case_insensitive(!match_case)
+        .build()
+        .ok()?;
+
+    Some((Pattern::Regex(regex), targets, exception))
+}
+
+// `<address> <host>` lines of a hosts file
+fn hosts_file_entry(line: &str) -> Option<String> {
+    let line = line.split('#').next()?;
+    let mut fields = line.split_whitespace();
+    let address = fields.next()?;
+    let host = fields.next()?.to_ascii_lowercase();
+
+    let blackhole = ["0.0.0.0", "127.0.0.1", "::", "::1"].contains(&address);
+    (blackhole && is_host(&host) && !LOCAL_HOSTS.contains(&host.as_str())).then_some(host)
+}
+
+fn is_regex(pattern: &str) -> bool {
+    pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/')
+}
+
+fn is_host(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '.' || char == '-')
+}
+
+// Regular expression for a pattern with adblock wildcards and anchors
+fn to_regex(pattern: &str) -> String {
+    let mut regex = String::new();
+    let mut rest = pattern;
+
+    if let Some(after) = rest.strip_prefix("||") {
+        // The scheme and any subdomains of the host
+        regex.push_str(r"^[a-z][a-z0-9+.\-]*://(?:[^/?#]*@)?(?:[^/?#]*\.)?");
+        rest = after;
+    } else if let Some(after) = rest.strip_prefix('|') {
+        regex.push('^');
+        rest = after;
+    }
+
+    let anchored_end = rest.ends_with('|');
+    if anchored_end {
+        rest = &rest[..rest.len() - 1];
+    }
+
+    for char in rest.chars() {
+        match char {
+            '*' => regex.push_str(".*"),
+            // Anything but a letter, digit or one of _-.% or the end of the URL
+            '^' => regex.push_str(r"(?:[^\w\-.%]|$)"),
+            char => regex.push_str(&regex::escape(&char.to_string())),
+        }
+    }
+
+    if anchored_end {
+        regex.push('$');
+    }
+
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = "[Adblock Plus 2.0]
+! Comment
+||ads.example.com^
+||tracker.net^$script,image
+|https://cdn.example.org/banner
+/ad-frame/*.html|
+&ad_id=
+/\\/pixel\\d+\\.gif/
+@@||ads.example.com/allowed^
+||paywall.example^$document
+example.com##.ad
+||social.example^$third-party
+0.0.0.0 malware.example
+127.0.0.1 localhost
+";
+
+    fn filter() -> RequestFilter {
+        let filter = RequestFilter::new();
+        assert_eq!(filter.add_rules(LIST), 9);
+        filter
+    }
+
+    #[test]
+    fn test_blocks() {
+        let filter = filter();
+        let blocked = |url: &str| filter.blocks(url, Destination::Subresource);
+
+        assert!(blocked("https://ads.example.com/banner.png"));
+        assert!(blocked("http://x.ads.example.com:8080/"));
+        assert!(!blocked("https://badads.example.com/"));
+        assert!(!blocked("https://ads.example.com.evil/"));
+        assert!(blocked("https://tracker.net/t.js"));
+        assert!(blocked("https://cdn.example.org/banner/1.jpg"));
+        assert!(!blocked("http://cdn.example.org/banner"));
+        assert!(blocked("https://example.net/ad-frame/x/y.html"));
+        assert!(!blocked("https://example.net/ad-frame/x.html?q"));
+        assert!(blocked("https://example.net/?a=1&AD_ID=2"));
+        assert!(blocked("https://example.net/img/pixel42.gif"));
+        assert!(blocked("http://malware.example/"));
+        assert!(!blocked("http://social.example/"));
+        assert!(!blocked("http://localhost/"));
+
+        // Exception
+        assert!(!blocked("https://ads.example.com/allowed/x.png"));
+        assert!(!blocked("https://ads.example.com/allowed"));
+    }
+
+    #[test]
+    fn test_destinations() {
+        let filter = filter();
+
+        assert!(!filter.blocks("https://ads.example.com/", Destination::Document));
+        assert!(filter.blocks("https://paywall.example/", Destination::Document));
+        assert!(!filter.blocks("https://paywall.example/a.js", Destination::Subresource));
+        assert!(filter.blocks("http://malware.example/", Destination::Document));
+    }
+
+    #[test]
+    fn test_check_counts() {
+        let filter = filter();
+
+        assert!(filter.check("https://ads.example.com/", Destination::Subresource));
+        assert!(!filter.check("https://example.com/", Destination::Subresource));
+
+        filter.set_enabled(false);
+        assert!(!filter.check("https://ads.example.com/", Destination::Subresource));
+
+        assert_eq!(
+            filter.stats(),
+            FilterStats {
+                checked: 2,
+                blocked: 1
+            }
+        );
+
+        filter.set_enabled(true);
+        filter.clear();
+        assert!(!filter.check("https://ads.example.com/", Destination::Subresource));
+    }
+
+    #[test]
+    fn test_separator() {
+        let regex = Regex::new(&to_regex("||example.com^")).unwrap();
+
+        assert!(regex.is_match("https://example.com"));
+        assert!(regex.is_match("https://example.com:8000/"));
+        assert!(!regex.is_match("https://example.community/"));
+    }
+}
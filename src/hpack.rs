@@ -0,0 +1,624 @@
+use std::{collections::HashMap, collections::VecDeque, error::Error, sync::OnceLock};
+
+type Err = Box<dyn Error>;
+
+const DEFAULT_TABLE_SIZE: usize = 4096;
+
+// Every dynamic table entry accounts for its name, value and 32 bytes of overhead (RFC 7541 4.1)
+const ENTRY_OVERHEAD: usize = 32;
+
+static STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// Huffman code (value, bit length) for every symbol, the last one being EOS (RFC 7541 Appendix B)
+static HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+fn huffman_lookup() -> &'static HashMap<(u8, u32), u16> {
+    static LOOKUP: OnceLock<HashMap<(u8, u32), u16>> = OnceLock::new();
+
+    LOOKUP.get_or_init(|| {
+        HUFFMAN_CODES
+            .iter()
+            .enumerate()
+            .map(|(symbol, (code, length))| ((*length, *code), symbol as u16))
+            .collect()
+    })
+}
+
+fn huffman_decode(input: &[u8]) -> Result<Vec<u8>, Err> {
+    let lookup = huffman_lookup();
+    let mut decoded = Vec::new();
+    let mut code = 0u32;
+    let mut length = 0u8;
+
+    for byte in input {
+        for shift in (0..8).rev() {
+            code = (code << 1) | ((*byte >> shift) & 1) as u32;
+            length += 1;
+
+            if let Some(symbol) = lookup.get(&(length, code)) {
+                if *symbol == 256 {
+                    return Err("Huffman string contains EOS".into());
+                }
+                decoded.push(*symbol as u8);
+                code = 0;
+                length = 0;
+            } else if length > 30 {
+                return Err("Invalid Huffman code".into());
+            }
+        }
+    }
+
+    // Leftover bits must be a prefix of EOS (all ones) shorter than a byte
+    if length > 7 || code != (1 << length) - 1 {
+        return Err("Invalid Huffman padding".into());
+    }
+
+    Ok(decoded)
+}
+
+fn encode_integer(buffer: &mut Vec<u8>, mut value: usize, prefix_bits: u8, first_byte: u8) {
+    let max_prefix = (1usize << prefix_bits) - 1;
+
+    if value < max_prefix {
+        buffer.push(first_byte | value as u8);
+        return;
+    }
+
+    buffer.push(first_byte | max_prefix as u8);
+    value -= max_prefix;
+    while value >= 128 {
+        buffer.push((value % 128 + 128) as u8);
+        value /= 128;
+    }
+    buffer.push(value as u8);
+}
+
+fn encode_string(buffer: &mut Vec<u8>, value: &str) {
+    encode_integer(buffer, value.len(), 7, 0);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+/// Appends a header as a "literal without indexing" representation with a literal name,
+/// which keeps the encoder stateless at the cost of a few bytes per header.
+pub fn encode(buffer: &mut Vec<u8>, name: &str, value: &str) {
+    buffer.push(0);
+    encode_string(buffer, name);
+    encode_string(buffer, value);
+}
+
+pub struct Decoder {
+    dynamic_table: VecDeque<(String, String)>,
+    table_size: usize,
+    max_table_size: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            dynamic_table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, Err> {
+        let mut headers = Vec::new();
+        let mut position = 0;
+
+        while position < block.len() {
+            let byte = block[position];
+
+            if byte & 0x80 != 0 {
+                // Indexed header field
+                let index = Self::read_integer(block, &mut position, 7)?;
+                headers.push(self.entry(index)?);
+            } else if byte & 0x40 != 0 {
+                // Literal header field with incremental indexing
+                let header = self.read_literal(block, &mut position, 6)?;
+                self.insert(header.clone());
+                headers.push(header);
+            } else if byte & 0x20 != 0 {
+                // Dynamic table size update
+                let size = Self::read_integer(block, &mut position, 5)?;
+                if size > DEFAULT_TABLE_SIZE {
+                    return Err("HPACK table size update above the advertised limit".into());
+                }
+                self.max_table_size = size;
+                self.evict();
+            } else {
+                // Literal header field without indexing / never indexed
+                headers.push(self.read_literal(block, &mut position, 4)?);
+            }
+        }
+
+        Ok(headers)
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), Err> {
+        if index == 0 {
+            return Err("HPACK index 0 is not valid".into());
+        }
+
+        if index <= STATIC_TABLE.len() {
+            let (name, value) = STATIC_TABLE[index - 1];
+            return Ok((name.to_string(), value.to_string()));
+        }
+
+        self.dynamic_table
+            .get(index - STATIC_TABLE.len() - 1)
+            .cloned()
+            .ok_or_else(|| "HPACK index out of range".into())
+    }
+
+    fn insert(&mut self, header: (String, String)) {
+        self.table_size += header.0.len() + header.1.len() + ENTRY_OVERHEAD;
+        self.dynamic_table.push_front(header);
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.table_size > self.max_table_size {
+            match self.dynamic_table.pop_back() {
+                Some((name, value)) => self.table_size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+
+    fn read_literal(
+        &self,
+        block: &[u8],
+        position: &mut usize,
+        prefix_bits: u8,
+    ) -> Result<(String, String), Err> {
+        let index = Self::read_integer(block, position, prefix_bits)?;
+
+        let name = if index == 0 {
+            Self::read_string(block, position)?
+        } else {
+            self.entry(index)?.0
+        };
+        let value = Self::read_string(block, position)?;
+
+        Ok((name, value))
+    }
+
+    fn read_integer(block: &[u8], position: &mut usize, prefix_bits: u8) -> Result<usize, Err> {
+        let max_prefix = (1usize << prefix_bits) - 1;
+        let mut value = (block[*position] as usize) & max_prefix;
+        *position += 1;
+
+        if value < max_prefix {
+            return Ok(value);
+        }
+
+        let mut shift = 0;
+        loop {
+            let byte = *block.get(*position).ok_or("Truncated HPACK integer")?;
+            *position += 1;
+
+            if shift > 28 {
+                return Err("HPACK integer overflow".into());
+            }
+            value += ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn read_string(block: &[u8], position: &mut usize) -> Result<String, Err> {
+        let first = *block.get(*position).ok_or("Truncated HPACK string")?;
+        let length = Self::read_integer(block, position, 7)?;
+        let raw = block
+            .get(*position..*position + length)
+            .ok_or("Truncated HPACK string")?;
+        *position += length;
+
+        let bytes = if first & 0x80 != 0 {
+            huffman_decode(raw)?
+        } else {
+            raw.to_vec()
+        };
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(input: &str) -> Vec<u8> {
+        (0..input.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&input[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_huffman_requests() {
+        let mut decoder = Decoder::new();
+
+        let headers = decoder
+            .decode(&hex("828684418cf1e3c2e5f23a6ba0ab90f4ff"))
+            .unwrap();
+
+        assert_eq!(headers[0], (":method".to_string(), "GET".to_string()));
+        assert_eq!(
+            headers[3],
+            (":authority".to_string(), "www.example.com".to_string())
+        );
+
+        // Second request references the dynamic table entry added by the first one
+        let headers = decoder.decode(&hex("828684be5886a8eb10649cbf")).unwrap();
+
+        assert_eq!(
+            headers[3],
+            (":authority".to_string(), "www.example.com".to_string())
+        );
+        assert_eq!(
+            headers[4],
+            ("cache-control".to_string(), "no-cache".to_string())
+        );
+    }
+
+    #[test]
+    fn test_decode_huffman_response() {
+        let mut decoder = Decoder::new();
+        let headers = decoder
+            .decode(&hex("488264026e919d29ad171863c78f0b97c8e9ae82ae43d3"))
+            .unwrap();
+
+        assert_eq!(headers[0], (":status".to_string(), "302".to_string()));
+        assert_eq!(
+            headers[1],
+            (
+                "location".to_string(),
+                "https://www.example.com".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let mut block = Vec::new();
+        encode(&mut block, ":path", "/examples/example1-simple.html");
+        encode(&mut block, "user-agent", &"x".repeat(300));
+
+        let headers = Decoder::new().decode(&block).unwrap();
+
+        assert_eq!(headers[0].1, "/examples/example1-simple.html");
+        assert_eq!(headers[1].1.len(), 300);
+    }
+
+    #[test]
+    fn test_encode_integer() {
+        let mut buffer = Vec::new();
+        encode_integer(&mut buffer, 1337, 5, 0);
+
+        assert_eq!(buffer, vec![0x1f, 0x9a, 0x0a]);
+    }
+}
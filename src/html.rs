@@ -1,276 +1,3017 @@
-use std::{collections::HashMap, iter::Peekable, str::Chars};
-
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    str::FromStr,
+};
+
+use encoding_rs::WINDOWS_1252;
 use regex::Regex;
 
-type Attrs = HashMap<String, String>;
+use crate::css::Stylesheet;
+use crate::entities::{ENTITIES, LONGEST_NAME};
+use crate::selector::{attribute, SelectorList};
 
-static SELF_CLOSING_TAGS: [&'static str; 5] = ["meta", "link", "input", "img", "br"];
+type Attrs = HashMap<String, String>;
 
-#[derive(Debug)]
+// Elements of the HTML namespace, obsolete ones included. A trailing `/>` doesn't close them
+static HTML_ELEMENTS: [&str; 137] = [
+    "a",
+    "abbr",
+    "acronym",
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "audio",
+    "b",
+    "base",
+    "basefont",
+    "bdi",
+    "bdo",
+    "bgsound",
+    "big",
+    "blink",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "canvas",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "data",
+    "datalist",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "font",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "i",
+    "iframe",
+    "image",
+    "img",
+    "input",
+    "ins",
+    "kbd",
+    "keygen",
+    "label",
+    "legend",
+    "li",
+    "link",
+    "listing",
+    "main",
+    "map",
+    "mark",
+    "marquee",
+    "menu",
+    "meta",
+    "meter",
+    "nav",
+    "nobr",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "optgroup",
+    "option",
+    "output",
+    "p",
+    "param",
+    "picture",
+    "plaintext",
+    "pre",
+    "progress",
+    "q",
+    "rb",
+    "rp",
+    "rt",
+    "rtc",
+    "ruby",
+    "s",
+    "samp",
+    "script",
+    "search",
+    "section",
+    "select",
+    "slot",
+    "small",
+    "source",
+    "span",
+    "strike",
+    "strong",
+    "style",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "title",
+    "tr",
+    "track",
+    "tt",
+    "u",
+    "ul",
+    "var",
+    "video",
+    "wbr",
+    "xmp",
+];
+
+// Elements without content or an end tag
+pub(crate) static VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr", "param",
+];
+
+// Laid out as blocks unless their `display` says otherwise, other elements are inline
+pub(crate) static BLOCK_ELEMENTS: [&str; 40] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "legend",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+// Never rendered, whatever their `display`
+pub(crate) static HIDDEN_ELEMENTS: [&str; 8] = [
+    "base", "head", "link", "meta", "script", "style", "template", "title",
+];
+
+// Their text is never markup, character references included
+static RAW_TEXT_TAGS: [&str; 2] = ["script", "style"];
+
+// Elements that belong in the head when `ParserOptions::document` fills in a missing one
+static HEAD_TAGS: [&str; 10] = [
+    "base", "basefont", "bgsound", "link", "meta", "noscript", "script", "style", "template",
+    "title",
+];
+
+// Start tags that end an open paragraph
+static CLOSES_PARAGRAPH: [&str; 31] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+static HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+// Implied end tags don't reach past these (the spec's "button scope")
+static SCOPE_BOUNDARIES: [&str; 10] = [
+    "applet", "button", "caption", "html", "marquee", "object", "table", "td", "th", "template",
+];
+
+#[derive(Debug, Clone)]
 pub struct NodeData {
     pub tag_name: String,
     pub attributes: Attrs,
+    /// Set on `<noscript>` and its content when parsed with `NoscriptMode::Flag`
+    pub in_noscript: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Node {
     pub children: Vec<Node>,
     pub data: NodeData,
+    /// Attached from a declarative `<template shadowrootmode="...">` child
+    pub shadow_root: Option<ShadowRoot>,
+    /// Where the node is in the markup, with `ParserOptions::source_positions`
+    pub position: Option<Box<SourcePosition>>,
+}
+
+/// A piece of the markup: byte offsets into it (as UTF-8) and the line and column, counted
+/// in characters from 1, where it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where a node came from in the markup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePosition {
+    /// The start tag of an element, all of a text run, comment or doctype
+    pub start: Span,
+    /// The end tag of an element, missing when the markup leaves it implied
+    pub end: Option<Span>,
+}
+
+/// What a node stands for, told apart by its tag name: "text", "#comment", "#doctype" and
+/// "#document" are reserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeType {
+    Element,
+    /// Its content is kept in the `content` attribute
+    Text,
+    /// Only kept with `ParserOptions::comments`, its content is in the `content` attribute
+    Comment,
+    /// Only kept with `ParserOptions::comments`, with `name`, `public_id` and `system_id`
+    /// attributes
+    Doctype,
+    /// What documents parsed with `ParserOptions::comments` come back as
+    Document,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShadowRoot {
+    /// "open" or "closed"
+    pub mode: String,
+    pub children: Vec<Node>,
 }
 
 impl Node {
-    fn new(node_data: NodeData, children: Vec<Node>) -> Self {
+    pub fn new(node_data: NodeData, children: Vec<Node>) -> Self {
         Self {
             data: node_data,
             children,
+            shadow_root: None,
+            position: None,
+        }
+    }
+
+    /// A "text" node, its content is kept in the `content` attribute.
+    pub fn text(content: String) -> Self {
+        Self::new(
+            NodeData {
+                tag_name: "text".to_string(),
+                attributes: HashMap::from([("content".to_string(), content)]),
+                in_noscript: false,
+            },
+            Vec::new(),
+        )
+    }
+
+    fn comment(content: String) -> Self {
+        let mut comment = Self::text(content);
+        comment.data.tag_name = "#comment".to_string();
+
+        comment
+    }
+
+    pub fn node_type(&self) -> NodeType {
+        match self.data.tag_name.as_str() {
+            "text" => NodeType::Text,
+            "#comment" => NodeType::Comment,
+            "#doctype" => NodeType::Doctype,
+            "#document" => NodeType::Document,
+            _ => NodeType::Element,
+        }
+    }
+
+    /// Collects all descendant text nodes, optionally descending into shadow trees
+    /// (shadow content comes before the host's light children).
+    pub fn text_nodes(&self, include_shadow: bool) -> Vec<&Node> {
+        let mut collected_nodes = Vec::new();
+
+        self.collect_nodes("text", include_shadow, &mut collected_nodes);
+
+        collected_nodes
+    }
+
+    fn collect_nodes<'a>(
+        &'a self,
+        node_type: &str,
+        include_shadow: bool,
+        collected_nodes: &mut Vec<&'a Node>,
+    ) {
+        let shadow_children = match (&self.shadow_root, include_shadow) {
+            (Some(shadow_root), true) => shadow_root.children.as_slice(),
+            _ => &[],
+        };
+
+        for child in shadow_children.iter().chain(&self.children) {
+            if child.data.tag_name == node_type {
+                collected_nodes.push(child);
+            } else {
+                child.collect_nodes(node_type, include_shadow, collected_nodes);
+            }
+        }
+    }
+
+    fn shadow_root_mode(&self) -> Option<&String> {
+        if self.data.tag_name != "template" {
+            return None;
+        }
+
+        self.data
+            .attributes
+            .get("shadowrootmode")
+            .filter(|mode| *mode == "open" || *mode == "closed")
+    }
+
+    #[deprecated]
+    #[allow(deprecated)]
+    pub fn find_text_nodes(&self) -> Vec<&Node> {
+        let mut collected_nodes = Vec::new();
+
+        self.find_nodes("text", &mut collected_nodes);
+
+        collected_nodes
+    }
+
+    #[deprecated]
+    pub fn find_nodes<'a>(&'a self, node_type: &str, collected_nodes: &mut Vec<&'a Node>) {
+        for child in &self.children {
+            if child.data.tag_name == node_type {
+                collected_nodes.push(child);
+            } else {
+                child.find_nodes(node_type, collected_nodes);
+            }
+        }
+    }
+
+    /// Finds every occurrence of `pattern` in the text nodes below this node, reporting
+    /// byte offsets within each text node and the tag names of its ancestors.
+    pub fn search(&self, pattern: &SearchPattern) -> Vec<SearchMatch<'_>> {
+        let mut matches = Vec::new();
+        let mut path = vec![self.data.tag_name.clone()];
+
+        self.collect_matches(pattern, &mut path, &mut matches);
+
+        matches
+    }
+
+    fn collect_matches<'a>(
+        &'a self,
+        pattern: &SearchPattern,
+        path: &mut Vec<String>,
+        matches: &mut Vec<SearchMatch<'a>>,
+    ) {
+        for child in &self.children {
+            if child.data.tag_name != "text" {
+                path.push(child.data.tag_name.clone());
+                child.collect_matches(pattern, path, matches);
+                path.pop();
+                continue;
+            }
+
+            let Some(content) = child.data.attributes.get("content") else {
+                continue;
+            };
+
+            for (start, end) in pattern.find_all(content) {
+                matches.push(SearchMatch {
+                    node: child,
+                    start,
+                    end,
+                    path: path.clone(),
+                });
+            }
+        }
+    }
+
+    /// First descendant element whose id is `id`. Shadow trees are left out, like for
+    /// selectors.
+    pub fn get_element_by_id(&self, id: &str) -> Option<&Node> {
+        let mut found = Vec::new();
+
+        self.collect_elements(
+            &mut |node| attribute(node, "id").is_some_and(|value| value == id),
+            true,
+            &mut found,
+        );
+
+        found.pop()
+    }
+
+    /// Descendant elements having all the space-separated `class_names`, in document order.
+    pub fn get_elements_by_class_name(&self, class_names: &str) -> Vec<&Node> {
+        let wanted: Vec<&str> = class_names.split_ascii_whitespace().collect();
+        if wanted.is_empty() {
+            return Vec::new();
+        }
+
+        self.find_elements(|node| {
+            let classes = attribute(node, "class").map_or("", String::as_str);
+            wanted
+                .iter()
+                .all(|name| classes.split_ascii_whitespace().any(|class| class == *name))
+        })
+    }
+
+    /// Descendant elements named `tag_name` (in any case), all of them for `*`.
+    pub fn get_elements_by_tag_name(&self, tag_name: &str) -> Vec<&Node> {
+        self.find_elements(|node| {
+            tag_name == "*" || node.data.tag_name.eq_ignore_ascii_case(tag_name)
+        })
+    }
+
+    /// Descendant elements with the attribute `name` (in any case), set to `value` when one
+    /// is given.
+    pub fn find(&self, name: &str, value: Option<&str>) -> Vec<&Node> {
+        self.find_elements(|node| match attribute(node, name) {
+            Some(attribute) => value.is_none_or(|value| attribute == value),
+            None => false,
+        })
+    }
+
+    /// Byte range of the whole node in the markup, from its start tag to its end tag (or to
+    /// the end of its content when that is implied). Only with `ParserOptions::source_positions`.
+    pub fn source_span(&self) -> Option<(usize, usize)> {
+        let position = self.position.as_ref()?;
+        if let Some(end) = position.end {
+            return Some((position.start.start, end.end));
+        }
+
+        let shadow_children = self.shadow_root.iter().flat_map(|root| &root.children);
+        let end = self
+            .children
+            .iter()
+            .chain(shadow_children)
+            .filter_map(|child| child.source_span())
+            .map(|(_, end)| end)
+            .fold(position.start.end, usize::max);
+
+        Some((position.start.start, end))
+    }
+
+    fn find_elements(&self, mut matches: impl FnMut(&Node) -> bool) -> Vec<&Node> {
+        let mut found = Vec::new();
+
+        self.collect_elements(&mut matches, false, &mut found);
+
+        found
+    }
+
+    // Walks the tree once collecting the elements `matches` holds for, stops at the first one
+    // with `first`. Returns whether to stop.
+    fn collect_elements<'a>(
+        &'a self,
+        matches: &mut dyn FnMut(&Node) -> bool,
+        first: bool,
+        found: &mut Vec<&'a Node>,
+    ) -> bool {
+        for child in &self.children {
+            if child.node_type() != NodeType::Element {
+                continue;
+            }
+
+            if matches(child) {
+                found.push(child);
+                if first {
+                    return true;
+                }
+            }
+
+            if child.collect_elements(matches, first, found) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// First descendant element matching the CSS `selectors`.
+    pub fn query_selector(&self, selectors: &str) -> Result<Option<&Node>, Box<dyn Error>> {
+        let selectors = SelectorList::parse(selectors)?;
+
+        Ok(selectors.select(self, true).into_iter().next())
+    }
+
+    /// Every descendant element matching the CSS `selectors`, in document order.
+    pub fn query_selector_all(&self, selectors: &str) -> Result<Vec<&Node>, Box<dyn Error>> {
+        Ok(SelectorList::parse(selectors)?.select(self, false))
+    }
+
+    /// Style sheets of the `<style>` elements below this node, in document order. The ones
+    /// in shadow trees only apply there and are left out.
+    pub fn stylesheets(&self) -> Vec<Stylesheet> {
+        let mut styles = Vec::new();
+
+        self.collect_nodes("style", false, &mut styles);
+
+        styles
+            .iter()
+            .map(|style| {
+                let text: String = style
+                    .children
+                    .iter()
+                    .filter_map(|child| child.data.attributes.get("content"))
+                    .map(String::as_str)
+                    .collect();
+
+                Stylesheet::parse(&text)
+            })
+            .collect()
+    }
+
+    pub fn attr(&self, name: &str) -> &String {
+        self.data.attributes.get(name).unwrap()
+    }
+
+    fn flag_noscript(&mut self) {
+        self.data.in_noscript = true;
+
+        for child in &mut self.children {
+            child.flag_noscript();
+        }
+    }
+
+    /// Position of `descendant` (this very node, not an equal one) in this tree, from
+    /// there its parent, siblings and ancestors can be reached.
+    pub fn locate(&self, descendant: &Node) -> Option<NodeRef<'_>> {
+        NodeRef::locate(self, &[descendant]).pop()
+    }
+
+    /// The node `path` leads to from this one.
+    pub fn descendant(&self, path: &[Step]) -> Option<&Node> {
+        path.iter().try_fold(self, |node, step| match *step {
+            Step::Child(index) => node.children.get(index),
+            Step::Shadow(index) => node.shadow_root.as_ref()?.children.get(index),
+        })
+    }
+
+    /// Serializes this node and its descendants as HTML. A shadow root is written back as
+    /// the declarative `<template shadowrootmode="...">` it was parsed from.
+    pub fn to_html(&self) -> String {
+        let mut output = String::new();
+
+        self.write_html(&mut output);
+
+        output
+    }
+
+    /// Serializes the content of this node, without its own tags.
+    pub fn inner_html(&self) -> String {
+        let mut output = String::new();
+
+        self.write_content(&mut output);
+
+        output
+    }
+
+    /// Readable text of this node, close to what `innerText` gives: runs of whitespace are
+    /// collapsed, blocks start on new lines (paragraphs get a blank line around them) and
+    /// what is never rendered, like scripts, styles and the head, is left out.
+    pub fn inner_text(&self) -> String {
+        let mut writer = TextWriter::default();
+
+        self.write_text(&mut writer, false);
+
+        writer.text
+    }
+
+    // https://html.spec.whatwg.org/multipage/dom.html#rendered-text-collection-steps
+    fn write_text(&self, writer: &mut TextWriter, preformatted: bool) {
+        let tag_name = self.data.tag_name.to_lowercase();
+
+        match self.node_type() {
+            NodeType::Element | NodeType::Document => {}
+            NodeType::Text => {
+                let content = self.attr("content");
+
+                match preformatted {
+                    true => writer.raw(content),
+                    false => writer.collapse(content),
+                }
+                return;
+            }
+            NodeType::Comment | NodeType::Doctype => return,
+        }
+
+        if HIDDEN_ELEMENTS.contains(&tag_name.as_str()) || self.data.in_noscript {
+            return;
+        }
+
+        let breaks = match tag_name.as_str() {
+            "p" => 2,
+            "tr" => 1,
+            _ if BLOCK_ELEMENTS.contains(&tag_name.as_str()) => 1,
+            _ => 0,
+        };
+
+        match tag_name.as_str() {
+            "br" => writer.line_break(),
+            "td" | "th" => writer.space = true,
+            _ => writer.breaks = writer.breaks.max(breaks),
+        }
+
+        for child in &self.children {
+            child.write_text(writer, preformatted || tag_name == "pre");
+        }
+
+        writer.breaks = writer.breaks.max(breaks);
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+    fn write_html(&self, output: &mut String) {
+        let tag_name = &self.data.tag_name;
+
+        match self.node_type() {
+            NodeType::Element => {}
+            NodeType::Text => {
+                if let Some(content) = self.data.attributes.get("content") {
+                    escape_html(content, false, output);
+                }
+                return;
+            }
+            NodeType::Comment => {
+                output.push_str("<!--");
+                output.push_str(self.attr("content"));
+                output.push_str("-->");
+                return;
+            }
+            NodeType::Doctype => return self.write_doctype(output),
+            NodeType::Document => return self.write_content(output),
+        }
+
+        // Sorted so the output does not depend on the hashing order
+        let mut attributes: Vec<(&String, &String)> = self.data.attributes.iter().collect();
+        attributes.sort();
+
+        output.push('<');
+        output.push_str(tag_name);
+
+        for (name, value) in attributes {
+            output.push(' ');
+            output.push_str(name);
+            output.push_str("=\"");
+            escape_html(value, true, output);
+            output.push('"');
+        }
+
+        output.push('>');
+
+        if VOID_ELEMENTS.contains(&tag_name.to_lowercase().as_str()) {
+            return;
+        }
+
+        self.write_content(output);
+
+        output.push_str("</");
+        output.push_str(tag_name);
+        output.push('>');
+    }
+
+    fn write_doctype(&self, output: &mut String) {
+        let attributes = &self.data.attributes;
+
+        output.push_str("<!DOCTYPE ");
+        output.push_str(self.attr("name"));
+
+        match (attributes.get("public_id"), attributes.get("system_id")) {
+            (Some(public_id), system_id) => {
+                output.push_str(&format!(" PUBLIC \"{}\"", public_id));
+                if let Some(system_id) = system_id {
+                    output.push_str(&format!(" \"{}\"", system_id));
+                }
+            }
+            (None, Some(system_id)) => output.push_str(&format!(" SYSTEM \"{}\"", system_id)),
+            (None, None) => {}
+        }
+
+        output.push('>');
+    }
+
+    fn write_content(&self, output: &mut String) {
+        if let Some(shadow_root) = &self.shadow_root {
+            output.push_str("<template shadowrootmode=\"");
+            escape_html(&shadow_root.mode, true, output);
+            output.push_str("\">");
+
+            for child in &shadow_root.children {
+                child.write_html(output);
+            }
+
+            output.push_str("</template>");
+        }
+
+        // Raw text was never decoded, escaping it would change it
+        let raw_text = RAW_TEXT_TAGS.contains(&self.data.tag_name.to_lowercase().as_str());
+
+        for child in &self.children {
+            match child.data.attributes.get("content") {
+                Some(content) if raw_text && child.data.tag_name == "text" => {
+                    output.push_str(content)
+                }
+                _ => child.write_html(output),
+            }
+        }
+    }
+}
+
+// Collects the text of `Node::inner_text`, separators are only written once some text
+// follows them so there are none at either end
+#[derive(Default)]
+struct TextWriter {
+    text: String,
+    space: bool,
+    breaks: usize,
+}
+
+impl TextWriter {
+    fn collapse(&mut self, content: &str) {
+        for (index, word) in content.split_whitespace().enumerate() {
+            if index > 0 || content.starts_with(char::is_whitespace) {
+                self.space = true;
+            }
+            self.raw(word);
+        }
+
+        if content.ends_with(char::is_whitespace) {
+            self.space = true;
+        }
+    }
+
+    fn raw(&mut self, content: &str) {
+        if content.is_empty() {
+            return;
+        }
+
+        if !self.text.is_empty() {
+            match self.breaks {
+                0 if self.space => self.text.push(' '),
+                breaks => self.text.push_str(&"\n".repeat(breaks)),
+            }
+        }
+
+        self.text.push_str(content);
+        self.space = false;
+        self.breaks = 0;
+    }
+
+    // A `<br>`, unlike block boundaries these add up
+    fn line_break(&mut self) {
+        self.breaks += 1;
+    }
+}
+
+/// One level of the way down from a root to one of its descendants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Step {
+    /// Index in the parent's children
+    Child(usize),
+    /// Index in the children of the parent's shadow root
+    Shadow(usize),
+}
+
+/// A node and the way to it from the root of its tree. Nodes only own their children, this
+/// is what walking up and sideways goes through.
+#[derive(Debug, Clone)]
+pub struct NodeRef<'a> {
+    root: &'a Node,
+    path: Vec<Step>,
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn root(root: &'a Node) -> Self {
+        Self {
+            root,
+            path: Vec::new(),
+        }
+    }
+
+    /// `None` when `path` doesn't lead anywhere from `root`.
+    pub fn at(root: &'a Node, path: Vec<Step>) -> Option<Self> {
+        root.descendant(&path)?;
+
+        Some(Self { root, path })
+    }
+
+    /// Positions of `targets` (by identity) below `root`, in the order given. The ones that
+    /// are not part of the tree are left out.
+    pub fn locate(root: &'a Node, targets: &[&Node]) -> Vec<Self> {
+        let wanted: HashSet<*const Node> = targets.iter().map(|node| *node as *const _).collect();
+        let mut found = HashMap::new();
+
+        Self::walk(root, &mut Vec::new(), &mut |node, path| {
+            if wanted.contains(&(node as *const _)) {
+                found.insert(node as *const Node, path.to_vec());
+            }
+        });
+
+        targets
+            .iter()
+            .filter_map(|node| found.get(&(*node as *const _)))
+            .map(|path| Self {
+                root,
+                path: path.clone(),
+            })
+            .collect()
+    }
+
+    fn walk(node: &Node, path: &mut Vec<Step>, visit: &mut dyn FnMut(&Node, &[Step])) {
+        visit(node, path);
+
+        let shadow_children = node
+            .shadow_root
+            .iter()
+            .flat_map(|shadow_root| shadow_root.children.iter().enumerate())
+            .map(|(index, child)| (Step::Shadow(index), child));
+        let children = node
+            .children
+            .iter()
+            .enumerate()
+            .map(|(index, child)| (Step::Child(index), child));
+
+        for (step, child) in shadow_children.chain(children) {
+            path.push(step);
+            Self::walk(child, path, visit);
+            path.pop();
+        }
+    }
+
+    pub fn node(&self) -> &'a Node {
+        // Only paths that lead somewhere are ever built
+        self.root.descendant(&self.path).unwrap()
+    }
+
+    pub fn path(&self) -> &[Step] {
+        &self.path
+    }
+
+    /// The enclosing node, `None` for the root and the top level nodes of a shadow tree.
+    pub fn parent(&self) -> Option<Self> {
+        match self.path.last()? {
+            Step::Child(_) => Some(Self {
+                root: self.root,
+                path: self.path[..self.path.len() - 1].to_vec(),
+            }),
+            Step::Shadow(_) => None,
         }
     }
 
-    #[deprecated]
-    pub fn find_text_nodes<'a>(&'a self) -> Vec<&'a Node> {
-        let mut collected_nodes = Vec::new();
+    /// Closest first, up to the root (or the shadow root the node is in).
+    pub fn ancestors(&self) -> Vec<Self> {
+        std::iter::successors(self.parent(), Self::parent).collect()
+    }
+
+    pub fn next_sibling(&self) -> Option<Self> {
+        self.sibling(1)
+    }
+
+    pub fn previous_sibling(&self) -> Option<Self> {
+        self.sibling(-1)
+    }
+
+    fn sibling(&self, offset: isize) -> Option<Self> {
+        let (last, parent_path) = self.path.split_last()?;
+        let step = match *last {
+            Step::Child(index) => Step::Child(index.checked_add_signed(offset)?),
+            Step::Shadow(index) => Step::Shadow(index.checked_add_signed(offset)?),
+        };
+
+        Self::at(self.root, [parent_path, &[step]].concat())
+    }
+
+    // The finders of `Node`, with the matches located in the same tree so they can be walked
+    // from too. Locating them all takes a single walk below this node.
+
+    pub fn get_element_by_id(&self, id: &str) -> Option<Self> {
+        self.descendants(&Vec::from_iter(self.node().get_element_by_id(id)))
+            .pop()
+    }
+
+    pub fn get_elements_by_class_name(&self, class_names: &str) -> Vec<Self> {
+        self.descendants(&self.node().get_elements_by_class_name(class_names))
+    }
+
+    pub fn get_elements_by_tag_name(&self, tag_name: &str) -> Vec<Self> {
+        self.descendants(&self.node().get_elements_by_tag_name(tag_name))
+    }
+
+    pub fn find(&self, name: &str, value: Option<&str>) -> Vec<Self> {
+        self.descendants(&self.node().find(name, value))
+    }
+
+    pub fn query_selector(&self, selectors: &str) -> Result<Option<Self>, Box<dyn Error>> {
+        let found = self.node().query_selector(selectors)?;
+
+        Ok(self.descendants(&Vec::from_iter(found)).pop())
+    }
+
+    pub fn query_selector_all(&self, selectors: &str) -> Result<Vec<Self>, Box<dyn Error>> {
+        Ok(self.descendants(&self.node().query_selector_all(selectors)?))
+    }
+
+    // Positions of `nodes`, all of them somewhere below this node
+    fn descendants(&self, nodes: &[&Node]) -> Vec<Self> {
+        Self::locate(self.node(), nodes)
+            .into_iter()
+            .map(|found| Self {
+                root: self.root,
+                path: [self.path.as_slice(), &found.path].concat(),
+            })
+            .collect()
+    }
+}
+
+pub enum SearchPattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl SearchPattern {
+    pub fn literal(pattern: &str) -> Self {
+        Self::Literal(pattern.to_string())
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self::Regex(Regex::new(pattern)?))
+    }
+
+    // Non-overlapping (start, end) byte ranges, empty matches are skipped
+    fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        match self {
+            SearchPattern::Literal(pattern) if pattern.is_empty() => Vec::new(),
+            SearchPattern::Literal(pattern) => text
+                .match_indices(pattern.as_str())
+                .map(|(start, found)| (start, start + found.len()))
+                .collect(),
+            SearchPattern::Regex(regex) => regex
+                .find_iter(text)
+                .filter(|found| !found.is_empty())
+                .map(|found| (found.start(), found.end()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SearchMatch<'a> {
+    /// The text node the match was found in
+    pub node: &'a Node,
+    /// Byte offsets within the text node content
+    pub start: usize,
+    pub end: usize,
+    /// Tag names from the searched node down to the element containing the text
+    pub path: Vec<String>,
+}
+
+/// What to do with `<noscript>` content. Since we never run scripts, browsers with
+/// scripting disabled (which render it) are the closest match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoscriptMode {
+    /// Parse the content as regular elements
+    #[default]
+    Parse,
+    /// Parse the content but mark it with `in_noscript`
+    Flag,
+    /// Drop the element and its content from the tree
+    Skip,
+}
+
+impl FromStr for NoscriptMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "parse" => Ok(NoscriptMode::Parse),
+            "flag" => Ok(NoscriptMode::Flag),
+            "skip" => Ok(NoscriptMode::Skip),
+            _ => Err(format!("Unknown noscript mode: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    pub noscript: NoscriptMode,
+    /// Attach `<template shadowrootmode>` content as a shadow root on its parent
+    pub declarative_shadow_dom: bool,
+    /// Replace character references (`&amp;`, `&#160;`) in text and attribute values,
+    /// disabled the markup's raw text is kept
+    pub decode_entities: bool,
+    /// Parse a whole document: the html, head and body elements the markup leaves out are
+    /// inserted and `parse` returns the html element
+    pub document: bool,
+    /// Keep comments and the doctype as nodes. Documents then come back as the `#document`
+    /// node, to hold the doctype and comments around the html element.
+    pub comments: bool,
+    /// Tags to treat as void (no content, no end tag) on top of the HTML ones
+    pub void_elements: Vec<String>,
+    /// Record where each node is in the markup, see `Node::position`
+    pub source_positions: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            noscript: NoscriptMode::default(),
+            declarative_shadow_dom: true,
+            decode_entities: true,
+            document: false,
+            comments: false,
+            void_elements: Vec::new(),
+            source_positions: false,
+        }
+    }
+}
+
+pub struct HTMLParser<'a> {
+    chars: Input<'a>,
+    options: ParserOptions,
+    // Where the input starts in the whole markup
+    offset: usize,
+    locator: Locator,
+    spans: TokenSpans,
+    // End of the last tag read, before the whitespace after it
+    tag_end: usize,
+}
+
+/// Parses markup that arrives in pieces, like a body while it downloads: `feed` adds to the
+/// tree as much as the input so far allows and `finish` completes it. The tree is the one
+/// `HTMLParser` builds from the whole input.
+pub struct StreamingParser {
+    options: ParserOptions,
+    // Input that isn't in the tree yet, a token cut short at most
+    pending: String,
+    // Whether the leading whitespace, which isn't part of the markup, is behind us
+    started: bool,
+    stack: Vec<Node>,
+    phase: Phase,
+    // Bytes of the input already dropped from `pending`
+    consumed: usize,
+    locator: Locator,
+}
+
+// The source of the tokenizer, keeping track of how far it got
+struct Input<'a> {
+    source: &'a str,
+    position: usize,
+    peeked: Option<char>,
+}
+
+/// What `HTMLParser::events` reports, in source order. Names are lowercase.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseEvent<'a> {
+    StartTag {
+        name: String,
+        attributes: Attrs,
+        /// The tag ends with `/>`
+        self_closing: bool,
+    },
+    EndTag(String),
+    /// References decoded with `ParserOptions::decode_entities`
+    Text(Cow<'a, str>),
+    /// Only with `ParserOptions::comments`
+    Comment(&'a str),
+}
+
+// Where the parts of the last token are in the input, as byte ranges: the token itself (the
+// start tag of a raw text element) and the content and end tag of raw text elements
+#[derive(Debug, Default, Clone, Copy)]
+struct TokenSpans {
+    token: (usize, usize),
+    content: (usize, usize),
+    end_tag: Option<(usize, usize)>,
+}
+
+// Line and column of the markup at `offset`. The markup is only ever scanned forward, the
+// offsets asked for grow as the tokens go.
+#[derive(Debug, Clone, Copy)]
+struct Locator {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Default for Locator {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Locator {
+    // Moves past `text`, which starts at `offset`
+    fn advance(&mut self, text: &str) {
+        for char in text.chars() {
+            match char {
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                _ => self.column += 1,
+            }
+        }
+        self.offset += text.len();
+    }
+}
+
+// What the tokenizer hands to the tree construction, comments and doctypes only with
+// `ParserOptions::comments`. Text is borrowed from the input, it is only copied (and its
+// references decoded) once it goes into the tree.
+enum Token<'a> {
+    /// An element and whether its tag ends with `/>`
+    StartTag(NodeData, bool),
+    /// A script or style element with its content
+    RawText(NodeData, &'a str),
+    EndTag(&'a str),
+    Text(&'a str),
+    Comment(&'a str),
+    Doctype(NodeData),
+}
+
+// How far `ParserOptions::document` parsing got through the html/head/body skeleton
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+}
+
+impl<'a> HTMLParser<'a> {
+    // Pages are parsed as documents, the default options are left to the tests
+    #[cfg(test)]
+    pub fn new(source: &'a str) -> Self {
+        Self::with_options(source, ParserOptions::default())
+    }
+
+    pub fn with_options(source: &'a str, options: ParserOptions) -> Self {
+        let trimmed = source.trim();
+        let offset = source.len() - source.trim_start().len();
+
+        let mut locator = Locator::default();
+        if options.source_positions {
+            locator.advance(&source[..offset]);
+        }
+
+        Self {
+            chars: Input::new(trimmed),
+            options,
+            offset,
+            locator,
+            spans: TokenSpans::default(),
+            tag_end: 0,
+        }
+    }
+
+    // The span of the input from byte `start` to `end`
+    fn span(&mut self, (start, end): (usize, usize)) -> Span {
+        let scanned = self.locator.offset - self.offset;
+        if start > scanned {
+            self.locator.advance(&self.chars.source[scanned..start]);
+        }
+
+        Span {
+            start: self.offset + start,
+            end: self.offset + end,
+            line: self.locator.line,
+            column: self.locator.column,
+        }
+    }
+
+    // Where a node made of the input from `start` to `end` is, if positions are recorded
+    fn position(&mut self, range: (usize, usize)) -> Option<Box<SourcePosition>> {
+        self.options.source_positions.then(|| {
+            Box::new(SourcePosition {
+                start: self.span(range),
+                end: None,
+            })
+        })
+    }
+
+    // A node for `data` at the range of the current token
+    fn element(&mut self, data: NodeData) -> Node {
+        let mut node = Node::new(data, Vec::new());
+        node.position = self.position(self.spans.token);
+
+        node
+    }
+
+    // Records the current token as the end tag of `node`
+    fn end_element(&mut self, node: &mut Node) {
+        if node.position.is_some() {
+            let end = self.span(self.spans.token);
+            if let Some(position) = node.position.as_mut() {
+                position.end = Some(end);
+            }
+        }
+    }
+
+    /// Builds the tree on a stack of open elements, closing the ones the markup leaves open
+    /// where the HTML spec implies it (`<p>`, `<li>`, table cells, ...) and ignoring stray end
+    /// tags. Returns the first root element, the `html` one with `ParserOptions::document`
+    /// (or the `#document` node when comments are kept too).
+    pub fn parse(&mut self) -> Option<Node> {
+        let mut stack = Self::document_stack();
+        let mut phase = Phase::BeforeHtml;
+
+        self.build(&mut stack, &mut phase, true);
+
+        self.finish(stack, phase)
+    }
+
+    /// Goes through the markup handing its tags, text and comments to `on_event` without
+    /// building a tree: elements are neither implied nor closed, end tags are the ones
+    /// written (plus the end of script and style elements). Stops at the first error
+    /// `on_event` returns.
+    pub fn events<E>(
+        &mut self,
+        mut on_event: impl FnMut(ParseEvent<'a>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let start_tag = |data: NodeData, self_closing| ParseEvent::StartTag {
+            name: data.tag_name.to_ascii_lowercase(),
+            attributes: data.attributes,
+            self_closing,
+        };
+
+        while let Some(token) = self.next_token() {
+            match token {
+                Token::StartTag(data, self_closing) => on_event(start_tag(data, self_closing))?,
+                Token::RawText(data, content) => {
+                    let name = data.tag_name.to_ascii_lowercase();
+
+                    on_event(start_tag(data, false))?;
+                    if !content.is_empty() {
+                        on_event(ParseEvent::Text(Cow::Borrowed(content)))?;
+                    }
+                    on_event(ParseEvent::EndTag(name))?;
+                }
+                Token::EndTag(name) => on_event(ParseEvent::EndTag(name.to_ascii_lowercase()))?,
+                Token::Text(content) => {
+                    let content = match self.options.decode_entities && content.contains('&') {
+                        true => Cow::Owned(decode_entities(content, false)),
+                        false => Cow::Borrowed(content),
+                    };
+
+                    on_event(ParseEvent::Text(content))?;
+                }
+                Token::Comment(content) => on_event(ParseEvent::Comment(content))?,
+                Token::Doctype(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // The bottom of the stack stands for the document, it is never closed
+    fn document_stack() -> Vec<Node> {
+        vec![Node::new(Self::node_data("#document"), Vec::new())]
+    }
+
+    // Inserts the tokens of the input in the tree on `stack`. Unless the input is `complete`,
+    // a token that runs to its end could go on in more input and is left out. Returns how
+    // many bytes of the input are in the tree.
+    fn build(&mut self, stack: &mut Vec<Node>, phase: &mut Phase, complete: bool) -> usize {
+        let mut used = self.chars.position;
+
+        while !self.root_closed(stack) {
+            let Some(token) = self.next_token() else {
+                break;
+            };
+
+            // Every token ends before what follows it, whitespace included
+            if !complete && self.chars.peek().is_none() {
+                break;
+            }
+            used = self.chars.position;
+
+            if self.options.document {
+                if let Some(token) = self.build_skeleton(stack, phase, token) {
+                    self.insert(stack, token);
+                }
+                continue;
+            }
+
+            // Only the root element is kept, whatever comes before or after it is dropped
+            if stack.len() == 1 && !matches!(token, Token::StartTag(..) | Token::RawText(..)) {
+                continue;
+            }
+
+            self.insert(stack, token);
+        }
+
+        used
+    }
+
+    // Without `ParserOptions::document` parsing ends with the first root element
+    fn root_closed(&self, stack: &[Node]) -> bool {
+        !self.options.document && stack.len() == 1 && !stack[0].children.is_empty()
+    }
+
+    // At the end of the input: closes whatever is still open and hands back the root
+    fn finish(&mut self, mut stack: Vec<Node>, mut phase: Phase) -> Option<Node> {
+        if self.options.document {
+            self.complete_skeleton(&mut stack, &mut phase);
+        }
+
+        while stack.len() > 1 {
+            self.close_element(&mut stack);
+        }
+
+        let document = stack.pop()?;
+
+        match self.options.document && self.options.comments {
+            true => Some(document),
+            false => document.children.into_iter().next(),
+        }
+    }
+
+    // Inserts the html, head and body elements the markup leaves out ahead of `token`, and
+    // hands the token back when it still has to be inserted
+    fn build_skeleton<'t>(
+        &mut self,
+        stack: &mut Vec<Node>,
+        phase: &mut Phase,
+        token: Token<'t>,
+    ) -> Option<Token<'t>> {
+        match &token {
+            // Only one doctype, before the html element
+            Token::Doctype(_)
+                if *phase != Phase::BeforeHtml
+                    || stack[0]
+                        .children
+                        .iter()
+                        .any(|child| child.node_type() == NodeType::Doctype) =>
+            {
+                return None
+            }
+            // Before the html element they go in the document
+            Token::Comment(_) | Token::Doctype(_) => return Some(token),
+            _ => {}
+        }
+
+        if let Token::StartTag(data, _) | Token::RawText(data, _) = &token {
+            if data.tag_name.eq_ignore_ascii_case("html") && *phase != Phase::BeforeHtml {
+                Self::merge_attributes(&mut stack[1], data);
+                return None;
+            }
+        }
+
+        if *phase == Phase::BeforeHtml {
+            *phase = Phase::BeforeHead;
+
+            match token {
+                Token::StartTag(data, _) if data.tag_name.eq_ignore_ascii_case("html") => {
+                    let html = self.element(data);
+                    stack.push(html);
+                    return None;
+                }
+                _ => stack.push(Node::new(Self::node_data("html"), Vec::new())),
+            }
+        }
+
+        match &token {
+            Token::StartTag(data, _) | Token::RawText(data, _) => {
+                match data.tag_name.to_ascii_lowercase().as_str() {
+                    "head" => {
+                        if *phase == Phase::BeforeHead {
+                            let head = self.element(Self::node_data("head"));
+                            stack.push(head);
+                            Self::merge_attributes(stack.last_mut().unwrap(), data);
+                            *phase = Phase::InHead;
+                        }
+                        return None;
+                    }
+                    "body" => {
+                        if *phase == Phase::InBody {
+                            Self::merge_attributes(&mut stack[2], data);
+                        } else {
+                            self.leave_head(stack, phase);
+                            let body = self.element(Self::node_data("body"));
+                            stack.push(body);
+                            Self::merge_attributes(stack.last_mut().unwrap(), data);
+                            *phase = Phase::InBody;
+                        }
+                        return None;
+                    }
+                    name if HEAD_TAGS.contains(&name) && *phase <= Phase::InHead => {
+                        if *phase == Phase::BeforeHead {
+                            stack.push(Node::new(Self::node_data("head"), Vec::new()));
+                            *phase = Phase::InHead;
+                        }
+                        return Some(token);
+                    }
+                    _ => {}
+                }
+            }
+            Token::EndTag(name) => {
+                let name = name.to_ascii_lowercase();
+
+                // Their end tags are recorded even though the body stays open
+                let element = match name.as_str() {
+                    "html" => stack.get_mut(1),
+                    "head" if *phase == Phase::InHead => stack.get_mut(2),
+                    "body" if *phase == Phase::InBody => stack.get_mut(2),
+                    _ => None,
+                };
+                if let Some(element) = element {
+                    if element
+                        .position
+                        .as_ref()
+                        .is_some_and(|position| position.end.is_none())
+                    {
+                        let end = self.span(self.spans.token);
+                        if let Some(position) = element.position.as_mut() {
+                            position.end = Some(end);
+                        }
+                    }
+                }
+
+                if name == "head" && *phase == Phase::InHead {
+                    self.leave_head(stack, phase);
+                }
+
+                // The body stays open for whatever comes after `</body>` or `</html>`
+                return match name.as_str() {
+                    "head" | "body" | "html" => None,
+                    _ => Some(token),
+                };
+            }
+            Token::Text(text) if *phase != Phase::InBody && text.trim().is_empty() => return None,
+            Token::Text(_) | Token::Comment(_) | Token::Doctype(_) => {}
+        }
+
+        // Anything else belongs to the body, unless it is the content of a head element
+        let in_skeleton = stack.len() <= 3 && ["html", "head"].contains(&Self::current(stack));
+
+        if *phase != Phase::InBody && in_skeleton {
+            self.leave_head(stack, phase);
+            stack.push(Node::new(Self::node_data("body"), Vec::new()));
+            *phase = Phase::InBody;
+        }
+
+        Some(token)
+    }
+
+    // At the end of the input: every document gets its html, head and body
+    fn complete_skeleton(&mut self, stack: &mut Vec<Node>, phase: &mut Phase) {
+        if *phase == Phase::BeforeHtml {
+            stack.push(Node::new(Self::node_data("html"), Vec::new()));
+            *phase = Phase::BeforeHead;
+        }
+
+        if *phase != Phase::InBody {
+            self.leave_head(stack, phase);
+            stack.push(Node::new(Self::node_data("body"), Vec::new()));
+            *phase = Phase::InBody;
+        }
+    }
+
+    // Closes the head (and whatever is still open in it), creating an empty one if missing
+    fn leave_head(&mut self, stack: &mut Vec<Node>, phase: &mut Phase) {
+        match phase {
+            Phase::BeforeHead => {
+                stack.push(Node::new(Self::node_data("head"), Vec::new()));
+                self.close_element(stack);
+            }
+            Phase::InHead => {
+                while stack.len() > 2 {
+                    self.close_element(stack);
+                }
+            }
+            _ => return,
+        }
+
+        *phase = Phase::AfterHead;
+    }
+
+    fn insert(&mut self, stack: &mut Vec<Node>, token: Token<'_>) {
+        match token {
+            Token::StartTag(data, self_closing) => {
+                let tag_name = data.tag_name.to_ascii_lowercase();
+
+                self.close_implied(stack, &tag_name);
+                let element = self.element(data);
+                stack.push(element);
+
+                if self.is_void(&tag_name) || (self_closing && Self::honors_self_closing(stack)) {
+                    self.close_element(stack);
+                }
+            }
+            Token::RawText(data, content) => {
+                self.close_implied(stack, &data.tag_name.to_ascii_lowercase());
+                let mut element = self.element(data);
+
+                if !content.is_empty() {
+                    let mut text = Node::text(content.to_string());
+                    text.position = self.position(self.spans.content);
+                    element.children.push(text);
+                }
+
+                if let Some(end_tag) = self.spans.end_tag {
+                    if element.position.is_some() {
+                        let end = self.span(end_tag);
+                        if let Some(position) = element.position.as_mut() {
+                            position.end = Some(end);
+                        }
+                    }
+                }
+
+                stack.push(element);
+                self.close_element(stack);
+            }
+            Token::EndTag(tag_name) => {
+                let tag_name = tag_name.to_ascii_lowercase();
+                // Table parts close across the cells they contain
+                let boundaries: &[&str] = match tag_name.as_str() {
+                    "table" | "thead" | "tbody" | "tfoot" | "tr" => &["html", "table", "template"],
+                    _ => &SCOPE_BOUNDARIES,
+                };
+
+                if let Some(index) = Self::in_scope(stack, &[&tag_name], boundaries) {
+                    let mut element = std::mem::replace(
+                        &mut stack[index],
+                        Node::new(Self::node_data(""), Vec::new()),
+                    );
+                    self.end_element(&mut element);
+                    stack[index] = element;
+
+                    while stack.len() > index {
+                        self.close_element(stack);
+                    }
+                }
+            }
+            Token::Text(content) => {
+                let content = match self.options.decode_entities && content.contains('&') {
+                    true => Cow::Owned(decode_entities(content, false)),
+                    false => Cow::Borrowed(content),
+                };
+
+                let parent = stack.last_mut().unwrap();
+
+                // Text split by a comment or a stray tag ends up in a single node
+                match parent.children.last_mut() {
+                    Some(last) if last.data.tag_name == "text" => {
+                        if let Some(text) = last.data.attributes.get_mut("content") {
+                            text.push_str(&content);
+                        }
+                        if let Some(position) = last.position.as_mut() {
+                            position.start.end = self.offset + self.spans.token.1;
+                        }
+                    }
+                    _ => {
+                        let mut text = Node::text(content.into_owned());
+                        text.position = self.position(self.spans.token);
+                        stack.last_mut().unwrap().children.push(text);
+                    }
+                }
+            }
+            Token::Comment(content) => {
+                let mut comment = Node::comment(content.to_string());
+                comment.position = self.position(self.spans.token);
+                stack.last_mut().unwrap().children.push(comment);
+            }
+            // Anywhere but in the document it's a stray one
+            Token::Doctype(data) if stack.len() == 1 => {
+                let doctype = self.element(data);
+                stack[0].children.push(doctype);
+            }
+            Token::Doctype(_) => {}
+        }
+    }
+
+    fn is_void(&self, tag_name: &str) -> bool {
+        VOID_ELEMENTS.contains(&tag_name)
+            || self
+                .options
+                .void_elements
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(tag_name))
+    }
+
+    // `/>` only closes SVG and MathML elements and the ones HTML doesn't know, like browsers
+    // `<div/>` is just `<div>`. The element it is about was just pushed.
+    fn honors_self_closing(stack: &[Node]) -> bool {
+        let is_foreign = |node: &Node| {
+            let tag_name = node.data.tag_name.to_ascii_lowercase();
+            tag_name == "svg" || tag_name == "math"
+        };
+
+        let tag_name = Self::current(stack).to_ascii_lowercase();
+
+        !HTML_ELEMENTS.contains(&tag_name.as_str()) || stack.iter().any(is_foreign)
+    }
+
+    // Start tags that end open elements (https://html.spec.whatwg.org/multipage/syntax.html#optional-tags)
+    fn close_implied(&mut self, stack: &mut Vec<Node>, tag_name: &str) {
+        if CLOSES_PARAGRAPH.contains(&tag_name) {
+            self.close_in_scope(stack, &["p"], &SCOPE_BOUNDARIES);
+        }
+
+        match tag_name {
+            "li" => self.close_in_scope(
+                stack,
+                &["li"],
+                &[&SCOPE_BOUNDARIES[..], &["ul", "ol"]].concat(),
+            ),
+            "dd" | "dt" => self.close_in_scope(
+                stack,
+                &["dd", "dt"],
+                &[&SCOPE_BOUNDARIES[..], &["dl"]].concat(),
+            ),
+            "tr" => self.close_in_scope(stack, &["tr"], &["table", "thead", "tbody", "tfoot"]),
+            "td" | "th" => self.close_in_scope(stack, &["td", "th"], &["tr", "table"]),
+            "thead" | "tbody" | "tfoot" => {
+                self.close_in_scope(stack, &["thead", "tbody", "tfoot"], &["table"])
+            }
+            "a" => self.close_in_scope(stack, &["a"], &SCOPE_BOUNDARIES),
+            "option" => self.close_current(stack, &["option"]),
+            "optgroup" => self.close_current(stack, &["option", "optgroup"]),
+            heading if HEADINGS.contains(&heading) => self.close_current(stack, &HEADINGS),
+            _ => {}
+        }
+    }
+
+    // Closes the innermost open element named one of `names` and everything opened after it,
+    // unless an element named one of `boundaries` comes first
+    fn close_in_scope(&mut self, stack: &mut Vec<Node>, names: &[&str], boundaries: &[&str]) {
+        if let Some(index) = Self::in_scope(stack, names, boundaries) {
+            while stack.len() > index {
+                self.close_element(stack);
+            }
+        }
+    }
+
+    // Index of the innermost open element named one of `names`, unless an element named one
+    // of `boundaries` comes first
+    fn in_scope(stack: &[Node], names: &[&str], boundaries: &[&str]) -> Option<usize> {
+        let named = |names: &[&str], tag_name: &str| {
+            names.iter().any(|name| name.eq_ignore_ascii_case(tag_name))
+        };
+
+        for index in (1..stack.len()).rev() {
+            let tag_name = &stack[index].data.tag_name;
+
+            if named(names, tag_name) {
+                return Some(index);
+            }
+
+            if named(boundaries, tag_name) {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    fn close_current(&mut self, stack: &mut Vec<Node>, names: &[&str]) {
+        while stack.len() > 1
+            && names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(Self::current(stack)))
+        {
+            self.close_element(stack);
+        }
+    }
+
+    // Pops the current element into its parent
+    fn close_element(&mut self, stack: &mut Vec<Node>) {
+        let mut node = stack.pop().unwrap();
+        let has_host = stack.len() > 1;
+        let parent = stack.last_mut().unwrap();
+
+        if node.data.tag_name.eq_ignore_ascii_case("noscript") {
+            match self.options.noscript {
+                NoscriptMode::Parse => {}
+                NoscriptMode::Flag => node.flag_noscript(),
+                NoscriptMode::Skip => return,
+            }
+        }
+
+        // Only the first declarative shadow root of a host is attached
+        if self.options.declarative_shadow_dom && has_host && parent.shadow_root.is_none() {
+            if let Some(mode) = node.shadow_root_mode().cloned() {
+                parent.shadow_root = Some(ShadowRoot {
+                    mode,
+                    children: node.children,
+                });
+                return;
+            }
+        }
+
+        parent.children.push(node);
+    }
+
+    fn current(stack: &[Node]) -> &str {
+        stack.last().map_or("", |node| node.data.tag_name.as_str())
+    }
+
+    fn node_data(tag_name: &str) -> NodeData {
+        NodeData {
+            tag_name: tag_name.to_string(),
+            attributes: HashMap::new(),
+            in_noscript: false,
+        }
+    }
+
+    // Attributes of a repeated `<html>` or `<body>` are added to the first one
+    fn merge_attributes(node: &mut Node, data: &NodeData) {
+        for (name, value) in &data.attributes {
+            node.data
+                .attributes
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        loop {
+            let start = self.chars.position;
+
+            if *self.chars.peek()? != '<' {
+                let text = self.read_until(&['<']);
+                self.spans = TokenSpans {
+                    token: (start, self.chars.position),
+                    ..Default::default()
+                };
+
+                return Some(Token::Text(text));
+            }
+
+            self.chars.next();
+
+            match self.chars.peek().copied() {
+                // Comments, doctypes and processing instructions
+                Some('!' | '?') => {
+                    if let Some(token) = self.read_markup_declaration() {
+                        self.spans = TokenSpans {
+                            token: (start, self.tag_end),
+                            ..Default::default()
+                        };
+
+                        return Some(token);
+                    }
+                }
+                Some('/') => {
+                    self.chars.next();
+                    let tag_name = self.read_until(&['>']);
+                    // Consume last >
+                    self.chars.next();
+                    self.spans = TokenSpans {
+                        token: (start, self.chars.position),
+                        ..Default::default()
+                    };
+                    self.consume_inter_element_whitespace();
+
+                    if let Some(tag_name) = tag_name.split_whitespace().next() {
+                        return Some(Token::EndTag(tag_name));
+                    }
+                }
+                Some(char) if char.is_ascii_alphabetic() => {
+                    let (data, self_closing) = self.parse_start_tag();
+                    let tag_name = data.tag_name.to_ascii_lowercase();
+                    self.spans = TokenSpans {
+                        token: (start, self.tag_end),
+                        ..Default::default()
+                    };
+
+                    if RAW_TEXT_TAGS.contains(&tag_name.as_str()) {
+                        let content_start = self.chars.position;
+                        let content = self.read_raw_text(&tag_name);
+                        let content_end = content_start + content.len();
+
+                        self.spans.content = (content_start, content_end);
+                        // Whatever follows the content is its end tag
+                        if self.chars.position > content_end {
+                            self.spans.end_tag = Some((content_end, self.tag_end));
+                        }
+
+                        return Some(Token::RawText(data, content));
+                    }
+
+                    return Some(Token::StartTag(data, self_closing));
+                }
+                // A `<` that doesn't start a tag is just text
+                _ => {
+                    self.read_until(&['<']);
+                    self.spans = TokenSpans {
+                        token: (start, self.chars.position),
+                        ..Default::default()
+                    };
+
+                    return Some(Token::Text(self.chars.since(start)));
+                }
+            }
+        }
+    }
+
+    // The tag's data and whether it ends with `/>`
+    fn parse_start_tag(&mut self) -> (NodeData, bool) {
+        // Collect chars from current pointer until we find an empty space or a closing tag char
+        // empty space: <p( )class="">
+        // closing tag char: <p(>), <br(/)>
+        let tag_name = self.read_until(&[' ', '\t', '\n', '\r', '/', '>']);
+        let mut data = Self::node_data(tag_name);
+
+        let self_closing = self.parse_attributes(&mut data);
+        self.tag_end = self.chars.position;
+
+        // Consume white spaces and line feeds before the content
+        self.consume_whitespaces();
+
+        (data, self_closing)
+    }
+
+    // Returns whether the tag ends with `/>`
+    fn parse_attributes(&mut self, data: &mut NodeData) -> bool {
+        let attributes_str = self.read_attributes_source();
+        // Consume last >
+        self.chars.next();
+
+        let attributes = tokenize_attributes(attributes_str);
+        // The `/` of an unquoted value (`<a href=/>`) is part of it
+        let self_closing = attributes_str.ends_with('/')
+            && attributes.last().is_none_or(|(_, value)| {
+                !attributes_str
+                    .strip_suffix(value)
+                    .is_some_and(|before| before.ends_with('='))
+            });
+
+        for (attr_name, attr_value) in attributes {
+            // Like browsers do, the first of repeated attributes wins and the others are dropped
+            if data.attributes.contains_key(attr_name) {
+                continue;
+            }
+
+            let attr_value = match self.options.decode_entities && attr_value.contains('&') {
+                true => decode_entities(attr_value, true),
+                false => attr_value.to_string(),
+            };
+            data.attributes.insert(attr_name.to_string(), attr_value);
+        }
+
+        self_closing
+    }
+
+    // Reads up to the `>` closing the tag, one inside a quoted value doesn't count
+    fn read_attributes_source(&mut self) -> &'a str {
+        let rest = self.chars.rest();
+        let mut quote = None;
+        let mut end = rest.len();
+
+        for (index, char) in rest.char_indices() {
+            match quote {
+                Some(open) if char == open => quote = None,
+                Some(_) => {}
+                None if char == '>' => {
+                    end = index;
+                    break;
+                }
+                None if ['"', '\''].contains(&char) && rest[..index].trim_end().ends_with('=') => {
+                    quote = Some(char)
+                }
+                None => {}
+            }
+        }
+
+        self.chars.advance(end);
+
+        &rest[..end]
+    }
+
+    // Script and style content runs up to the matching end tag, whatever it contains
+    fn read_raw_text(&mut self, tag_name: &str) -> &'a str {
+        let rest = self.chars.rest();
+        let end_tag = format!("</{}", tag_name);
+        let end = rest
+            .match_indices("</")
+            .map(|(index, _)| index)
+            .find(|&index| {
+                rest[index..]
+                    .get(..end_tag.len())
+                    .is_some_and(|candidate| candidate.eq_ignore_ascii_case(&end_tag))
+            });
+
+        let Some(end) = end else {
+            self.chars.advance(rest.len());
+            return rest;
+        };
+
+        self.chars.advance(end);
+        self.consume_until(&'>');
+        self.tag_end = self.chars.position;
+        self.consume_whitespaces();
+
+        &rest[..end]
+    }
+
+    fn read_until(&mut self, chars: &[char]) -> &'a str {
+        let rest = self.chars.rest();
+        let end = rest.find(chars).unwrap_or(rest.len());
+        self.chars.advance(end);
+
+        &rest[..end]
+    }
+
+    fn consume_until(&mut self, char: &char) {
+        let rest = self.chars.rest();
+        let end = rest
+            .find(*char)
+            .map_or(rest.len(), |index| index + char.len_utf8());
+        self.chars.advance(end);
+    }
+
+    fn consume_whitespaces(&mut self) {
+        let rest = self.chars.rest();
+        let trimmed = rest.trim_start_matches([' ', '\t', '\n']);
+        self.chars.advance(rest.len() - trimmed.len());
+    }
+
+    // Whitespace is only dropped between tags, before text it separates words (`<b>a</b> b`)
+    fn consume_inter_element_whitespace(&mut self) {
+        let rest = self.chars.rest().trim_start_matches([' ', '\t', '\n']);
+
+        if rest.is_empty() || rest.starts_with('<') {
+            self.consume_whitespaces();
+        }
+    }
+
+    // Reads a comment, doctype or processing instruction, its `<` is already consumed. They
+    // are only tokens with `ParserOptions::comments`, otherwise they are skipped.
+    fn read_markup_declaration(&mut self) -> Option<Token<'a>> {
+        let rest = self.chars.rest();
+
+        let token = if let Some(comment) = rest.strip_prefix("!--") {
+            // Comments end at the first `-->`, a `>` alone doesn't
+            let (content, length) = match comment.find("-->") {
+                Some(end) => (&comment[..end], end + 3),
+                None => (comment, comment.len()),
+            };
+            self.chars.advance(3 + length);
+
+            Token::Comment(content)
+        } else if rest
+            .get(1..8)
+            .is_some_and(|name| name.eq_ignore_ascii_case("doctype"))
+        {
+            self.chars.advance(8);
+            let declaration = self.read_until(&['>']);
+            self.chars.next();
+
+            Token::Doctype(Self::doctype_data(declaration))
+        } else {
+            // Anything else is a bogus comment, a processing instruction keeps its `?`
+            self.chars.next_if_eq(&'!');
+            let content = self.read_until(&['>']);
+            self.chars.next();
+
+            Token::Comment(content)
+        };
+
+        self.tag_end = self.chars.position;
+        self.consume_whitespaces();
+
+        self.options.comments.then_some(token)
+    }
+
+    // `<!DOCTYPE html PUBLIC "..." "...">`, from after the DOCTYPE keyword
+    fn doctype_data(declaration: &str) -> NodeData {
+        let mut data = Self::node_data("#doctype");
+        let declaration = declaration.trim_start();
+        let (name, rest) = declaration
+            .split_once(char::is_whitespace)
+            .unwrap_or((declaration, ""));
+        let rest = rest.trim_start();
+        let identifiers = quoted_strings(rest);
+
+        data.attributes
+            .insert("name".to_string(), name.to_ascii_lowercase());
+
+        let keyword = rest.get(..6).map(str::to_ascii_uppercase);
+        let names: &[&str] = match keyword.as_deref() {
+            Some("PUBLIC") => &["public_id", "system_id"],
+            Some("SYSTEM") => &["system_id"],
+            _ => &[],
+        };
+
+        for (name, identifier) in names.iter().zip(identifiers) {
+            data.attributes
+                .insert(name.to_string(), identifier.to_string());
+        }
+
+        data
+    }
+}
+
+impl StreamingParser {
+    pub fn new(options: ParserOptions) -> Self {
+        Self {
+            options,
+            pending: String::new(),
+            started: false,
+            stack: HTMLParser::document_stack(),
+            phase: Phase::BeforeHtml,
+            consumed: 0,
+            locator: Locator::default(),
+        }
+    }
+
+    // A parser for `input`, the part of the markup after the `consumed` bytes
+    fn parser<'a>(&mut self, input: &'a str) -> HTMLParser<'a> {
+        HTMLParser {
+            chars: Input::new(input),
+            options: self.options.clone(),
+            offset: self.consumed,
+            locator: self.locator,
+            spans: TokenSpans::default(),
+            tag_end: 0,
+        }
+    }
+
+    // Drops the first `used` bytes of the pending input
+    fn consume(&mut self, used: usize) {
+        if self.options.source_positions {
+            let scanned = self.locator.offset.saturating_sub(self.consumed);
+            if used > scanned {
+                self.locator.advance(&self.pending[scanned..used]);
+            }
+        }
+
+        self.pending.drain(..used);
+        self.consumed += used;
+    }
+
+    pub fn feed(&mut self, chunk: &str) {
+        self.pending.push_str(chunk);
+
+        if !self.started {
+            self.consume(self.pending.len() - self.pending.trim_start().len());
+            self.started = !self.pending.is_empty();
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        let mut parser = self.parser(&pending);
+        let used = parser.build(&mut self.stack, &mut self.phase, false);
+        let root_closed = parser.root_closed(&self.stack);
+        self.locator = parser.locator;
+        self.pending = pending;
+
+        // The rest of a fragment is dropped anyway
+        match root_closed {
+            true => self.pending.clear(),
+            false => self.consume(used),
+        }
+    }
+
+    /// Ends the input, returns what `HTMLParser::parse` would.
+    pub fn finish(mut self) -> Option<Node> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut parser = self.parser(pending.trim_end());
+
+        parser.build(&mut self.stack, &mut self.phase, true);
+        parser.finish(self.stack, self.phase)
+    }
+}
+
+impl<'a> Input<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position: 0,
+            peeked: None,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.peeked = self.source[self.position..].chars().next();
+        self.peeked.as_ref()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    // The input from `start` up to where the tokenizer got
+    fn since(&self, start: usize) -> &'a str {
+        &self.source[start..self.position]
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.position += bytes;
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        match self.peek() == Some(expected) {
+            true => self.next(),
+            false => None,
+        }
+    }
+}
+
+impl Iterator for Input<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let next_char = self.source[self.position..].chars().next()?;
+        self.position += next_char.len_utf8();
+
+        Some(next_char)
+    }
+}
+
+// The content of each `"..."` or `'...'` in `text`
+fn quoted_strings(text: &str) -> Vec<&str> {
+    let mut strings = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['"', '\'']) {
+        let quote = &rest[start..start + 1];
+        let after = &rest[start + 1..];
+        let end = after.find(quote).unwrap_or(after.len());
+
+        strings.push(&after[..end]);
+        rest = after.get(end + 1..).unwrap_or("");
+    }
+
+    strings
+}
+
+// Splits the source of a tag's attributes into name/value pairs. Values can be double quoted,
+// single quoted or unquoted, attributes without one (`disabled`) get an empty value.
+fn tokenize_attributes(source: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = source;
+
+    loop {
+        rest = rest.trim_start_matches(|char: char| char.is_whitespace() || char == '/');
+
+        // A leading `=` is part of the name
+        let skip = usize::from(rest.starts_with('='));
+        let end = rest[skip..]
+            .find(|char: char| char.is_whitespace() || "/=".contains(char))
+            .map_or(rest.len(), |index| index + skip);
+        let name = &rest[..end];
+
+        if name.is_empty() {
+            break;
+        }
+
+        rest = rest[end..].trim_start();
+        let mut value = "";
+
+        if let Some(after) = rest.strip_prefix('=') {
+            rest = after.trim_start();
+
+            match rest.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &rest[1..];
+                    let end = quoted.find(quote).unwrap_or(quoted.len());
+                    value = &quoted[..end];
+                    rest = quoted.get(end + 1..).unwrap_or("");
+                }
+                _ => {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    value = &rest[..end];
+                    rest = &rest[end..];
+                }
+            }
+        }
+
+        attributes.push((name, value));
+    }
+
+    attributes
+}
+
+fn escape_html(text: &str, in_attribute: bool, output: &mut String) {
+    for char in text.chars() {
+        match char {
+            '&' => output.push_str("&amp;"),
+            '\u{A0}' => output.push_str("&nbsp;"),
+            '"' if in_attribute => output.push_str("&quot;"),
+            '<' if !in_attribute => output.push_str("&lt;"),
+            '>' if !in_attribute => output.push_str("&gt;"),
+            char => output.push(char),
+        }
+    }
+}
+
+/// Replaces the character references in `text` following the HTML tokenizer's rules. In
+/// attribute values, legacy references without a `;` are left alone when a `=` or an
+/// alphanumeric follows them (`?a=1&copy=2`).
+pub fn decode_entities(text: &str, in_attribute: bool) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        match decode_reference(rest, in_attribute) {
+            Some((replacement, length)) => {
+                decoded.push_str(&replacement);
+                rest = &rest[length..];
+            }
+            None => decoded.push('&'),
+        }
+    }
+
+    decoded.push_str(rest);
+
+    decoded
+}
+
+// Replacement for the reference `text` starts with (right after its `&`), and its length
+fn decode_reference(text: &str, in_attribute: bool) -> Option<(String, usize)> {
+    if let Some(numeric) = text.strip_prefix('#') {
+        let (digits, radix) = match numeric.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16),
+            None => (numeric, 10),
+        };
+        let length = digits
+            .find(|char: char| !char.is_digit(radix))
+            .unwrap_or(digits.len());
+
+        if length == 0 {
+            return None;
+        }
+
+        // Too large to fit is as out of range as anything past U+10FFFF
+        let code = u32::from_str_radix(&digits[..length], radix).unwrap_or(u32::MAX);
+        let semicolon = digits[length..].starts_with(';') as usize;
+
+        return Some((
+            numeric_reference(code).to_string(),
+            text.len() - digits.len() + length + semicolon,
+        ));
+    }
+
+    // The longest name that matches wins, `&notit;` is `¬it;`
+    let name_length = text
+        .bytes()
+        .take(LONGEST_NAME)
+        .take_while(u8::is_ascii_alphanumeric)
+        .count();
+    let end = name_length + text[name_length..].starts_with(';') as usize;
+
+    for length in (1..=end).rev() {
+        let name = &text[..length];
+
+        if let Ok(index) = ENTITIES.binary_search_by_key(&name, |(name, _)| name) {
+            let next = text[length..].chars().next();
+
+            if in_attribute
+                && !name.ends_with(';')
+                && next.is_some_and(|char| char == '=' || char.is_ascii_alphanumeric())
+            {
+                return None;
+            }
+
+            return Some((ENTITIES[index].1.to_string(), length));
+        }
+    }
+
+    None
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+fn numeric_reference(code: u32) -> char {
+    match code {
+        0 => '\u{FFFD}',
+        // C1 controls are what windows-1252 pages meant by them
+        0x80..=0x9F => WINDOWS_1252
+            .decode_without_bom_handling(&[code as u8])
+            .0
+            .chars()
+            .next()
+            .unwrap_or('\u{FFFD}'),
+        // Surrogates and anything out of range
+        _ => char::from_u32(code).unwrap_or('\u{FFFD}'),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::get_first)]
+mod tests {
+    use std::fs::read_to_string;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_node() {
+        let html = r#"<html data-darkreader-mode="dynamic" data-darkreader-scheme="dark"></html>"#;
+        let mut parser = HTMLParser::new(html);
+        let node = parser.parse().unwrap();
+
+        assert_eq!(node.data.tag_name, "html");
+        assert_eq!(
+            node.data.attributes.get("data-darkreader-mode"),
+            Some(&"dynamic".to_string())
+        );
+        assert_eq!(
+            node.data.attributes.get("data-darkreader-scheme"),
+            Some(&"dark".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_text_content() {
+        let html = r#"<html data-darkreader-mode="dynamic" data-darkreader-scheme="dark">welcome to my page</html>"#;
+        let mut parser = HTMLParser::new(html);
+        let node = parser.parse().unwrap();
 
-        self.find_nodes("text", &mut collected_nodes);
+        let child = node.children.get(0).unwrap();
 
-        collected_nodes
+        assert_eq!(child.data.tag_name, "text".to_string());
+        assert_eq!(
+            child.data.attributes.get("content"),
+            Some(&"welcome to my page".to_string())
+        );
     }
 
-    #[deprecated]
-    pub fn find_nodes<'a>(&'a self, node_type: &str, collected_nodes: &mut Vec<&'a Node>) {
-        for child in &self.children {
-            if child.data.tag_name == node_type {
-                collected_nodes.push(child);
-            } else {
-                child.find_nodes(node_type, collected_nodes);
-            }
-        }
+    #[test]
+    fn test_parse_attribute_syntaxes() {
+        let html =
+            r#"<input type=checkbox id='x' disabled data-a = "1 > 0" title='say "hi"' checked/>"#;
+        let node = HTMLParser::new(html).parse().unwrap();
+        let attributes = &node.data.attributes;
+
+        assert_eq!(node.data.tag_name, "input");
+        assert_eq!(attributes.len(), 6);
+        assert_eq!(node.attr("type"), "checkbox");
+        assert_eq!(node.attr("id"), "x");
+        assert_eq!(node.attr("disabled"), "");
+        assert_eq!(node.attr("data-a"), "1 > 0");
+        assert_eq!(node.attr("title"), "say \"hi\"");
+        assert_eq!(node.attr("checked"), "");
     }
 
-    pub fn attr(&self, name: &str) -> &String {
-        self.data.attributes.get(name).unwrap()
+    #[test]
+    fn test_parse_duplicate_attributes() {
+        let node = HTMLParser::new(r#"<a href="/first" class=a href='/second'>x</a>"#)
+            .parse()
+            .unwrap();
+
+        assert_eq!(node.attr("href"), "/first");
+        assert_eq!(node.attr("class"), "a");
+        assert_eq!(node.children[0].attr("content"), "x");
     }
-}
 
-pub struct HTMLParser<'a> {
-    chars: Peekable<Chars<'a>>,
-}
-
-impl<'a> HTMLParser<'a> {
-    pub fn new(source: &'a str) -> Self {
-        let mut instance = Self {
-            chars: source.trim().chars().peekable(),
+    #[test]
+    fn test_tokenize_attributes() {
+        let pairs = |source: &str| {
+            tokenize_attributes(source)
+                .into_iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
         };
 
-        instance.consume_doctype(source);
-
-        instance
+        assert_eq!(pairs(""), Vec::<String>::new());
+        assert_eq!(pairs(" a b=c\td='e f'/"), ["a=", "b=c", "d=e f"]);
+        assert_eq!(pairs("href=/a/b/ =x"), ["href=/a/b/", "=x="]);
+        assert_eq!(pairs("x=\"unterminated"), ["x=unterminated"]);
     }
 
-    pub fn parse(&mut self) -> Option<Node> {
-        let mut root = Node::new(
-            NodeData {
-                tag_name: "".to_string(),
-                attributes: HashMap::new(),
-            },
-            Vec::new(),
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities(
+                "a &amp; b &lt;i&gt; &quot;q&quot; &#160;&#xA0;&#X41;",
+                false
+            ),
+            "a & b <i> \"q\" \u{a0}\u{a0}A"
+        );
+        assert_eq!(
+            decode_entities("&NotNestedGreaterGreater;", false),
+            "\u{2aa2}\u{338}"
+        );
+        assert_eq!(
+            decode_entities("&notit; &copy2023 &ampx", false),
+            "\u{ac}it; \u{a9}2023 &x"
+        );
+        assert_eq!(
+            decode_entities("&#128; &#0; &#xD800; &#1114112; &#99999999999", false),
+            "\u{20ac} \u{fffd} \u{fffd} \u{fffd} \u{fffd}"
+        );
+        assert_eq!(
+            decode_entities("& &# &#x; &bogus; AT&T", false),
+            "& &# &#x; &bogus; AT&T"
+        );
+
+        assert_eq!(decode_entities("?a=1&copy=2&amp;b", true), "?a=1&copy=2&b");
+        assert_eq!(
+            decode_entities("&copy;2023 &copy 2023", true),
+            "\u{a9}2023 \u{a9} 2023"
         );
+    }
 
-        // 1. Parse tag name
-        self.parse_tag_name(&mut root);
+    #[test]
+    fn test_parse_entities() {
+        let html = r#"<p title="Tom &amp; Jerry">1 &lt; 2<script>if (a &lt; b) {}</script></p>"#;
+        let node = HTMLParser::new(html).parse().unwrap();
 
-        let tag_name = root.data.tag_name.clone();
+        assert_eq!(node.attr("title"), "Tom & Jerry");
+        assert_eq!(node.children[0].attr("content"), "1 < 2");
+        assert_eq!(
+            node.children[1].children[0].attr("content"),
+            "if (a &lt; b) {}"
+        );
 
-        // 2. Parse attributes
-        self.parse_attributes(&mut root);
+        let options = ParserOptions {
+            decode_entities: false,
+            ..Default::default()
+        };
+        let node = HTMLParser::with_options(html, options).parse().unwrap();
 
-        // 2.a. consume white spaces and line feeds before the content
-        self.consume_whitespaces();
+        assert_eq!(node.attr("title"), "Tom &amp; Jerry");
+        assert_eq!(node.children[0].attr("content"), "1 &lt; 2");
+    }
 
-        // 2.b. do not parse content if it's a self-closing tag
-        if SELF_CLOSING_TAGS.contains(&tag_name.as_str()) {
-            return Some(root);
-        }
+    fn document(html: &str) -> Node {
+        let options = ParserOptions {
+            document: true,
+            ..Default::default()
+        };
 
-        // 4. Parse content
-        self.parse_content(&mut root);
+        HTMLParser::with_options(html, options).parse().unwrap()
+    }
 
-        // 5. consume white spaces and line feeds after the content
-        self.consume_whitespaces();
+    fn tag_names(node: &Node) -> Vec<&str> {
+        node.children
+            .iter()
+            .map(|child| child.data.tag_name.as_str())
+            .collect()
+    }
 
-        Some(root)
+    #[test]
+    fn test_implied_end_tags() {
+        let html = "<div><ul><li>one<li>two</ul><p>a<p>b<div>c</div><dl><dt>t<dd>d</dl></div>";
+        let node = HTMLParser::new(html).parse().unwrap();
+
+        assert_eq!(tag_names(&node), ["ul", "p", "p", "div", "dl"]);
+        assert_eq!(tag_names(&node.children[0]), ["li", "li"]);
+        assert_eq!(
+            node.children[0].children[1].children[0].attr("content"),
+            "two"
+        );
+        assert_eq!(node.children[2].children[0].attr("content"), "b");
+        assert_eq!(tag_names(&node.children[4]), ["dt", "dd"]);
     }
 
-    fn parse_tag_name(&mut self, node: &mut Node) {
-        // Collect chars from current pointer until we find an empty space or a closing tag char
-        // empty space: <p( )class="">
-        // closing tag char: <p(>)
-        let tag_name_str = self.read_until(vec![&' ', &'>']);
+    #[test]
+    fn test_implied_table_ends() {
+        let html = "<table><tr><td>1<td>2<tr><th>3</table>";
+        let node = HTMLParser::new(html).parse().unwrap();
 
-        // Remove < from the start and / from the end for self-closing tags
-        // <br/>
-        node.data.tag_name = tag_name_str.replace('<', "").replace('/', "");
+        assert_eq!(tag_names(&node), ["tr", "tr"]);
+        assert_eq!(tag_names(&node.children[0]), ["td", "td"]);
+        assert_eq!(tag_names(&node.children[1]), ["th"]);
     }
 
-    fn parse_attributes(&mut self, node: &mut Node) {
-        let attributes_str = self.read_until(vec![&'>']);
-        // Consume last >
-        self.chars.next().unwrap();
+    #[test]
+    fn test_unclosed_and_stray_tags() {
+        let node = HTMLParser::new("<div>a</span>b<SPAN>c").parse().unwrap();
+
+        assert_eq!(tag_names(&node), ["text", "SPAN"]);
+        assert_eq!(node.children[0].attr("content"), "ab");
+        assert_eq!(node.children[1].children[0].attr("content"), "c");
+
+        let node = HTMLParser::new("<p><b>bold<i>both</b>italic</p>")
+            .parse()
+            .unwrap();
+        assert_eq!(tag_names(&node), ["b", "text"]);
+        assert_eq!(tag_names(&node.children[0]), ["text", "i"]);
+    }
 
-        // No attributes just return
-        if attributes_str.is_empty() {
-            return;
-        }
+    #[test]
+    fn test_raw_text_and_comments() {
+        let html = "<div><!-- a > b --><script>if (a < b && c > d) {}</SCRIPT>x<!-->y</div>";
+        let node = HTMLParser::new(html).parse().unwrap();
 
-        let mut attributes = HashMap::new();
+        assert_eq!(tag_names(&node), ["script", "text"]);
+        assert_eq!(
+            node.children[0].children[0].attr("content"),
+            "if (a < b && c > d) {}"
+        );
+        assert_eq!(node.children[1].attr("content"), "x");
+    }
 
-        let attributes_pairs = Regex::new(r#"[^\s=]+="[^"]*""#)
-            .unwrap()
-            .find_iter(attributes_str.as_str())
-            .map(|m| m.as_str())
-            .collect::<Vec<&str>>();
+    #[test]
+    fn test_document_skeleton() {
+        let node = document("<!doctype html><title>t</title><meta charset=utf-8><p>hi");
 
-        for attr_pair in attributes_pairs {
-            let (attr_name, attr_value) = attr_pair
-                .split_once('=')
-                .ok_or("Error on parsing attribute")
-                .unwrap();
+        assert_eq!(node.data.tag_name, "html");
+        assert_eq!(tag_names(&node), ["head", "body"]);
+        assert_eq!(tag_names(&node.children[0]), ["title", "meta"]);
+        assert_eq!(tag_names(&node.children[1]), ["p"]);
 
-            attributes.insert(attr_name.to_string(), attr_value.replace('"', ""));
-        }
+        let node = document("hello <b>world</b>");
+        assert_eq!(tag_names(&node), ["head", "body"]);
+        assert_eq!(tag_names(&node.children[1]), ["text", "b"]);
 
-        node.data.attributes.extend(attributes);
+        let node = document("");
+        assert_eq!(tag_names(&node), ["head", "body"]);
     }
 
-    fn parse_content(&mut self, node: &mut Node) {
-        loop {
-            if let Some(next_char) = self.chars.peek() {
-                // Check if content is another element
-                if *next_char == '<' {
-                    self.chars.next().unwrap();
+    #[test]
+    fn test_document_explicit_skeleton() {
+        let html = r#"<html lang="en"><head><title>t</title></head><body class="a"><p>one</body></html><p>two<body id="b">"#;
+        let node = document(html);
+        let body = &node.children[1];
+
+        assert_eq!(node.attr("lang"), "en");
+        assert_eq!(tag_names(&node), ["head", "body"]);
+        assert_eq!(tag_names(body), ["p", "p"]);
+        assert_eq!(body.attr("class"), "a");
+        assert_eq!(body.attr("id"), "b");
+    }
 
-                    // check that we are not in a closing tag or comment instead of an opening one
-                    let next_char = self.chars.peek().unwrap().clone();
+    #[test]
+    fn test_node_ref_traversal() {
+        let html = r#"<div><h1>title</h1><p>one <b>two</b> three</p><template shadowrootmode="open"><i>shadow</i></template></div>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
+        let text_nodes = root.text_nodes(false);
+        let bold_text = root.locate(text_nodes[2]).unwrap();
 
-                    if ['!', '/'].contains(&next_char) {
-                        // If we are in a closing tag, consume all the chars until we find a > char
-                        self.consume_until(&'>');
+        assert_eq!(bold_text.node().attr("content"), "two");
+        assert_eq!(
+            bold_text.path(),
+            [Step::Child(1), Step::Child(1), Step::Child(0)]
+        );
 
-                        if next_char == '/' {
-                            break; // We break out of the loop since we already parsed child for this element
-                        } else {
-                            // TODO: remove this from here, find a better place
-                            self.consume_whitespaces();
-                            continue; // We found a comment, consumed it and keep going
-                        }
-                    };
+        let tags = |nodes: Vec<NodeRef>| {
+            nodes
+                .iter()
+                .map(|node| node.node().data.tag_name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tags(bold_text.ancestors()), ["b", "p", "div"]);
 
-                    if let Some(child) = self.parse() {
-                        node.children.push(child);
-                    }
-                } else {
-                    // Treat content as plain text and skip the closing tag
-                    let content_str = self.read_until(vec![&'<']);
-
-                    // We create a "text" node for now to represent non-node children
-                    // This will contain all CSS / JS / Plan Text
-                    let mut text_node = Node {
-                        data: NodeData {
-                            tag_name: "text".to_string(),
-                            attributes: HashMap::new(),
-                        },
-                        children: Vec::new(),
-                    };
+        let bold = bold_text.parent().unwrap();
+        assert_eq!(
+            bold.previous_sibling().unwrap().node().attr("content"),
+            "one "
+        );
+        assert_eq!(
+            bold.next_sibling().unwrap().node().attr("content"),
+            " three"
+        );
+        assert!(bold.next_sibling().unwrap().next_sibling().is_none());
+        assert!(bold_text.previous_sibling().is_none());
+        assert!(NodeRef::root(&root).parent().is_none());
+
+        // Shadow tree content has no parent, like in the DOM
+        let shadow_text = root.locate(root.text_nodes(true)[0]).unwrap();
+        assert_eq!(shadow_text.node().attr("content"), "shadow");
+        assert_eq!(tags(shadow_text.ancestors()), ["i"]);
+        assert!(shadow_text.parent().unwrap().parent().is_none());
+
+        let other = HTMLParser::new("<p>two</p>").parse().unwrap();
+        assert!(root.locate(&other).is_none());
+    }
 
-                    text_node
-                        .data
-                        .attributes
-                        .insert("content".to_string(), content_str);
+    #[test]
+    fn test_node_ref_finders() {
+        let html = r#"<div><ul id="list"><li class="a">one</li><li class="b" data-x="1">two</li><li class="a">three</li></ul></div>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
+        let document = NodeRef::root(&root);
+
+        let list = document.get_element_by_id("list").unwrap();
+        assert_eq!(list.path(), [Step::Child(0)]);
+        assert!(document.get_element_by_id("missing").is_none());
+
+        let items = list.get_elements_by_class_name("a");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].next_sibling().unwrap().node().attr("class"), "b");
+        assert_eq!(items[1].parent().unwrap().node().attr("id"), "list");
+
+        let second = document.find("data-x", Some("1")).pop().unwrap();
+        assert_eq!(second.previous_sibling().unwrap().path(), items[0].path());
+        assert_eq!(document.get_elements_by_tag_name("li").len(), 3);
+
+        let first = list.query_selector("li.a").unwrap().unwrap();
+        assert_eq!(first.path(), items[0].path());
+        assert_eq!(first.ancestors()[0].path(), list.path());
+        assert_eq!(document.query_selector_all("ul > li").unwrap().len(), 3);
+        assert!(document.query_selector("[").is_err());
+    }
 
-                    node.children.push(text_node);
-                }
-            } else {
-                break;
+    #[test]
+    fn test_events() {
+        let options = ParserOptions {
+            comments: true,
+            ..Default::default()
+        };
+        let mut parser = HTMLParser::with_options(
+            r#"<DIV class="a &amp; b"><!-- note --><p>Fish &amp; chips<br/><script>if (a < b) {}</script></div>"#,
+            options,
+        );
+
+        let mut events = Vec::new();
+        parser
+            .events(|event| {
+                events.push(event);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+
+        let start_tag =
+            |name: &str, attributes: &[(&str, &str)], self_closing| ParseEvent::StartTag {
+                name: name.to_string(),
+                attributes: attributes
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                self_closing,
+            };
+        assert_eq!(
+            events,
+            [
+                start_tag("div", &[("class", "a & b")], false),
+                ParseEvent::Comment(" note "),
+                start_tag("p", &[], false),
+                ParseEvent::Text("Fish & chips".into()),
+                start_tag("br", &[], true),
+                start_tag("script", &[], false),
+                ParseEvent::Text("if (a < b) {}".into()),
+                ParseEvent::EndTag("script".to_string()),
+                ParseEvent::EndTag("div".to_string()),
+            ]
+        );
+
+        // An error stops the events
+        let mut count = 0;
+        let result = HTMLParser::new("<a><b><c>").events(|_| {
+            count += 1;
+            match count {
+                2 => Err("enough"),
+                _ => Ok(()),
             }
-        }
+        });
+        assert_eq!((result, count), (Err("enough"), 2));
     }
 
-    fn read_until(&mut self, chars: Vec<&char>) -> String {
-        let mut collected = String::new();
+    #[test]
+    fn test_streaming_parser() {
+        let sources = [
+            (
+                "\n <!DOCTYPE html><title>A &amp; B</title><script>if (a < b) {}</script>\r\n<p class='x' data-a=\"1 > 0\">caf\u{e9}&nbsp;<!-- <b> -->&eacute;<li>one<li>two\t\n\n",
+                true,
+            ),
+            (
+                "  <div><style>p { color: red }</style><my-card><template shadowrootmode=open><b>s</b></template>light</my-card><br></div> after",
+                false,
+            ),
+            (" \n ", true),
+        ];
+
+        for (source, document) in sources {
+            let options = ParserOptions {
+                document,
+                ..Default::default()
+            };
+            let expected = HTMLParser::with_options(source, options.clone())
+                .parse()
+                .map(|root| root.to_html());
+
+            let boundaries: Vec<usize> = source.char_indices().map(|(index, _)| index).collect();
+
+            for split in &boundaries {
+                let mut parser = StreamingParser::new(options.clone());
+                parser.feed(&source[..*split]);
+                parser.feed(&source[*split..]);
+
+                assert_eq!(parser.finish().map(|root| root.to_html()), expected);
+            }
 
-        while let Some(next_char) = self.chars.peek() {
-            if chars.contains(&next_char) {
-                break;
+            let mut parser = StreamingParser::new(options);
+            for (index, char) in source.char_indices() {
+                parser.feed(&source[index..index + char.len_utf8()]);
             }
-            collected.push(self.chars.next().unwrap());
+
+            assert_eq!(parser.finish().map(|root| root.to_html()), expected);
         }
+    }
+
+    #[test]
+    fn test_source_positions() {
+        let options = ParserOptions {
+            source_positions: true,
+            ..Default::default()
+        };
+        let source = "\n  <div id=\"a\">h\u{e9}<br>\n<p>one<p>two</div>";
+        let root = HTMLParser::with_options(source, options).parse().unwrap();
+        let span = |start, end, line, column| Span {
+            start,
+            end,
+            line,
+            column,
+        };
 
-        collected
+        let div = root.position.as_deref().unwrap();
+        assert_eq!(div.start, span(3, 15, 2, 3));
+        assert_eq!(div.end, Some(span(35, 41, 3, 13)));
+        assert_eq!(root.source_span(), Some((3, 41)));
+        assert!(source[3..41].starts_with("<div") && source[3..41].ends_with("</div>"));
+
+        // Columns count characters, not bytes
+        let text = root.children[0].position.as_deref().unwrap();
+        assert_eq!(text.start, span(15, 18, 2, 15));
+        let br = root.children[1].position.as_deref().unwrap();
+        assert_eq!((br.start, br.end), (span(18, 22, 2, 17), None));
+
+        // The first p is closed by the second one without an end tag
+        let p = &root.children[2];
+        assert_eq!(p.position.as_deref().unwrap().start.line, 3);
+        assert_eq!(p.position.as_deref().unwrap().end, None);
+        assert_eq!(p.source_span(), Some((23, 29)));
+        assert_eq!(&source[23..29], "<p>one");
+        assert_eq!(root.children[3].source_span(), Some((29, 35)));
+
+        // Nothing is recorded by default
+        let root = HTMLParser::new(source).parse().unwrap();
+        assert!(root.position.is_none());
+        assert!(root.source_span().is_none());
     }
 
-    fn consume_until(&mut self, char: &char) {
-        while let Some(_) = self.chars.peek() {
-            let consumed = self.chars.next().unwrap();
-            if consumed == *char {
-                break;
-            }
-        }
+    #[test]
+    fn test_raw_text_source_positions() {
+        let options = ParserOptions {
+            source_positions: true,
+            ..Default::default()
+        };
+        let source = "<div><script type=module>a < b</script ><style>p {}</div>";
+        let root = HTMLParser::with_options(source, options).parse().unwrap();
+
+        let script = &root.children[0];
+        let position = script.position.as_deref().unwrap();
+        assert_eq!((position.start.start, position.start.end), (5, 25));
+        assert_eq!(
+            position.end.map(|end| (end.start, end.end, end.column)),
+            Some((30, 40, 31))
+        );
+        assert_eq!(script.children[0].source_span(), Some((25, 30)));
+
+        // An unclosed one runs to the end of the markup
+        let style = &root.children[1];
+        assert_eq!(style.position.as_deref().unwrap().end, None);
+        assert_eq!(style.source_span(), Some((40, source.len())));
     }
 
-    fn consume_whitespaces(&mut self) {
-        while let Some(next_char) = self.chars.peek() {
-            if *next_char == ' ' || *next_char == '\t' || *next_char == '\n' {
-                self.chars.next().unwrap();
-            } else {
-                break;
+    #[test]
+    fn test_document_source_positions() {
+        let options = ParserOptions {
+            document: true,
+            comments: true,
+            source_positions: true,
+            ..Default::default()
+        };
+        let source = "<!DOCTYPE html>\n<html><head><title>T</title></head>\n<body><!-- c --><p>x</body></html>";
+        let root = HTMLParser::with_options(source, options).parse().unwrap();
+        let spans = |node: &Node| {
+            let position = node.position.as_deref().unwrap();
+            (
+                (position.start.start, position.start.end),
+                position.end.map(|end| (end.start, end.end)),
+            )
+        };
+
+        assert!(root.position.is_none());
+        assert_eq!(spans(&root.children[0]), ((0, 15), None));
+
+        let html = &root.children[1];
+        assert_eq!(spans(html), ((16, 22), Some((79, 86))));
+        let head = &html.children[0];
+        assert_eq!(spans(head), ((22, 28), Some((44, 51))));
+        let body = &html.children[1];
+        assert_eq!(spans(body), ((52, 58), Some((72, 79))));
+        assert_eq!(spans(&body.children[0]), ((58, 68), None));
+        assert_eq!(body.children[0].position.as_deref().unwrap().start.line, 3);
+    }
+
+    #[test]
+    fn test_streaming_source_positions() {
+        let options = ParserOptions {
+            document: true,
+            source_positions: true,
+            ..Default::default()
+        };
+        let source = "\n <title>caf\u{e9}</title><script>if (a < b) {}</script>\r\n<ul>\n<li>one<li>two</ul>\n";
+        let collect = |root: &Node| {
+            let mut positions = Vec::new();
+            let mut pending = vec![root];
+            while let Some(node) = pending.pop() {
+                positions.push(node.position.clone());
+                pending.extend(node.children.iter().rev());
             }
+            positions
+        };
+        let expected = collect(
+            &HTMLParser::with_options(source, options.clone())
+                .parse()
+                .unwrap(),
+        );
+
+        for (split, _) in source.char_indices() {
+            let mut parser = StreamingParser::new(options.clone());
+            parser.feed(&source[..split]);
+            parser.feed(&source[split..]);
+
+            assert_eq!(collect(&parser.finish().unwrap()), expected);
         }
     }
 
-    fn consume_doctype(&mut self, source: &'a str) {
-        let doctype_regex = Regex::new(r#"<!(?i)(doctype)\shtml(.+)?>"#).unwrap();
-        if doctype_regex.is_match(source) {
-            self.consume_until(&'>');
-            self.consume_whitespaces();
-        }
+    #[test]
+    fn test_to_html() {
+        let html = r#"<div id=main class='a "b"'><p>1 &lt; 2 &amp;&amp; caf&eacute;&nbsp;<br><img src="x.png" alt=""></p><script>if (a < b && c) {}</script><my-card><template shadowrootmode="closed"><b>shadow</b></template><i>light</i></my-card></div>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
+        let serialized = root.to_html();
+
+        assert_eq!(
+            serialized,
+            r#"<div class="a &quot;b&quot;" id="main"><p>1 &lt; 2 &amp;&amp; café&nbsp;<br><img alt="" src="x.png"></p><script>if (a < b && c) {}</script><my-card><template shadowrootmode="closed"><b>shadow</b></template><i>light</i></my-card></div>"#
+        );
+        assert_eq!(
+            HTMLParser::new(&serialized).parse().unwrap().to_html(),
+            serialized
+        );
+
+        let paragraph = root.query_selector("p").unwrap().unwrap();
+        assert_eq!(
+            paragraph.inner_html(),
+            r#"1 &lt; 2 &amp;&amp; café&nbsp;<br><img alt="" src="x.png">"#
+        );
+        assert_eq!(Node::text("<&>".to_string()).to_html(), "&lt;&amp;&gt;");
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::fs::read_to_string;
+    #[test]
+    fn test_inner_text() {
+        let html = r#"<html><head><title>Title</title><style>p { color: red }</style></head><body>
+            <h1>  The   title </h1>
+            <div>Some <b>bold</b>
+                text<script>var a = 1;</script></div>
+            <p>First paragraph</p><p>Second<br>line</p>
+            <pre>keep   this
+  as is</pre>
+            <table><tr><td>a</td><td>b</td></tr><tr><td>c</td></tr></table>
+        </body></html>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
 
-    use super::*;
+        assert_eq!(
+            root.inner_text(),
+            "The title\nSome bold text\n\nFirst paragraph\n\nSecond\nline\n\nkeep   this\n  as is\na b\nc"
+        );
+        assert_eq!(
+            root.query_selector("div").unwrap().unwrap().inner_text(),
+            "Some bold text"
+        );
+    }
 
     #[test]
-    fn test_parse_node() {
-        let html = r#"<html data-darkreader-mode="dynamic" data-darkreader-scheme="dark"></html>"#;
-        let mut parser = HTMLParser::new(html);
-        let node = parser.parse().unwrap();
+    fn test_comments_and_doctype() {
+        let html = r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" 'http://www.w3.org/TR/html4/strict.dtd'><!-- before --><html><body><p>a<!-- in -- text -->b</p><?xml-stylesheet href="a.css"?></body></html>"#;
+        let options = ParserOptions {
+            document: true,
+            comments: true,
+            ..Default::default()
+        };
+        let document = HTMLParser::with_options(html, options).parse().unwrap();
+        let types: Vec<NodeType> = document.children.iter().map(Node::node_type).collect();
 
-        assert_eq!(node.data.tag_name, "html");
+        assert_eq!(document.node_type(), NodeType::Document);
         assert_eq!(
-            node.data.attributes.get("data-darkreader-mode"),
-            Some(&"dynamic".to_string())
+            types,
+            [NodeType::Doctype, NodeType::Comment, NodeType::Element]
         );
+        assert_eq!(document.children[0].attr("name"), "html");
         assert_eq!(
-            node.data.attributes.get("data-darkreader-scheme"),
-            Some(&"dark".to_string())
+            document.children[0].attr("public_id"),
+            "-//W3C//DTD HTML 4.01//EN"
         );
+        assert_eq!(
+            document.children[0].attr("system_id"),
+            "http://www.w3.org/TR/html4/strict.dtd"
+        );
+        assert_eq!(
+            document.to_html(),
+            r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd"><!-- before --><html><head></head><body><p>a<!-- in -- text -->b</p><!--?xml-stylesheet href="a.css"?--></body></html>"#
+        );
+
+        // Helpers going through elements and text skip them
+        assert_eq!(document.query_selector_all("*").unwrap().len(), 4);
+        assert_eq!(document.text_nodes(false).len(), 2);
+        assert_eq!(document.inner_text(), "ab");
+
+        // Dropped by default
+        let root = HTMLParser::new(html).parse().unwrap();
+        assert_eq!(root.to_html(), "<html><body><p>ab</p></body></html>");
     }
 
     #[test]
-    fn test_parse_text_content() {
-        let html = r#"<html data-darkreader-mode="dynamic" data-darkreader-scheme="dark">welcome to my page</html>"#;
-        let mut parser = HTMLParser::new(html);
-        let node = parser.parse().unwrap();
+    fn test_void_elements() {
+        let html = r#"<div><hr><p>a<wbr>b</p><table><colgroup><col><col></colgroup></table><video><source src="a"><track src="b"></video><embed src="c"></div>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
 
-        let child = node.children.get(0).unwrap();
+        assert_eq!(root.to_html(), html);
+
+        // `/>` closes foreign and unknown elements only
+        let html = r#"<div><svg><circle r="5"/><path d="M0 0" /></svg><my-icon/><span/>a<x-link href=/>b</x-link></div>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
 
-        assert_eq!(child.data.tag_name, "text".to_string());
         assert_eq!(
-            child.data.attributes.get("content"),
-            Some(&"welcome to my page".to_string())
+            root.to_html(),
+            r#"<div><svg><circle r="5"></circle><path d="M0 0"></path></svg><my-icon></my-icon><span>a<x-link href="/">b</x-link></span></div>"#
         );
+
+        let options = ParserOptions {
+            void_elements: vec!["X-Icon".to_string()],
+            ..Default::default()
+        };
+        let root = HTMLParser::with_options("<div><x-icon>a</div>", options)
+            .parse()
+            .unwrap();
+
+        assert_eq!(root.to_html(), "<div><x-icon></x-icon>a</div>");
     }
 
     #[test]
@@ -374,6 +3115,51 @@ mod tests {
     }
 
     #[test]
+    fn test_element_finders() {
+        let root = HTMLParser::new(
+            r#"<div><p id="intro" class="lead  note">one</p><SPAN class="note" data-kind="x">two</SPAN><p class="leading" data-kind="y" id="intro">three</p><template shadowrootmode="open"><p id="shadow"></p></template></div>"#,
+        )
+        .parse()
+        .unwrap();
+        let texts = |nodes: Vec<&Node>| -> Vec<String> {
+            nodes.iter().map(|node| node.inner_html()).collect()
+        };
+
+        assert_eq!(root.get_element_by_id("intro").unwrap().inner_html(), "one");
+        assert!(root.get_element_by_id("shadow").is_none());
+        assert!(root.get_element_by_id("missing").is_none());
+
+        assert_eq!(
+            texts(root.get_elements_by_class_name("note")),
+            ["one", "two"]
+        );
+        assert_eq!(
+            texts(root.get_elements_by_class_name(" note lead ")),
+            ["one"]
+        );
+        assert!(root.get_elements_by_class_name(" ").is_empty());
+
+        assert_eq!(texts(root.get_elements_by_tag_name("span")), ["two"]);
+        assert_eq!(root.get_elements_by_tag_name("P").len(), 2);
+        assert_eq!(root.get_elements_by_tag_name("*").len(), 3);
+
+        assert_eq!(texts(root.find("DATA-KIND", None)), ["two", "three"]);
+        assert_eq!(texts(root.find("data-kind", Some("y"))), ["three"]);
+        assert!(root.find("content", None).is_empty());
+
+        let root = HTMLParser::new(r#"<div><p DATA-X="1" ID="a" Class="big">upper</p></div>"#)
+            .parse()
+            .unwrap();
+
+        assert_eq!(root.get_element_by_id("a").unwrap().inner_html(), "upper");
+        assert_eq!(texts(root.get_elements_by_class_name("big")), ["upper"]);
+        assert_eq!(texts(root.find("data-x", None)), ["upper"]);
+        assert_eq!(texts(root.find("DATA-X", Some("1"))), ["upper"]);
+        assert_eq!(root.query_selector_all("[data-x]").unwrap().len(), 1);
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_search_text_nodes() {
         let html = r#"<!doctype html><html><head><title>Example Domain</title><meta charset="utf-8"><meta content="text/html; charset=utf-8" http-equiv="Content-type"><meta content="width=device-width,initial-scale=1" name="viewport"></head><body><div><h1>Example Domain</h1><p>This domain is for use in illustrative examples in documents. You may use this domain in literature without prior coordination or asking for permission.</p><p><a>More information...</a></p></div></body></html>"#;
         let mut parser = HTMLParser::new(html);
@@ -417,9 +3203,9 @@ mod tests {
     fn test_consume_read_until() {
         let html = r#"hello world</>"#;
         let mut parser = HTMLParser::new(html);
-        let collected = parser.read_until(vec![&'<']);
+        let collected = parser.read_until(&['<']);
 
-        assert_eq!(collected, "hello world".to_string());
+        assert_eq!(collected, "hello world");
         assert_eq!(parser.chars.next(), Some('<'));
     }
 
@@ -494,6 +3280,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_full_text() {
         let html_str = read_to_string("server/web.html").unwrap();
         let mut parser = HTMLParser::new(&html_str);
@@ -503,4 +3290,144 @@ mod tests {
 
         assert_eq!(nodes.len(), 83);
     }
+
+    // Run with `cargo test --release bench_parse -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_parse_web_fixture() {
+        let html_str = read_to_string("server/web.html").unwrap().repeat(20);
+        let runs = 20;
+        let start = std::time::Instant::now();
+
+        for _ in 0..runs {
+            let options = ParserOptions {
+                document: true,
+                ..Default::default()
+            };
+            HTMLParser::with_options(&html_str, options)
+                .parse()
+                .unwrap();
+        }
+
+        println!(
+            "{} KB parsed in {:?}",
+            html_str.len() / 1024,
+            start.elapsed() / runs
+        );
+    }
+
+    #[test]
+    fn test_noscript_parsed_by_default() {
+        let html = r#"<div><noscript><img src="pixel.gif"></noscript><p>content</p></div>"#;
+        let node = HTMLParser::new(html).parse().unwrap();
+        let noscript = node.children.first().unwrap();
+
+        assert_eq!(node.children.len(), 2);
+        assert_eq!(noscript.children.first().unwrap().data.tag_name, "img");
+        assert!(!noscript.data.in_noscript);
+    }
+
+    #[test]
+    fn test_noscript_flagged() {
+        let html = r#"<div><noscript><p>enable js</p></noscript><p>content</p></div>"#;
+        let options = ParserOptions {
+            noscript: NoscriptMode::Flag,
+            ..Default::default()
+        };
+        let node = HTMLParser::with_options(html, options).parse().unwrap();
+        let noscript = node.children.first().unwrap();
+        let paragraph = noscript.children.first().unwrap();
+
+        assert!(noscript.data.in_noscript);
+        assert!(paragraph.data.in_noscript);
+        assert!(paragraph.children.first().unwrap().data.in_noscript);
+        assert!(!node.children.get(1).unwrap().data.in_noscript);
+    }
+
+    #[test]
+    fn test_noscript_skipped() {
+        let html = r#"<div><noscript><p>enable js</p></noscript><p>content</p></div>"#;
+        let options = ParserOptions {
+            noscript: NoscriptMode::Skip,
+            ..Default::default()
+        };
+        let node = HTMLParser::with_options(html, options).parse().unwrap();
+
+        assert_eq!(node.children.len(), 1);
+        assert_eq!(node.children.first().unwrap().data.tag_name, "p");
+    }
+
+    #[test]
+    fn test_declarative_shadow_root() {
+        let html = r#"<my-card><template shadowrootmode="open"><p>shadow text</p></template><span>light text</span></my-card>"#;
+        let node = HTMLParser::new(html).parse().unwrap();
+        let shadow_root = node.shadow_root.as_ref().unwrap();
+
+        assert_eq!(shadow_root.mode, "open");
+        assert_eq!(shadow_root.children.first().unwrap().data.tag_name, "p");
+        assert_eq!(node.children.len(), 1);
+        assert_eq!(node.children.first().unwrap().data.tag_name, "span");
+    }
+
+    #[test]
+    fn test_shadow_text_extraction() {
+        let html = r#"<my-card><template shadowrootmode="closed"><p>shadow text</p></template><span>light text</span></my-card>"#;
+        let node = HTMLParser::new(html).parse().unwrap();
+
+        let light = node.text_nodes(false);
+        let composed = node.text_nodes(true);
+
+        assert_eq!(light.len(), 1);
+        assert_eq!(light[0].attr("content"), "light text");
+        assert_eq!(composed.len(), 2);
+        assert_eq!(composed[0].attr("content"), "shadow text");
+    }
+
+    #[test]
+    fn test_regular_template_kept() {
+        let html = r#"<div><template shadowrootmode="invalid"><p>inert</p></template></div>"#;
+        let node = HTMLParser::new(html).parse().unwrap();
+
+        assert!(node.shadow_root.is_none());
+        assert_eq!(node.children.first().unwrap().data.tag_name, "template");
+
+        let options = ParserOptions {
+            declarative_shadow_dom: false,
+            ..Default::default()
+        };
+        let html = r#"<div><template shadowrootmode="open"><p>inert</p></template></div>"#;
+        let node = HTMLParser::with_options(html, options).parse().unwrap();
+
+        assert!(node.shadow_root.is_none());
+    }
+
+    #[test]
+    fn test_search_literal() {
+        let html = "<html><body><p>one two one</p><div><span>one</span></div></body></html>";
+        let node = HTMLParser::new(html).parse().unwrap();
+
+        let matches = node.search(&SearchPattern::literal("one"));
+
+        assert_eq!(matches.len(), 3);
+        assert_eq!((matches[0].start, matches[0].end), (0, 3));
+        assert_eq!((matches[1].start, matches[1].end), (8, 11));
+        assert_eq!(matches[0].path, vec!["html", "body", "p"]);
+        assert_eq!(matches[2].path, vec!["html", "body", "div", "span"]);
+        assert_eq!(matches[2].node.attr("content"), "one");
+    }
+
+    #[test]
+    fn test_search_regex() {
+        let html = "<html><p>call 555-1234 or 555-9876</p></html>";
+        let node = HTMLParser::new(html).parse().unwrap();
+
+        let matches = node.search(&SearchPattern::regex(r"\d{3}-\d{4}").unwrap());
+        let found: Vec<&str> = matches
+            .iter()
+            .map(|found| &found.node.attr("content")[found.start..found.end])
+            .collect();
+
+        assert_eq!(found, vec!["555-1234", "555-9876"]);
+        assert!(node.search(&SearchPattern::regex("x*").unwrap()).is_empty());
+    }
 }
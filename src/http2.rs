@@ -0,0 +1,494 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+};
+
+use crate::hpack;
+
+type Err = Box<dyn Error>;
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// Frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// Frame flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+// Settings identifiers
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+
+const DEFAULT_WINDOW_SIZE: u32 = 65_535;
+const MAX_WINDOW_SIZE: u32 = 0x7fff_ffff;
+// Every peer must accept frames up to this size, so we never need to look at SETTINGS_MAX_FRAME_SIZE
+const MAX_FRAME_SIZE: usize = 16_384;
+
+// The only stream we ever open, requests are sent one per connection
+const STREAM_ID: u32 = 1;
+
+pub struct H2Response {
+    pub status: usize,
+    /// In the order received, names may repeat (`set-cookie`)
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+fn read_frame<S: Read>(stream: &mut S) -> Result<Frame, Err> {
+    let mut header = [0u8; 9];
+    stream.read_exact(&mut header)?;
+
+    let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    let stream_id =
+        u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & MAX_WINDOW_SIZE;
+
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload)?;
+
+    Ok(Frame {
+        kind: header[3],
+        flags: header[4],
+        stream_id,
+        payload,
+    })
+}
+
+fn write_frame<S: Write>(
+    stream: &mut S,
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: &[u8],
+) -> Result<(), Err> {
+    let length = (payload.len() as u32).to_be_bytes();
+
+    stream.write_all(&[length[1], length[2], length[3], kind, flags])?;
+    stream.write_all(&stream_id.to_be_bytes())?;
+    stream.write_all(payload)?;
+
+    Ok(())
+}
+
+/// Strips the pad length byte and trailing padding from DATA and HEADERS payloads,
+/// as well as the priority block HEADERS frames may carry.
+fn frame_fragment(frame: &Frame) -> Result<&[u8], Err> {
+    let mut start = 0;
+    let mut end = frame.payload.len();
+
+    if frame.flags & PADDED != 0 {
+        let pad_length = *frame.payload.first().ok_or("Malformed padded frame")? as usize;
+        start += 1;
+        end = end
+            .checked_sub(pad_length)
+            .ok_or("Malformed padded frame")?;
+    }
+
+    if frame.kind == HEADERS && frame.flags & PRIORITY != 0 {
+        start += 5;
+    }
+
+    if start > end {
+        return Err("Malformed frame payload".into());
+    }
+
+    Ok(&frame.payload[start..end])
+}
+
+// State of our single stream while the request is sent and the response read
+struct Exchange {
+    decoder: hpack::Decoder,
+    header_block: Vec<u8>,
+    stream_ended: bool,
+    response: H2Response,
+
+    // Flow control windows the server granted us for sending DATA
+    connection_window: i64,
+    stream_window: i64,
+}
+
+impl Exchange {
+    fn new() -> Self {
+        Self {
+            decoder: hpack::Decoder::new(),
+            header_block: Vec::new(),
+            stream_ended: false,
+            response: H2Response {
+                status: 0,
+                headers: Vec::new(),
+                body: Vec::new(),
+            },
+            connection_window: DEFAULT_WINDOW_SIZE as i64,
+            stream_window: DEFAULT_WINDOW_SIZE as i64,
+        }
+    }
+
+    fn send_window(&self) -> i64 {
+        self.connection_window.min(self.stream_window)
+    }
+
+    // END_STREAM on HEADERS may still be followed by CONTINUATION frames
+    fn is_complete(&self) -> bool {
+        self.stream_ended && self.header_block.is_empty()
+    }
+
+    fn handle<S: Write>(&mut self, stream: &mut S, frame: Frame) -> Result<(), Err> {
+        match frame.kind {
+            SETTINGS if frame.flags & ACK == 0 => {
+                for setting in frame.payload.chunks_exact(6) {
+                    let id = u16::from_be_bytes([setting[0], setting[1]]);
+                    let value =
+                        u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+
+                    // Changing the initial window also resizes windows of open streams
+                    if id == SETTINGS_INITIAL_WINDOW_SIZE {
+                        self.stream_window += value as i64 - DEFAULT_WINDOW_SIZE as i64;
+                    }
+                }
+                write_frame(stream, SETTINGS, ACK, 0, &[])?;
+                stream.flush()?;
+            }
+            PING if frame.flags & ACK == 0 => {
+                write_frame(stream, PING, ACK, 0, &frame.payload)?;
+                stream.flush()?;
+            }
+            WINDOW_UPDATE if frame.payload.len() == 4 => {
+                let increment = u32::from_be_bytes([
+                    frame.payload[0],
+                    frame.payload[1],
+                    frame.payload[2],
+                    frame.payload[3],
+                ]) & MAX_WINDOW_SIZE;
+
+                if frame.stream_id == 0 {
+                    self.connection_window += increment as i64;
+                } else if frame.stream_id == STREAM_ID {
+                    self.stream_window += increment as i64;
+                }
+            }
+            GOAWAY if frame.payload.len() >= 4 => {
+                let last_stream_id = u32::from_be_bytes([
+                    frame.payload[0],
+                    frame.payload[1],
+                    frame.payload[2],
+                    frame.payload[3],
+                ]) & MAX_WINDOW_SIZE;
+
+                // A graceful shutdown still lets our stream finish
+                if last_stream_id < STREAM_ID {
+                    return Err("Server closed the HTTP/2 connection (GOAWAY)".into());
+                }
+            }
+            RST_STREAM if frame.stream_id == STREAM_ID => {
+                return Err("Server reset the HTTP/2 stream".into());
+            }
+            HEADERS | CONTINUATION if frame.stream_id == STREAM_ID => {
+                self.header_block.extend_from_slice(frame_fragment(&frame)?);
+
+                if frame.flags & END_HEADERS != 0 {
+                    for (name, value) in self.decoder.decode(&self.header_block)? {
+                        if name == ":status" {
+                            self.response.status = value.parse()?;
+                            // Informational (1xx) responses are followed by the final one
+                            if self.response.status < 200 {
+                                self.response.headers.clear();
+                            }
+                        } else if !name.starts_with(':') {
+                            self.response.headers.push((name, value));
+                        }
+                    }
+                    self.header_block.clear();
+                }
+            }
+            DATA if frame.stream_id == STREAM_ID => {
+                self.response
+                    .body
+                    .extend_from_slice(frame_fragment(&frame)?);
+            }
+            _ => {}
+        }
+
+        if frame.stream_id == STREAM_ID
+            && frame.flags & END_STREAM != 0
+            && (frame.kind == DATA || frame.kind == HEADERS)
+        {
+            self.stream_ended = true;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends a single request over a fresh HTTP/2 connection and waits for the full response.
+///
+/// `stream` must already be connected (and TLS-negotiated with ALPN "h2" for https).
+pub fn request<S: Read + Write>(
+    stream: &mut S,
+    method: &str,
+    scheme: &str,
+    authority: &str,
+    path: &str,
+    headers: &[(String, String)],
+    body: Option<&mut dyn Read>,
+) -> Result<H2Response, Err> {
+    stream.write_all(PREFACE)?;
+
+    // Turn off server push and open our receive windows as wide as possible so we never
+    // have to send per-frame WINDOW_UPDATEs while reading the body
+    let mut settings = Vec::new();
+    settings.extend(SETTINGS_ENABLE_PUSH.to_be_bytes());
+    settings.extend(0u32.to_be_bytes());
+    settings.extend(SETTINGS_INITIAL_WINDOW_SIZE.to_be_bytes());
+    settings.extend(MAX_WINDOW_SIZE.to_be_bytes());
+    write_frame(stream, SETTINGS, 0, 0, &settings)?;
+    write_frame(
+        stream,
+        WINDOW_UPDATE,
+        0,
+        0,
+        &(MAX_WINDOW_SIZE - DEFAULT_WINDOW_SIZE).to_be_bytes(),
+    )?;
+
+    let mut encoder = Vec::new();
+    hpack::encode(&mut encoder, ":method", method);
+    hpack::encode(&mut encoder, ":scheme", scheme);
+    hpack::encode(&mut encoder, ":authority", authority);
+    hpack::encode(&mut encoder, ":path", path);
+    for (name, value) in headers {
+        let name = name.to_lowercase();
+        // Connection specific headers are forbidden in HTTP/2
+        if [
+            "host",
+            "connection",
+            "keep-alive",
+            "transfer-encoding",
+            "upgrade",
+        ]
+        .contains(&name.as_str())
+        {
+            continue;
+        }
+        hpack::encode(&mut encoder, &name, value);
+    }
+
+    let mut chunks = encoder.chunks(MAX_FRAME_SIZE).peekable();
+    let mut kind = HEADERS;
+    let mut flags = if body.is_none() { END_STREAM } else { 0 };
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_none() {
+            flags |= END_HEADERS;
+        }
+        write_frame(stream, kind, flags, STREAM_ID, chunk)?;
+        kind = CONTINUATION;
+        flags = 0;
+    }
+    stream.flush()?;
+
+    let mut exchange = Exchange::new();
+
+    if let Some(body) = body {
+        send_body(stream, &mut exchange, body)?;
+    }
+
+    while !exchange.is_complete() {
+        let frame = read_frame(stream)?;
+        exchange.handle(stream, frame)?;
+    }
+
+    if exchange.response.status == 0 {
+        return Err("HTTP/2 stream ended without a response".into());
+    }
+
+    Ok(exchange.response)
+}
+
+fn send_body<S: Read + Write>(
+    stream: &mut S,
+    exchange: &mut Exchange,
+    body: &mut dyn Read,
+) -> Result<(), Err> {
+    let mut buffer = vec![0u8; MAX_FRAME_SIZE];
+    let mut pending = 0;
+
+    loop {
+        // The server may answer (e.g. with an error) before the upload is over
+        if exchange.is_complete() {
+            return Ok(());
+        }
+
+        if pending == 0 {
+            pending = body.read(&mut buffer)?;
+
+            if pending == 0 {
+                write_frame(stream, DATA, END_STREAM, STREAM_ID, &[])?;
+                stream.flush()?;
+                return Ok(());
+            }
+        }
+
+        let window = exchange.send_window();
+
+        if window <= 0 {
+            // Wait for the server to grant us more room
+            let frame = read_frame(stream)?;
+            exchange.handle(stream, frame)?;
+            continue;
+        }
+
+        let size = pending.min(window as usize);
+        write_frame(stream, DATA, 0, STREAM_ID, &buffer[..size])?;
+        buffer.copy_within(size..pending, 0);
+        pending -= size;
+        exchange.connection_window -= size as i64;
+        exchange.stream_window -= size as i64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, net::TcpStream, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_prior_knowledge_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+
+            let mut preface = [0u8; 24];
+            sock.read_exact(&mut preface).unwrap();
+            assert_eq!(&preface[..], PREFACE);
+
+            write_frame(&mut sock, SETTINGS, 0, 0, &[]).unwrap();
+
+            let request_headers = loop {
+                let frame = read_frame(&mut sock).unwrap();
+                if frame.kind == HEADERS {
+                    break hpack::Decoder::new().decode(&frame.payload).unwrap();
+                }
+            };
+
+            let mut block = Vec::new();
+            hpack::encode(&mut block, ":status", "200");
+            hpack::encode(&mut block, "content-type", "text/html");
+            write_frame(&mut sock, HEADERS, END_HEADERS, STREAM_ID, &block).unwrap();
+            write_frame(&mut sock, DATA, 0, STREAM_ID, b"<html>").unwrap();
+            write_frame(&mut sock, DATA, END_STREAM, STREAM_ID, b"</html>").unwrap();
+
+            // Keep the connection open until the client is done acknowledging our settings
+            sock.read_to_end(&mut Vec::new()).unwrap();
+
+            request_headers
+        });
+
+        let mut sock = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let headers = vec![("User-Agent".to_string(), "Mozilla/5.0".to_string())];
+        let response = request(
+            &mut sock,
+            "GET",
+            "http",
+            "localhost",
+            "/index.html",
+            &headers,
+            None,
+        )
+        .unwrap();
+        drop(sock);
+        let request_headers = server.join().unwrap();
+
+        assert_eq!(response.status, 200);
+        assert!(response
+            .headers
+            .contains(&("content-type".to_string(), "text/html".to_string())));
+        assert_eq!(response.body, b"<html></html>");
+        assert!(request_headers.contains(&(":path".to_string(), "/index.html".to_string())));
+        assert!(request_headers.contains(&("user-agent".to_string(), "Mozilla/5.0".to_string())));
+    }
+
+    #[test]
+    fn test_request_with_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+
+            let mut preface = [0u8; 24];
+            sock.read_exact(&mut preface).unwrap();
+
+            // Grant enough window for the whole upload once the defaults are used up
+            let mut settings = Vec::new();
+            settings.extend(SETTINGS_INITIAL_WINDOW_SIZE.to_be_bytes());
+            settings.extend(200_000u32.to_be_bytes());
+            write_frame(&mut sock, SETTINGS, 0, 0, &settings).unwrap();
+            write_frame(&mut sock, WINDOW_UPDATE, 0, 0, &200_000u32.to_be_bytes()).unwrap();
+
+            // Collect the uploaded DATA frames until the client ends the stream
+            let mut uploaded = Vec::new();
+            loop {
+                let frame = read_frame(&mut sock).unwrap();
+                if frame.kind == DATA {
+                    assert!(frame.payload.len() <= MAX_FRAME_SIZE);
+                    uploaded.extend_from_slice(&frame.payload);
+                    if frame.flags & END_STREAM != 0 {
+                        break;
+                    }
+                }
+            }
+
+            let mut block = Vec::new();
+            hpack::encode(&mut block, ":status", "201");
+            write_frame(
+                &mut sock,
+                HEADERS,
+                END_HEADERS | END_STREAM,
+                STREAM_ID,
+                &block,
+            )
+            .unwrap();
+            sock.read_to_end(&mut Vec::new()).unwrap();
+
+            uploaded
+        });
+
+        // Larger than a single frame and the default flow control window
+        let payload = vec![b'x'; 100_000];
+        let mut sock = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let response = request(
+            &mut sock,
+            "POST",
+            "http",
+            "localhost",
+            "/upload",
+            &[],
+            Some(&mut payload.as_slice()),
+        )
+        .unwrap();
+
+        drop(sock);
+        let uploaded = server.join().unwrap();
+
+        assert_eq!(response.status, 201);
+        assert_eq!(uploaded, payload);
+    }
+}
@@ -0,0 +1,220 @@
+// Internationalized domain names: the mapping of UTS #46 (https://www.unicode.org/reports/tr46/)
+// that matters in practice (case folding, full-width forms and ideographic dots) and Punycode
+// (https://www.rfc-editor.org/rfc/rfc3492) for the labels that aren't ASCII. Unicode
+// normalization is left out, hosts are expected in NFC like they are typed.
+
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+/// ASCII form of `domain`, like `xn--bcher-kva.example` for `Bücher.example`. `None` when
+/// a label can't be encoded or an `xn--` label isn't valid Punycode.
+pub fn to_ascii(domain: &str) -> Option<String> {
+    // What's left of bytes that weren't UTF-8
+    if domain.contains(char::REPLACEMENT_CHARACTER) {
+        return None;
+    }
+
+    let mapped: String = domain.chars().filter_map(map_char).collect();
+
+    let labels = mapped
+        .split('.')
+        .map(|label| match label.strip_prefix("xn--") {
+            Some(encoded) => decode(encoded).map(|_| label.to_string()),
+            None if label.is_ascii() => Some(label.to_string()),
+            None => encode(label).map(|encoded| format!("xn--{}", encoded)),
+        })
+        .collect::<Option<Vec<String>>>()?;
+
+    Some(labels.join("."))
+}
+
+fn map_char(char: char) -> Option<char> {
+    match char {
+        // Ideographic and full-width full stops
+        '\u{3002}' | '\u{ff0e}' | '\u{ff61}' => Some('.'),
+        // Soft hyphen and zero width space are dropped
+        '\u{ad}' | '\u{200b}' => None,
+        // Full-width ASCII
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(char as u32 - 0xfee0).map(to_lowercase),
+        // Other spaces become ASCII ones, which hosts can't have
+        char if char.is_whitespace() => Some(' '),
+        _ => Some(to_lowercase(char)),
+    }
+}
+
+// Characters that lowercase to more than one (only İ does) keep their first one
+fn to_lowercase(char: char) -> char {
+    char.to_lowercase().next().unwrap_or(char)
+}
+
+fn encode(label: &str) -> Option<String> {
+    let input: Vec<u32> = label.chars().map(|char| char as u32).collect();
+    let mut output: String = label.chars().filter(char::is_ascii).collect();
+
+    let basic_count = output.len() as u32;
+    let mut handled = basic_count;
+    if basic_count > 0 {
+        output.push('-');
+    }
+
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+
+    while (handled as usize) < input.len() {
+        let next = *input.iter().filter(|&&code| code >= n).min()?;
+        delta = delta.checked_add((next - n).checked_mul(handled + 1)?)?;
+        n = next;
+
+        for &code in &input {
+            if code < n {
+                delta = delta.checked_add(1)?;
+            }
+
+            if code == n {
+                let mut q = delta;
+                let mut k = BASE;
+
+                loop {
+                    let t = threshold(k, bias);
+                    if q < t {
+                        break;
+                    }
+                    output.push(digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+
+                output.push(digit(q));
+                bias = adapt(delta, handled + 1, handled == basic_count);
+                delta = 0;
+                handled += 1;
+            }
+        }
+
+        delta = delta.checked_add(1)?;
+        n = n.checked_add(1)?;
+    }
+
+    Some(output)
+}
+
+fn decode(input: &str) -> Option<String> {
+    let (basic, extended) = match input.rfind('-') {
+        Some(index) => (&input[..index], &input[index + 1..]),
+        None => ("", input),
+    };
+
+    if !basic.is_ascii() {
+        return None;
+    }
+
+    let mut output: Vec<char> = basic.chars().collect();
+    let mut digits = extended.chars();
+    let mut n = INITIAL_N;
+    let mut i: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+
+    while !digits.as_str().is_empty() {
+        let old_i = i;
+        let mut weight: u32 = 1;
+        let mut k = BASE;
+
+        loop {
+            let value = digit_value(digits.next()?)?;
+            i = i.checked_add(value.checked_mul(weight)?)?;
+
+            let t = threshold(k, bias);
+            if value < t {
+                break;
+            }
+            weight = weight.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+
+        let length = output.len() as u32 + 1;
+        bias = adapt(i - old_i, length, old_i == 0);
+        n = n.checked_add(i / length)?;
+        i %= length;
+
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+
+    Some(output.into_iter().collect())
+}
+
+fn threshold(k: u32, bias: u32) -> u32 {
+    k.saturating_sub(bias).clamp(T_MIN, T_MAX)
+}
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = match first {
+        true => delta / DAMP,
+        false => delta / 2,
+    };
+    delta += delta / points;
+
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+fn digit(value: u32) -> char {
+    match value {
+        0..=25 => (b'a' + value as u8) as char,
+        _ => (b'0' + (value - 26) as u8) as char,
+    }
+}
+
+fn digit_value(char: char) -> Option<u32> {
+    match char {
+        'a'..='z' => Some(char as u32 - 'a' as u32),
+        'A'..='Z' => Some(char as u32 - 'A' as u32),
+        '0'..='9' => Some(char as u32 - '0' as u32 + 26),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ascii() {
+        assert_eq!(to_ascii("Bücher.example").unwrap(), "xn--bcher-kva.example");
+        assert_eq!(to_ascii("例え。テスト").unwrap(), "xn--r8jz45g.xn--zckzah");
+        assert_eq!(to_ascii("faß.de").unwrap(), "xn--fa-hia.de");
+        assert_eq!(to_ascii("☃.net").unwrap(), "xn--n3h.net");
+        assert_eq!(to_ascii("ＥＸＡＭＰＬＥ.com").unwrap(), "example.com");
+        assert_eq!(
+            to_ascii("xn--bcher-kva.example").unwrap(),
+            "xn--bcher-kva.example"
+        );
+        assert_eq!(to_ascii("xn--zz.example"), None);
+    }
+
+    #[test]
+    fn test_punycode_round_trip() {
+        // Samples from RFC 3492 section 7.1
+        let samples = [
+            ("他们为什么不说中文", "ihqwcrb4cv8a8dqg056pqjye"),
+            ("Pročprostěnemluvíčesky", "Proprostnemluvesky-uyb24dma41a"),
+            ("ひとつ屋根の下2", "2-u9tlzr9756bt3uc0v"),
+        ];
+
+        for (unicode, ascii) in samples {
+            assert_eq!(encode(unicode).unwrap(), ascii);
+            assert_eq!(decode(ascii).unwrap(), unicode);
+        }
+    }
+}
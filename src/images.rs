@@ -0,0 +1,389 @@
+// Images of pages: fetched through the URL layer and decoded from PNG, JPEG or GIF into
+// RGBA pixels, the decoded ones kept in a least recently used cache
+
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use crate::page::Prepare;
+use crate::url::{URLError, URL};
+
+type Err = Box<dyn Error>;
+
+/// How many decoded images a cache keeps by default
+pub const DEFAULT_CACHE_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Row after row, 4 bytes (red, green, blue, alpha) per pixel
+    pub pixels: Vec<u8>,
+}
+
+/// Decodes a PNG, JPEG or GIF (its first frame), recognized by their signature.
+pub fn decode(bytes: &[u8]) -> Result<Image, Err> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        decode_png(bytes).map_err(invalid)
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        decode_jpeg(bytes).map_err(invalid)
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        decode_gif(bytes).map_err(invalid)
+    } else {
+        Err(Box::new(URLError {
+            message: "Unsupported image format".to_string(),
+        }))
+    }
+}
+
+/// Fetches and decodes the image at `url`, `prepare` sets up the request like for pages.
+pub fn fetch_image(url: &str, prepare: Option<&Prepare>) -> Result<Image, Err> {
+    let mut url = URL::new(url.to_string())?;
+    if let Some(prepare) = prepare {
+        prepare(&mut url);
+    }
+
+    url.fetch()?;
+
+    if url.status() >= 400 {
+        return Err(Box::new(URLError {
+            message: format!("Unable to fetch image: {} {}", url.status(), url.reason()),
+        }));
+    }
+
+    decode(url.body())
+}
+
+/// Decoded images by URL, the least recently used ones are dropped past `capacity`.
+pub struct ImageCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    images: HashMap<String, Arc<Image>>,
+    // Least recently used first
+    order: VecDeque<String>,
+}
+
+impl ImageCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// The cached image for `url`, fetched and decoded when there is none. Failures aren't
+    /// cached.
+    pub fn load(&self, url: &str, prepare: Option<&Prepare>) -> Result<Arc<Image>, Err> {
+        if let Some(image) = self.get(url) {
+            return Ok(image);
+        }
+
+        // Not holding the lock while fetching, other images load meanwhile
+        let image = Arc::new(fetch_image(url, prepare)?);
+        self.insert(url, Arc::clone(&image));
+
+        Ok(image)
+    }
+
+    pub fn get(&self, url: &str) -> Option<Arc<Image>> {
+        let mut entries = self.entries.lock().unwrap();
+        let image = Arc::clone(entries.images.get(url)?);
+        entries.touch(url);
+
+        Some(image)
+    }
+
+    pub fn insert(&self, url: &str, image: Arc<Image>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+
+        match entries.images.insert(url.to_string(), image) {
+            Some(_) => entries.touch(url),
+            None => entries.order.push_back(url.to_string()),
+        }
+
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.images.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+}
+
+impl Entries {
+    // Marks `url` as the most recently used
+    fn touch(&mut self, url: &str) {
+        if let Some(index) = self.order.iter().position(|entry| entry == url) {
+            let entry = self.order.remove(index).unwrap();
+            self.order.push_back(entry);
+        }
+    }
+}
+
+fn invalid(error: impl Display) -> Err {
+    Box::new(URLError {
+        message: format!("Invalid image: {}", error),
+    })
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Image, Err> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes expanded, 16 bit samples reduced to 8
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => rgb_to_rgba(&buffer),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|gray| [gray[0], gray[0], gray[0], gray[1]])
+            .collect(),
+        png::ColorType::Grayscale => gray_to_rgba(&buffer),
+        png::ColorType::Indexed => return Err("unexpanded palette".into()),
+    };
+
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+fn decode_jpeg(bytes: &[u8]) -> Result<Image, Err> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    let buffer = decoder.decode()?;
+    let info = decoder.info().ok_or("missing frame header")?;
+
+    let pixels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => rgb_to_rgba(&buffer),
+        jpeg_decoder::PixelFormat::L8 => gray_to_rgba(&buffer),
+        // Big endian samples, the high byte is enough
+        jpeg_decoder::PixelFormat::L16 => buffer
+            .chunks_exact(2)
+            .flat_map(|gray| [gray[0], gray[0], gray[0], 255])
+            .collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => buffer
+            .chunks_exact(4)
+            .flat_map(|cmyk| {
+                let ink = |color: u8| ((255 - color as u32) * (255 - cmyk[3] as u32) / 255) as u8;
+                [ink(cmyk[0]), ink(cmyk[1]), ink(cmyk[2]), 255]
+            })
+            .collect(),
+    };
+
+    Ok(Image {
+        width: info.width as u32,
+        height: info.height as u32,
+        pixels,
+    })
+}
+
+// The first frame drawn at its place on a transparent canvas the size of the image
+fn decode_gif(bytes: &[u8]) -> Result<Image, Err> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+
+    let mut decoder = options.read_info(bytes)?;
+    let width = decoder.width() as usize;
+    let height = decoder.height() as usize;
+    let mut pixels = vec![0; width * height * 4];
+
+    let frame = decoder.read_next_frame()?.ok_or("no frame")?;
+    let frame_width = frame.width as usize;
+
+    for (row, line) in frame.buffer.chunks_exact(frame_width * 4).enumerate() {
+        let y = frame.top as usize + row;
+        let left = frame.left as usize;
+        if y >= height || left >= width {
+            continue;
+        }
+
+        let visible = frame_width.min(width - left) * 4;
+        let start = (y * width + left) * 4;
+        pixels[start..start + visible].copy_from_slice(&line[..visible]);
+    }
+
+    Ok(Image {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    })
+}
+
+fn rgb_to_rgba(buffer: &[u8]) -> Vec<u8> {
+    buffer
+        .chunks_exact(3)
+        .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+        .collect()
+}
+
+fn gray_to_rgba(buffer: &[u8]) -> Vec<u8> {
+    buffer
+        .iter()
+        .flat_map(|&gray| [gray, gray, gray, 255])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_once;
+
+    fn png(width: u32, height: u32, color_type: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+
+        bytes
+    }
+
+    fn gif(width: u16, height: u16, frame: gif::Frame<'_>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let palette = [0, 0, 0, 255, 0, 0];
+        let mut encoder = gif::Encoder::new(&mut bytes, width, height, &palette).unwrap();
+        encoder.write_frame(&frame).unwrap();
+        drop(encoder);
+
+        bytes
+    }
+
+    // An 8x8 baseline JPEG of a single gray block, Huffman tables with just the codes needed
+    fn gray_jpeg() -> Vec<u8> {
+        let segment = |marker: u8, data: &[u8]| {
+            let mut segment = vec![0xff, marker];
+            segment.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+            segment.extend_from_slice(data);
+            segment
+        };
+        let huffman_table = |class: u8| {
+            let mut table = vec![class, 1];
+            table.extend_from_slice(&[0; 15]);
+            table.push(0);
+            table
+        };
+
+        let mut quantization = vec![0];
+        quantization.extend_from_slice(&[1; 64]);
+
+        let mut bytes = vec![0xff, 0xd8];
+        bytes.extend(segment(0xdb, &quantization));
+        bytes.extend(segment(0xc0, &[8, 0, 8, 0, 8, 1, 1, 0x11, 0]));
+        bytes.extend(segment(0xc4, &huffman_table(0x00)));
+        bytes.extend(segment(0xc4, &huffman_table(0x10)));
+        bytes.extend(segment(0xda, &[1, 1, 0, 0, 63, 0]));
+        // A DC difference of 0 then the end of block, padded with ones
+        bytes.extend([0x3f, 0xff, 0xd9]);
+
+        bytes
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let image = decode(&png(2, 1, png::ColorType::Rgb, &[255, 0, 0, 0, 0, 255])).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [255, 0, 0, 255, 0, 0, 255, 255]);
+
+        let image = decode(&png(1, 1, png::ColorType::GrayscaleAlpha, &[7, 128])).unwrap();
+        assert_eq!(image.pixels, [7, 7, 7, 128]);
+
+        assert!(decode(&png(1, 1, png::ColorType::Rgb, &[1, 2, 3])[..20]).is_err());
+    }
+
+    #[test]
+    fn test_decode_jpeg() {
+        let image = decode(&gray_jpeg()).unwrap();
+
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!(image.pixels.len(), 8 * 8 * 4);
+        assert!(image
+            .pixels
+            .chunks(4)
+            .all(|pixel| pixel == [128, 128, 128, 255]));
+    }
+
+    #[test]
+    fn test_decode_gif_first_frame() {
+        let mut frame = gif::Frame::from_indexed_pixels(1, 2, vec![1, 0], None);
+        frame.left = 1;
+        let image = decode(&gif(2, 2, frame)).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(
+            image.pixels,
+            [0, 0, 0, 0, 255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn test_decode_unsupported() {
+        let error = decode(b"<svg></svg>").unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported image format");
+    }
+
+    #[test]
+    fn test_cache_drops_least_recently_used() {
+        let cache = ImageCache::new(2);
+        let image = |width| {
+            Arc::new(Image {
+                width,
+                height: 0,
+                pixels: Vec::new(),
+            })
+        };
+
+        cache.insert("a", image(1));
+        cache.insert("b", image(2));
+        cache.get("a");
+        cache.insert("c", image(3));
+
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a").unwrap().width, 1);
+        assert_eq!(cache.get("c").unwrap().width, 3);
+    }
+
+    #[test]
+    fn test_cache_fetches_once() {
+        let body = png(1, 1, png::ColorType::Rgba, &[1, 2, 3, 4]);
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(&body);
+
+        // A single response, a second request would find nobody listening
+        let (port, _) = serve_once(response);
+
+        let url = format!("http://127.0.0.1:{}/pixel.png", port);
+        let cache = ImageCache::new(DEFAULT_CACHE_CAPACITY);
+        let first = cache.load(&url, None).unwrap();
+        let second = cache.load(&url, None).unwrap();
+
+        assert_eq!(first.pixels, [1, 2, 3, 4]);
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+}
@@ -0,0 +1,502 @@
+// Block and inline layout (https://browser.engineering/layout.html): block boxes stack up
+// vertically at the width of their container, inline content is broken into lines of words
+
+use std::sync::Arc;
+
+use crate::css::Stylesheet;
+use crate::html::{Node, NodeType, Step, BLOCK_ELEMENTS, HIDDEN_ELEMENTS};
+use crate::style::{self, ComputedStyle};
+use crate::text;
+
+const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+// Height of a line relative to the size of its largest font
+const LINE_HEIGHT: f64 = 1.25;
+
+/// Width of some text drawn at a font size, both in pixels.
+pub type Measure<'a> = &'a dyn Fn(&str, f64) -> f64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxKind {
+    /// An element, or an anonymous block holding the lines of inline content
+    Block,
+    Line,
+    /// A word
+    Text,
+}
+
+#[derive(Debug)]
+pub struct LayoutBox {
+    pub kind: BoxKind,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Way from the laid out root to the node of the box, anonymous blocks and lines have the
+    /// one of the block containing them
+    pub path: Vec<Step>,
+    /// Computed style of that node
+    pub style: Arc<ComputedStyle>,
+    /// The word of a text box
+    pub text: Option<String>,
+    pub children: Vec<LayoutBox>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Display {
+    Block,
+    Inline,
+    None,
+}
+
+// A node with its computed style, what layout works on
+struct StyledNode<'a> {
+    node: &'a Node,
+    path: Vec<Step>,
+    style: Arc<ComputedStyle>,
+    display: Display,
+    children: Vec<StyledNode<'a>>,
+}
+
+enum InlineItem<'a> {
+    Word {
+        text: &'a str,
+        /// Whitespace separates it from the previous word
+        space_before: bool,
+        owner: &'a StyledNode<'a>,
+    },
+    LineBreak,
+}
+
+/// Rough width of `text` for a font whose characters take half its size per column, for
+/// when there is nothing to measure actual glyphs with.
+pub fn estimate_width(text: &str, font_size: f64) -> f64 {
+    text::display_width(text) as f64 * font_size * 0.5
+}
+
+/// Lays out `root` as a block as wide as the viewport, `ancestors` are its ancestors from the
+/// outermost down to its parent (for the styles it inherits and the selectors they match).
+pub fn layout(
+    root: &Node,
+    ancestors: &[&Node],
+    stylesheets: &[&Stylesheet],
+    viewport_width: f64,
+    measure: Measure,
+) -> LayoutBox {
+    let parent_style = match ancestors.split_last() {
+        Some((parent, rest)) => style::computed_style(stylesheets, parent, rest),
+        None => ComputedStyle::new(),
+    };
+
+    let styled = style_tree(
+        stylesheets,
+        root,
+        &mut Vec::new(),
+        &mut ancestors.to_vec(),
+        &parent_style,
+    );
+
+    let layout = Layout { measure };
+
+    layout.block(&styled, 0.0, 0.0, viewport_width)
+}
+
+fn style_tree<'a>(
+    stylesheets: &[&Stylesheet],
+    node: &'a Node,
+    path: &mut Vec<Step>,
+    ancestors: &mut Vec<&'a Node>,
+    parent_style: &ComputedStyle,
+) -> StyledNode<'a> {
+    let style = style::computed_style_with_parent(stylesheets, node, ancestors, parent_style);
+    let display = display(node, &style);
+    let mut children = Vec::new();
+
+    if display != Display::None {
+        ancestors.push(node);
+
+        for (index, child) in node.children.iter().enumerate() {
+            path.push(Step::Child(index));
+            children.push(style_tree(stylesheets, child, path, ancestors, &style));
+            path.pop();
+        }
+
+        ancestors.pop();
+    }
+
+    StyledNode {
+        node,
+        path: path.clone(),
+        style: Arc::new(style),
+        display,
+        children,
+    }
+}
+
+fn display(node: &Node, style: &ComputedStyle) -> Display {
+    let tag_name = node.data.tag_name.to_ascii_lowercase();
+
+    match node.node_type() {
+        NodeType::Element => {}
+        NodeType::Text => return Display::Inline,
+        NodeType::Comment | NodeType::Doctype => return Display::None,
+        NodeType::Document => return Display::Block,
+    }
+
+    if HIDDEN_ELEMENTS.contains(&tag_name.as_str()) {
+        return Display::None;
+    }
+
+    match style
+        .get("display")
+        .map(|display| display.to_ascii_lowercase())
+    {
+        Some(display) if display == "none" => Display::None,
+        Some(display) if display.starts_with("inline") => Display::Inline,
+        Some(_) => Display::Block,
+        None if BLOCK_ELEMENTS.contains(&tag_name.as_str()) => Display::Block,
+        None => Display::Inline,
+    }
+}
+
+/// Font size in pixels, the default one unless `font-size` is given in pixels.
+pub fn font_size(style: &ComputedStyle) -> f64 {
+    style
+        .get("font-size")
+        .and_then(|size| style::pixels(size))
+        .unwrap_or(DEFAULT_FONT_SIZE)
+}
+
+struct Layout<'a> {
+    measure: Measure<'a>,
+}
+
+impl Layout<'_> {
+    fn block(&self, styled: &StyledNode, x: f64, y: f64, width: f64) -> LayoutBox {
+        let mut children = Vec::new();
+        let mut cursor_y = y;
+        let mut inline_run = Vec::new();
+
+        let visible = styled
+            .children
+            .iter()
+            .filter(|child| child.display != Display::None);
+
+        // Inline children next to blocks are wrapped in anonymous blocks
+        for child in visible {
+            if child.display == Display::Inline {
+                inline_run.push(child);
+                continue;
+            }
+
+            if let Some(anonymous) = self.anonymous_block(styled, &inline_run, x, cursor_y, width) {
+                cursor_y += anonymous.height;
+                children.push(anonymous);
+            }
+            inline_run.clear();
+
+            let block = self.block(child, x, cursor_y, width);
+            cursor_y += block.height;
+            children.push(block);
+        }
+
+        if children.is_empty() {
+            children = self.lines(styled, &inline_run, x, y, width);
+            cursor_y += children.iter().map(|line| line.height).sum::<f64>();
+        } else if let Some(anonymous) =
+            self.anonymous_block(styled, &inline_run, x, cursor_y, width)
+        {
+            cursor_y += anonymous.height;
+            children.push(anonymous);
+        }
+
+        LayoutBox {
+            kind: BoxKind::Block,
+            x,
+            y,
+            width,
+            height: cursor_y - y,
+            path: styled.path.clone(),
+            style: styled.style.clone(),
+            text: None,
+            children,
+        }
+    }
+
+    // `None` when the run has nothing to show, like the whitespace between two blocks
+    fn anonymous_block(
+        &self,
+        container: &StyledNode,
+        run: &[&StyledNode],
+        x: f64,
+        y: f64,
+        width: f64,
+    ) -> Option<LayoutBox> {
+        let lines = self.lines(container, run, x, y, width);
+
+        if lines.is_empty() {
+            return None;
+        }
+
+        Some(LayoutBox {
+            kind: BoxKind::Block,
+            x,
+            y,
+            width,
+            height: lines.iter().map(|line| line.height).sum(),
+            path: container.path.clone(),
+            style: container.style.clone(),
+            text: None,
+            children: lines,
+        })
+    }
+
+    fn lines(
+        &self,
+        container: &StyledNode,
+        run: &[&StyledNode],
+        x: f64,
+        y: f64,
+        width: f64,
+    ) -> Vec<LayoutBox> {
+        let mut items = Vec::new();
+        let mut space_before = false;
+
+        for styled in run {
+            inline_items(styled, &mut space_before, &mut items);
+        }
+
+        let mut lines = Vec::new();
+        let mut words: Vec<LayoutBox> = Vec::new();
+        let mut cursor_x = x;
+        let mut cursor_y = y;
+
+        let mut finish_line = |words: &mut Vec<LayoutBox>, cursor_y: &mut f64| {
+            let line = self.line(container, std::mem::take(words), x, *cursor_y, width);
+            *cursor_y += line.height;
+            lines.push(line);
+        };
+
+        for item in items {
+            let InlineItem::Word {
+                text,
+                space_before,
+                owner,
+            } = item
+            else {
+                finish_line(&mut words, &mut cursor_y);
+                cursor_x = x;
+                continue;
+            };
+
+            let size = font_size(&owner.style);
+            let word_width = (self.measure)(text, size);
+            let mut word_x = cursor_x;
+
+            if space_before && !words.is_empty() {
+                word_x += (self.measure)(" ", size);
+            }
+
+            if !words.is_empty() && word_x + word_width > x + width {
+                finish_line(&mut words, &mut cursor_y);
+                word_x = x;
+            }
+
+            words.push(LayoutBox {
+                kind: BoxKind::Text,
+                x: word_x,
+                y: 0.0,
+                width: word_width,
+                height: size * LINE_HEIGHT,
+                path: owner.path.clone(),
+                style: owner.style.clone(),
+                text: Some(text.to_string()),
+                children: Vec::new(),
+            });
+            cursor_x = word_x + word_width;
+        }
+
+        if !words.is_empty() {
+            finish_line(&mut words, &mut cursor_y);
+        }
+
+        lines
+    }
+
+    fn line(
+        &self,
+        container: &StyledNode,
+        mut words: Vec<LayoutBox>,
+        x: f64,
+        y: f64,
+        width: f64,
+    ) -> LayoutBox {
+        // Never lower than a line of the container's own text, which is all an empty line
+        // (from a `<br>`) has
+        let height = words
+            .iter()
+            .map(|word| word.height)
+            .fold(font_size(&container.style) * LINE_HEIGHT, f64::max);
+
+        // Smaller words sit at the bottom of the line, roughly where the baseline is
+        for word in &mut words {
+            word.y = y + height - word.height;
+        }
+
+        LayoutBox {
+            kind: BoxKind::Line,
+            x,
+            y,
+            width,
+            height,
+            path: container.path.clone(),
+            style: container.style.clone(),
+            text: None,
+            children: words,
+        }
+    }
+}
+
+// Words of `styled` in order, whitespace collapsed the way `white-space: normal` does
+fn inline_items<'a>(
+    styled: &'a StyledNode<'a>,
+    space_before: &mut bool,
+    items: &mut Vec<InlineItem<'a>>,
+) {
+    if styled.display == Display::None {
+        return;
+    }
+
+    if styled.node.data.tag_name.eq_ignore_ascii_case("br") {
+        items.push(InlineItem::LineBreak);
+        *space_before = false;
+        return;
+    }
+
+    let Some(content) = styled
+        .node
+        .data
+        .attributes
+        .get("content")
+        .filter(|_| styled.node.data.tag_name == "text")
+    else {
+        for child in &styled.children {
+            inline_items(child, space_before, items);
+        }
+        return;
+    };
+
+    *space_before |= content.starts_with(char::is_whitespace);
+
+    for word in content.split_whitespace() {
+        items.push(InlineItem::Word {
+            text: word,
+            space_before: *space_before,
+            owner: styled,
+        });
+        *space_before = true;
+    }
+
+    *space_before = content.ends_with(char::is_whitespace);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::{HTMLParser, ParserOptions};
+
+    use super::*;
+
+    // Every character is 10px wide at any size
+    fn fixed_width(text: &str, _font_size: f64) -> f64 {
+        text.chars().count() as f64 * 10.0
+    }
+
+    fn words(layout_box: &LayoutBox) -> Vec<(String, f64, f64)> {
+        let mut found = Vec::new();
+
+        if let Some(text) = &layout_box.text {
+            found.push((text.clone(), layout_box.x, layout_box.y));
+        }
+
+        for child in &layout_box.children {
+            found.extend(words(child));
+        }
+
+        found
+    }
+
+    #[test]
+    fn test_block_layout() {
+        let html = r#"<html><head><title>t</title><style>.hidden { display: none } h1 { font-size: 32px }</style></head><body><h1>Title</h1>
+            <p class="hidden">gone</p>
+            <div>aa <span>bb</span><p>cc</p>dd</div></body></html>"#;
+        let options = ParserOptions {
+            document: true,
+            ..Default::default()
+        };
+        let root = HTMLParser::with_options(html, options).parse().unwrap();
+        let stylesheets = root.stylesheets();
+        let stylesheets: Vec<&Stylesheet> = stylesheets.iter().collect();
+        let root_box = layout(&root, &[], &stylesheets, 200.0, &fixed_width);
+
+        assert_eq!(root_box.height, 40.0 + 20.0 * 3.0);
+        assert_eq!(
+            words(&root_box),
+            [
+                ("Title".to_string(), 0.0, 0.0),
+                ("aa".to_string(), 0.0, 40.0),
+                ("bb".to_string(), 30.0, 40.0),
+                ("cc".to_string(), 0.0, 60.0),
+                ("dd".to_string(), 0.0, 80.0),
+            ]
+        );
+
+        // body > div, whose inline content around the paragraph is wrapped
+        let div = &root_box.children[0].children[1];
+        let kinds: Vec<BoxKind> = div.children.iter().map(|child| child.kind).collect();
+        assert_eq!(kinds, [BoxKind::Block, BoxKind::Block, BoxKind::Block]);
+        assert_eq!(div.children[0].path, div.path);
+        assert_eq!(root.descendant(&div.path).unwrap().data.tag_name, "div");
+    }
+
+    #[test]
+    fn test_line_breaking() {
+        let html = "<p>one two three<b>four</b> five<br><br>six</p>";
+        let root = HTMLParser::new(html).parse().unwrap();
+        let root_box = layout(&root, &[], &[], 100.0, &fixed_width);
+
+        assert_eq!(
+            words(&root_box),
+            [
+                ("one".to_string(), 0.0, 0.0),
+                ("two".to_string(), 40.0, 0.0),
+                ("three".to_string(), 0.0, 20.0),
+                ("four".to_string(), 50.0, 20.0),
+                ("five".to_string(), 0.0, 40.0),
+                ("six".to_string(), 0.0, 80.0),
+            ]
+        );
+        assert_eq!(root_box.children.len(), 5);
+        assert_eq!(root_box.height, 100.0);
+
+        // A word longer than the line still gets one of its own
+        let root = HTMLParser::new("<p>a extraordinarily b</p>")
+            .parse()
+            .unwrap();
+        let root_box = layout(&root, &[], &[], 100.0, &fixed_width);
+        assert_eq!(root_box.children.len(), 3);
+    }
+
+    #[test]
+    fn test_inherited_font_size() {
+        let html = r#"<div style="font-size: 20px"><p>a <small style="font-size: 50%">b</small></p></div>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
+        let root_box = layout(&root, &[], &[], 100.0, &estimate_width);
+        let line = &root_box.children[0].children[0];
+
+        assert_eq!(line.height, 25.0);
+        assert_eq!(line.children[0].height, 25.0);
+        assert_eq!(line.children[1].y, 12.5);
+        assert_eq!(line.children[1].style["font-size"], "10px");
+    }
+}
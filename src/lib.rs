@@ -1,6 +1,44 @@
+mod a11y;
+mod auth;
 mod bindings;
+mod body;
+mod cache;
+mod charset;
+mod compression;
+mod cookie;
+mod crawler;
+mod css;
+mod data_url;
+mod date;
+mod download;
+mod entities;
+mod filter;
+mod hpack;
 mod html;
+mod http2;
+mod idna;
+mod images;
+mod layout;
+mod mime;
+mod page;
+mod paint;
+mod pool;
+mod proxy;
+mod robots;
+#[cfg(feature = "javascript")]
+mod script;
+mod selector;
+mod sitemap;
+mod style;
+mod tab;
+#[cfg(test)]
+mod test_server;
+mod text;
+mod tls;
 mod url;
+mod whatwg_url;
+mod xhtml;
+mod xml;
 
 use bindings::*;
 use pyo3::prelude::*;
@@ -17,6 +55,34 @@ fn ewb(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
     m.add_function(wrap_pyfunction!(load, m)?)?;
     m.add_function(wrap_pyfunction!(request, m)?)?;
+    m.add_function(wrap_pyfunction!(fetch, m)?)?;
+    m.add_function(wrap_pyfunction!(fetch_many, m)?)?;
     m.add_function(wrap_pyfunction!(find_text_nodes, m)?)?;
+    m.add_function(wrap_pyfunction!(load_page, m)?)?;
+    m.add_function(wrap_pyfunction!(load_xml, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_events, m)?)?;
+    m.add_function(wrap_pyfunction!(fetch_sitemap, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_image, m)?)?;
+    m.add_function(wrap_pyfunction!(bindings::download, m)?)?;
+    #[cfg(feature = "javascript")]
+    m.add_function(wrap_pyfunction!(run_scripts, m)?)?;
+    m.add_function(wrap_pyfunction!(crawl, m)?)?;
+    m.add_function(wrap_pyfunction!(join, m)?)?;
+    m.add_function(wrap_pyfunction!(percent_encode, m)?)?;
+    m.add_function(wrap_pyfunction!(percent_decode, m)?)?;
+    m.add_function(wrap_pyfunction!(grapheme_len, m)?)?;
+    m.add_function(wrap_pyfunction!(display_width, m)?)?;
+    m.add_function(wrap_pyfunction!(truncate, m)?)?;
+    m.add_class::<PyNode>()?;
+    m.add_class::<PySession>()?;
+    m.add_class::<PyCookieJar>()?;
+    m.add_class::<PyURL>()?;
+    m.add_class::<PyStylesheet>()?;
+    m.add_class::<PyStreamingParser>()?;
+    m.add_class::<PyParserOptions>()?;
+    m.add_class::<PyTab>()?;
+    m.add("ConnectError", m.py().get_type::<ConnectError>())?;
+    m.add("TimeoutError", m.py().get_type::<TimeoutError>())?;
+    m.add("BlockedError", m.py().get_type::<BlockedError>())?;
     Ok(())
 }
@@ -0,0 +1,310 @@
+// Subset of the WHATWG MIME Sniffing standard (https://mimesniff.spec.whatwg.org/)
+
+// Only this many leading bytes are looked at by the standard
+pub(crate) const SNIFF_LENGTH: usize = 1445;
+
+// Types servers send when they don't know better, these are always sniffed
+static UNKNOWN_TYPES: [&str; 3] = ["unknown/unknown", "application/unknown", "*/*"];
+
+// HTML patterns, matched case-insensitively after leading whitespace and
+// only when followed by a tag-terminating byte (space or >)
+static HTML_PATTERNS: [&[u8]; 17] = [
+    b"<!DOCTYPE HTML",
+    b"<HTML",
+    b"<HEAD",
+    b"<SCRIPT",
+    b"<IFRAME",
+    b"<H1",
+    b"<DIV",
+    b"<FONT",
+    b"<TABLE",
+    b"<A",
+    b"<STYLE",
+    b"<TITLE",
+    b"<B",
+    b"<BODY",
+    b"<BR",
+    b"<P",
+    b"<!--",
+];
+
+static IMAGE_PATTERNS: [(&[u8], &str); 7] = [
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"\x00\x00\x02\x00", "image/x-icon"),
+    (b"BM", "image/bmp"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+];
+
+static ARCHIVE_PATTERNS: [(&[u8], &str); 3] = [
+    (b"\x1f\x8b\x08", "application/x-gzip"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"Rar!\x1a\x07\x00", "application/x-rar-compressed"),
+];
+
+// Types of local files, keyed by lowercase extension
+static EXTENSIONS: [(&str, &str); 20] = [
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("xhtml", "application/xhtml+xml"),
+    ("xml", "text/xml"),
+    ("txt", "text/plain"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/x-gzip"),
+];
+
+/// How a resource should be handled once fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// Markup to run through the HTML parser
+    Document,
+    /// Anything human readable that is displayed as is
+    Text,
+    /// Binary content (images, archives, ...) that is handed back untouched
+    Download,
+}
+
+impl ResourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResourceKind::Document => "document",
+            ResourceKind::Text => "text",
+            ResourceKind::Download => "download",
+        }
+    }
+}
+
+/// Drops parameters (e.g. charset) and normalizes the case of a Content-Type value.
+pub fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase()
+}
+
+/// Computes the MIME type of a response from the Content-Type the server supplied (if any)
+/// and the leading bytes of the body.
+pub fn sniff(content_type: Option<&str>, body: &[u8]) -> String {
+    let body = &body[..body.len().min(SNIFF_LENGTH)];
+
+    let supplied = match content_type.map(essence) {
+        Some(supplied) if !supplied.is_empty() && supplied.contains('/') => supplied,
+        _ => return sniff_unknown(body),
+    };
+
+    if UNKNOWN_TYPES.contains(&supplied.as_str()) {
+        return sniff_unknown(body);
+    }
+
+    // Plenty of servers label everything text/plain, binary bodies are caught here
+    if supplied == "text/plain" {
+        return sniff_text_or_binary(body);
+    }
+
+    if supplied.starts_with("image/") {
+        if let Some(image) = match_pattern(&IMAGE_PATTERNS, body) {
+            return image.to_string();
+        }
+    }
+
+    supplied
+}
+
+/// Decides what to do with a resource of the given MIME type.
+pub fn classify(mime_type: &str) -> ResourceKind {
+    let mime_type = essence(mime_type);
+
+    if mime_type == "text/html" || mime_type == "application/xhtml+xml" {
+        return ResourceKind::Document;
+    }
+
+    if mime_type.starts_with("text/")
+        || mime_type.ends_with("+xml")
+        || mime_type.ends_with("+json")
+        || [
+            "application/json",
+            "application/xml",
+            "application/javascript",
+        ]
+        .contains(&mime_type.as_str())
+    {
+        return ResourceKind::Text;
+    }
+
+    ResourceKind::Download
+}
+
+/// Guesses a MIME type from the extension of a file name, `None` leaves it to sniffing.
+pub fn from_extension(path: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let (_, extension) = name.rsplit_once('.')?;
+    let extension = extension.to_lowercase();
+
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
+
+fn sniff_unknown(body: &[u8]) -> String {
+    let start = body
+        .iter()
+        .position(|byte| !is_whitespace(*byte))
+        .unwrap_or(body.len());
+    let trimmed = &body[start..];
+
+    for pattern in HTML_PATTERNS {
+        if trimmed.len() > pattern.len()
+            && trimmed[..pattern.len()].eq_ignore_ascii_case(pattern)
+            && [b' ', b'>'].contains(&trimmed[pattern.len()])
+        {
+            return "text/html".to_string();
+        }
+    }
+
+    if trimmed.starts_with(b"<?xml") {
+        return "text/xml".to_string();
+    }
+
+    if body.starts_with(b"%PDF-") {
+        return "application/pdf".to_string();
+    }
+
+    sniff_text_or_binary(body)
+}
+
+fn sniff_text_or_binary(body: &[u8]) -> String {
+    // UTF-16BE, UTF-16LE and UTF-8 byte order marks
+    if body.starts_with(b"\xfe\xff")
+        || body.starts_with(b"\xff\xfe")
+        || body.starts_with(b"\xef\xbb\xbf")
+    {
+        return "text/plain".to_string();
+    }
+
+    if !body.iter().any(|byte| is_binary_data(*byte)) {
+        return "text/plain".to_string();
+    }
+
+    if let Some(mime_type) =
+        match_pattern(&IMAGE_PATTERNS, body).or_else(|| match_pattern(&ARCHIVE_PATTERNS, body))
+    {
+        return mime_type.to_string();
+    }
+
+    if body.len() >= 14 && &body[..4] == b"RIFF" && &body[8..14] == b"WEBPVP" {
+        return "image/webp".to_string();
+    }
+
+    "application/octet-stream".to_string()
+}
+
+fn match_pattern(patterns: &[(&[u8], &'static str)], body: &[u8]) -> Option<&'static str> {
+    patterns
+        .iter()
+        .find(|(pattern, _)| body.starts_with(pattern))
+        .map(|(_, mime_type)| *mime_type)
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    [0x09, 0x0a, 0x0c, 0x0d, 0x20].contains(&byte)
+}
+
+fn is_binary_data(byte: u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0b | 0x0e..=0x1a | 0x1c..=0x1f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_html_without_content_type() {
+        assert_eq!(
+            sniff(None, b"\n  <!doctype html><html></html>"),
+            "text/html"
+        );
+        assert_eq!(sniff(None, b"<p>paragraph</p>"), "text/html");
+        assert_eq!(sniff(Some("*/*"), b"<!-- comment -->"), "text/html");
+    }
+
+    #[test]
+    fn test_sniff_requires_tag_terminator() {
+        // <pre does not match the <p pattern
+        assert_eq!(sniff(None, b"<pre>code</pre>"), "text/plain");
+    }
+
+    #[test]
+    fn test_sniff_images() {
+        assert_eq!(
+            sniff(None, b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"),
+            "image/png"
+        );
+        assert_eq!(sniff(None, b"GIF89a\x01\x00\x01\x00\x00"), "image/gif");
+        assert_eq!(
+            sniff(Some("text/plain"), b"\xff\xd8\xff\xe0\x00\x10JFIF"),
+            "image/jpeg"
+        );
+    }
+
+    #[test]
+    fn test_sniff_text_or_binary() {
+        assert_eq!(
+            sniff(Some("text/plain; charset=utf-8"), b"hello"),
+            "text/plain"
+        );
+        assert_eq!(
+            sniff(Some("text/plain"), b"\x00\x01\x02\x03"),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_supplied_type_is_kept() {
+        assert_eq!(
+            sniff(Some("Text/HTML; charset=UTF-8"), b"hello"),
+            "text/html"
+        );
+        assert_eq!(sniff(Some("application/json"), b"{}"), "application/json");
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("text/html"), ResourceKind::Document);
+        assert_eq!(classify("text/css"), ResourceKind::Text);
+        assert_eq!(classify("application/rss+xml"), ResourceKind::Text);
+        assert_eq!(classify("image/png"), ResourceKind::Download);
+        assert_eq!(classify("application/octet-stream"), ResourceKind::Download);
+    }
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(
+            from_extension("/tmp/fixtures/Index.HTML"),
+            Some("text/html")
+        );
+        assert_eq!(
+            from_extension("/tmp/archive.tar.gz"),
+            Some("application/x-gzip")
+        );
+        assert_eq!(from_extension("/tmp/v1.0/README"), None);
+        assert_eq!(from_extension("/tmp/data.bin"), None);
+    }
+}
@@ -0,0 +1,652 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use encoding_rs::{Decoder, Encoding};
+
+use crate::charset;
+use crate::filter::Destination;
+use crate::html::{HTMLParser, Node, NodeData, ParserOptions, StreamingParser};
+use crate::mime::{self, ResourceKind};
+use crate::url::{BodyEvent, DEFAULT_MAX_REDIRECTS, URL};
+
+type Err = Box<dyn Error>;
+
+const DEFAULT_MAX_REFRESH_HOPS: usize = 5;
+
+/// Sets up each request of a page load, e.g. to share the connections, cookies and cache
+/// of a session.
+pub type Prepare = Arc<dyn Fn(&mut URL) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct LoadOptions {
+    /// How many `<meta http-equiv="refresh">` redirects to follow, 0 disables them
+    pub max_refresh_hops: usize,
+    /// How many HTTP redirects to follow per fetch, 0 disables them
+    pub max_redirects: usize,
+    pub prepare: Option<Prepare>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            max_refresh_hops: DEFAULT_MAX_REFRESH_HOPS,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            prepare: None,
+        }
+    }
+}
+
+/// A fetched resource, parsed when it turned out to be an HTML document.
+#[derive(Clone)]
+pub struct Page {
+    pub url: String,
+    pub mime_type: String,
+    pub kind: ResourceKind,
+    /// What the body is transcoded from to get text
+    pub encoding: &'static Encoding,
+    pub body: Vec<u8>,
+    pub document: Option<Node>,
+    /// URLs that redirected us here (HTTP and meta refresh redirects alike), oldest first
+    pub redirects: Vec<String>,
+}
+
+/// Which element a link of a document comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    /// `<a href>` and `<area href>`
+    Anchor,
+    /// `<img src>`
+    Image,
+    /// `<link href>`, stylesheets, icons, preloads, ...
+    Link,
+    /// `<script src>`
+    Script,
+}
+
+impl LinkKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkKind::Anchor => "anchor",
+            LinkKind::Image => "image",
+            LinkKind::Link => "link",
+            LinkKind::Script => "script",
+        }
+    }
+
+    // The kind of link an element is and the attribute holding its URL
+    fn of(tag_name: &str) -> Option<(Self, &'static str)> {
+        match tag_name {
+            "a" | "area" => Some((LinkKind::Anchor, "href")),
+            "img" => Some((LinkKind::Image, "src")),
+            "link" => Some((LinkKind::Link, "href")),
+            "script" => Some((LinkKind::Script, "src")),
+            _ => None,
+        }
+    }
+}
+
+/// An absolute URL a document points to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub url: String,
+}
+
+/// Parsed `content` of a `<meta http-equiv="refresh">` element.
+#[derive(Debug, PartialEq)]
+pub struct MetaRefresh {
+    pub delay: u32,
+    /// `None` means reloading the current document
+    pub url: Option<String>,
+}
+
+impl MetaRefresh {
+    /// Parses a refresh declaration like `0; url='/next'` following the HTML spec's
+    /// "shared declarative refresh steps".
+    pub fn parse(content: &str) -> Option<Self> {
+        let content = content.trim_start();
+        let digits = content
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(content.len());
+
+        if digits == 0 && !content.starts_with('.') {
+            return None;
+        }
+
+        let delay = content[..digits].parse().unwrap_or(0);
+
+        // Fractional part is ignored
+        let rest = content[digits..].trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let rest = rest.trim_start();
+        let rest = rest
+            .strip_prefix(';')
+            .or_else(|| rest.strip_prefix(','))
+            .unwrap_or(rest)
+            .trim_start();
+
+        if rest.is_empty() {
+            return Some(Self { delay, url: None });
+        }
+
+        let mut url = rest;
+        if url.len() >= 3 && url[..3].eq_ignore_ascii_case("url") {
+            let after = url[3..].trim_start();
+            if let Some(after) = after.strip_prefix('=') {
+                url = after.trim_start();
+            }
+        }
+
+        if let Some(quote) = url.chars().next().filter(|c| *c == '\'' || *c == '"') {
+            url = &url[1..];
+            if let Some(end) = url.find(quote) {
+                url = &url[..end];
+            }
+        }
+
+        let url = url.trim();
+
+        Some(Self {
+            delay,
+            url: (!url.is_empty()).then(|| url.to_string()),
+        })
+    }
+
+    /// Looks for the first refresh declaration in a parsed document.
+    pub fn find(node: &Node) -> Option<Self> {
+        if node.data.tag_name.eq_ignore_ascii_case("meta") {
+            let attribute = |name: &str| {
+                node.data
+                    .attributes
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value)
+            };
+
+            if attribute("http-equiv").is_some_and(|value| value.eq_ignore_ascii_case("refresh")) {
+                if let Some(refresh) = attribute("content").and_then(|content| Self::parse(content))
+                {
+                    return Some(refresh);
+                }
+            }
+        }
+
+        node.children.iter().find_map(Self::find)
+    }
+}
+
+impl Page {
+    /// Body as text, `None` for downloads.
+    pub fn text(&self) -> Option<String> {
+        match self.kind {
+            ResourceKind::Download => None,
+            _ => Some(self.encoding.decode(&self.body).0.into_owned()),
+        }
+    }
+
+    /// Text of the document's `<title>`, whitespace collapsed. `None` without one.
+    pub fn title(&self) -> Option<String> {
+        let title = self.document.as_ref()?.query_selector("title").ok()??;
+        let text: Vec<&str> = title
+            .children
+            .iter()
+            .filter_map(|child| child.data.attributes.get("content"))
+            .flat_map(|content| content.split_whitespace())
+            .collect();
+
+        Some(text.join(" "))
+    }
+
+    /// What the links of the page are relative to, its `<base href>` when it has one.
+    pub fn base_url(&self) -> String {
+        match &self.document {
+            Some(document) => base_url(document, &self.url),
+            None => self.url.clone(),
+        }
+    }
+
+    /// Links of the document, see [`links`].
+    pub fn links(&self) -> Vec<Link> {
+        match &self.document {
+            Some(document) => links(document, &self.url),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// What the links of `document`, loaded from `url`, are relative to.
+pub fn base_url(document: &Node, url: &str) -> String {
+    let base = document.query_selector("base[href]").ok().flatten();
+
+    match (base, URL::new(url.to_string())) {
+        (Some(base), Ok(url)) => url.resolve(base.attr("href")),
+        _ => url.to_string(),
+    }
+}
+
+/// URLs of the `a`/`area`, `img`, `link` and `script` elements of `document`, in document
+/// order and resolved against its base. Ones that don't make a valid URL are left out.
+pub fn links(document: &Node, url: &str) -> Vec<Link> {
+    let base = base_url(document, url);
+    let mut links = Vec::new();
+    collect_links(document, &base, &mut links);
+
+    links
+}
+
+fn collect_links(node: &Node, base: &str, links: &mut Vec<Link>) {
+    for child in &node.children {
+        let found = LinkKind::of(&child.data.tag_name.to_ascii_lowercase())
+            .and_then(|(kind, attribute)| Some((kind, child.data.attributes.get(attribute)?)));
+
+        if let Some((kind, reference)) = found {
+            if let Ok(url) = URL::join(base, reference) {
+                links.push(Link { kind, url });
+            }
+        }
+
+        collect_links(child, base, links);
+    }
+}
+
+/// Fetches `url` and decides from its (sniffed) MIME type whether to parse it, keep it as
+/// text or hand it back as a download.
+pub fn load_page(url: &str) -> Result<Page, Err> {
+    load_page_with(url, &LoadOptions::default())
+}
+
+pub fn load_page_with(url: &str, options: &LoadOptions) -> Result<Page, Err> {
+    let mut page = fetch_page(url, options)?;
+    let mut redirects = std::mem::take(&mut page.redirects);
+
+    for _ in 0..options.max_refresh_hops {
+        let target = match page.document.as_ref().and_then(MetaRefresh::find) {
+            Some(MetaRefresh {
+                url: Some(target), ..
+            }) => URL::new(page.url.clone())?.resolve(&target),
+            _ => break,
+        };
+
+        // Pages refreshing to themselves (or looping) are left alone
+        if target == page.url || redirects.contains(&target) {
+            break;
+        }
+
+        redirects.push(page.url.clone());
+        page = fetch_page(&target, options)?;
+        redirects.append(&mut page.redirects);
+    }
+
+    page.redirects = redirects;
+
+    Ok(page)
+}
+
+fn fetch_page(url: &str, options: &LoadOptions) -> Result<Page, Err> {
+    let mut url = URL::new(url.to_string())?;
+    if let Some(prepare) = &options.prepare {
+        prepare(&mut url);
+    }
+    url.set_destination(Destination::Document);
+    url.set_max_redirects(options.max_redirects);
+
+    let stream = Arc::new(Mutex::new(DocumentStream::default()));
+    if !url.is_view_source() {
+        let stream = Arc::clone(&stream);
+        url.set_body_observer(Box::new(move |event| {
+            stream.lock().unwrap().observe(event);
+            Ok(())
+        }));
+    }
+
+    url.fetch()?;
+
+    let mime_type = url.mime_type();
+    let kind = mime::classify(&mime_type);
+    let encoding = url.encoding();
+    let body = url.body().to_vec();
+
+    let document = match kind {
+        ResourceKind::Download => None,
+        _ if url.is_view_source() => Some(view_source(&encoding.decode(&body).0)),
+        ResourceKind::Document => {
+            let stream = std::mem::take(&mut *stream.lock().unwrap());
+
+            match stream.finish(encoding) {
+                Some(document) => document,
+                None => {
+                    HTMLParser::with_options(&encoding.decode(&body).0, document_options()).parse()
+                }
+            }
+        }
+        ResourceKind::Text => None,
+    };
+
+    let page_url = match url.is_view_source() {
+        true => format!("view-source:{}", url.url()),
+        false => url.url().to_string(),
+    };
+
+    Ok(Page {
+        url: page_url,
+        mime_type,
+        kind,
+        encoding,
+        body,
+        document,
+        redirects: url.redirects().to_vec(),
+    })
+}
+
+fn document_options() -> ParserOptions {
+    ParserOptions {
+        document: true,
+        ..Default::default()
+    }
+}
+
+// Parses a body while it downloads. The encoding is guessed from its start, when the whole
+// body says otherwise the document has to be parsed again.
+#[derive(Default)]
+struct DocumentStream {
+    content_type: Option<String>,
+    // What came in before there was enough to sniff the MIME type and encoding
+    head: Vec<u8>,
+    parsing: Option<(&'static Encoding, Decoder, StreamingParser)>,
+    // Not a document
+    skipped: bool,
+}
+
+impl DocumentStream {
+    fn observe(&mut self, event: BodyEvent) {
+        match event {
+            BodyEvent::Start { content_type, .. } => {
+                *self = Self {
+                    content_type: content_type.map(str::to_string),
+                    ..Default::default()
+                }
+            }
+            BodyEvent::Data(data) => match &mut self.parsing {
+                Some((_, decoder, parser)) => parser.feed(&decode(decoder, data, false)),
+                None if self.skipped => {}
+                None => {
+                    self.head.extend_from_slice(data);
+
+                    if self.head.len() >= mime::SNIFF_LENGTH {
+                        self.start();
+                    }
+                }
+            },
+        }
+    }
+
+    fn start(&mut self) {
+        let content_type = self.content_type.as_deref();
+
+        if mime::classify(&mime::sniff(content_type, &self.head)) != ResourceKind::Document {
+            self.skipped = true;
+            return;
+        }
+
+        let encoding = charset::detect(content_type, &self.head);
+        let mut decoder = encoding.new_decoder_with_bom_removal();
+        let mut parser = StreamingParser::new(document_options());
+        parser.feed(&decode(
+            &mut decoder,
+            &std::mem::take(&mut self.head),
+            false,
+        ));
+
+        self.parsing = Some((encoding, decoder, parser));
+    }
+
+    // The document, unless it wasn't parsed or not as `encoding`
+    fn finish(mut self, encoding: &'static Encoding) -> Option<Option<Node>> {
+        // Short bodies are parsed at the end
+        if self.parsing.is_none() && !self.skipped {
+            self.start();
+        }
+
+        let (guessed, mut decoder, mut parser) = self.parsing?;
+
+        if guessed != encoding {
+            return None;
+        }
+
+        parser.feed(&decode(&mut decoder, &[], true));
+
+        Some(parser.finish())
+    }
+}
+
+fn decode(decoder: &mut Decoder, bytes: &[u8], last: bool) -> String {
+    let capacity = decoder
+        .max_utf8_buffer_length(bytes.len())
+        .unwrap_or(bytes.len() * 3 + 16);
+    let mut text = String::with_capacity(capacity);
+    // With room for all of it, everything is decoded in one go
+    let _ = decoder.decode_to_string(bytes, &mut text, last);
+
+    text
+}
+
+// Like browsers do, the source is shown as preformatted text instead of being parsed
+fn view_source(markup: &str) -> Node {
+    Node::new(
+        NodeData {
+            tag_name: "pre".to_string(),
+            attributes: HashMap::new(),
+            in_noscript: false,
+        },
+        vec![Node::text(markup.to_string())],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_sequence;
+
+    fn serve_once(response: &'static [u8]) -> String {
+        serve(vec![response])
+    }
+
+    fn serve(responses: Vec<&'static [u8]>) -> String {
+        let (port, _) = serve_sequence(responses);
+
+        format!("http://127.0.0.1:{}/", port)
+    }
+
+    #[test]
+    fn test_load_document_without_content_type() {
+        let url = serve_once(b"HTTP/1.0 200 OK\r\n\r\n<html><p>hello</p></html>");
+        let page = load_page(&url).unwrap();
+
+        assert_eq!(page.mime_type, "text/html");
+        assert_eq!(page.kind, ResourceKind::Document);
+        assert_eq!(page.document.unwrap().data.tag_name, "html");
+    }
+
+    #[test]
+    fn test_load_text() {
+        let url = serve_once(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\n<pre>not html");
+        let page = load_page(&url).unwrap();
+
+        assert_eq!(page.kind, ResourceKind::Text);
+        assert!(page.document.is_none());
+        assert_eq!(page.text(), Some("<pre>not html".to_string()));
+    }
+
+    #[test]
+    fn test_load_non_utf8_text() {
+        let url = serve_once(
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/plain; charset=ISO-8859-1\r\n\r\ncaf\xE9",
+        );
+        let page = load_page(&url).unwrap();
+
+        assert_eq!(page.encoding.name(), "windows-1252");
+        assert_eq!(page.text(), Some("caf\u{e9}".to_string()));
+    }
+
+    #[test]
+    fn test_load_binary_as_download() {
+        let url = serve_once(
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\n\x89PNG\r\n\x1a\n\x00\x00",
+        );
+        let page = load_page(&url).unwrap();
+
+        assert_eq!(page.mime_type, "image/png");
+        assert_eq!(page.kind, ResourceKind::Download);
+        assert_eq!(page.text(), None);
+        assert_eq!(page.body.len(), 10);
+    }
+
+    #[test]
+    fn test_load_streamed_document() {
+        let body = format!("<html><body>{}</body></html>", "<p>word</p>".repeat(500));
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n".to_vec();
+        for chunk in body.as_bytes().chunks(1000) {
+            response.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+            response.extend_from_slice(chunk);
+            response.extend_from_slice(b"\r\n");
+        }
+        response.extend_from_slice(b"0\r\n\r\n");
+
+        let url = serve_once(Box::leak(response.into_boxed_slice()));
+        let page = load_page(&url).unwrap();
+        let expected = HTMLParser::with_options(&body, document_options())
+            .parse()
+            .unwrap();
+
+        assert_eq!(page.document.unwrap().to_html(), expected.to_html());
+    }
+
+    #[test]
+    fn test_load_streamed_document_with_late_encoding() {
+        // Valid UTF-8 as far as the guess looks, the é at the end is windows-1252
+        let mut response = b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<p>".to_vec();
+        response.extend_from_slice(&[b'a'; 2000]);
+        response.extend_from_slice(b"\xe9</p>");
+
+        let url = serve_once(Box::leak(response.into_boxed_slice()));
+        let page = load_page(&url).unwrap();
+        let document = page.document.unwrap();
+
+        assert_eq!(page.encoding, encoding_rs::WINDOWS_1252);
+        assert!(document.text_nodes(false)[0].data.attributes["content"].ends_with("aé"));
+    }
+
+    #[test]
+    fn test_view_source() {
+        let url = serve_once(b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<html><head><meta http-equiv=\"refresh\" content=\"0; url=/next\"></head></html>");
+        let page = load_page(&format!("view-source:{}", url)).unwrap();
+        let document = page.document.unwrap();
+        let text_nodes = document.text_nodes(false);
+
+        assert_eq!(page.url, format!("view-source:{}", url));
+        assert_eq!(page.kind, ResourceKind::Document);
+        assert!(page.redirects.is_empty());
+        assert_eq!(document.data.tag_name, "pre");
+        assert_eq!(text_nodes.len(), 1);
+        assert_eq!(
+            text_nodes[0].data.attributes.get("content").unwrap(),
+            &String::from_utf8_lossy(&page.body)
+        );
+    }
+
+    #[test]
+    fn test_parse_meta_refresh() {
+        assert_eq!(
+            MetaRefresh::parse("0;url=https://example.com/"),
+            Some(MetaRefresh {
+                delay: 0,
+                url: Some("https://example.com/".to_string())
+            })
+        );
+        assert_eq!(
+            MetaRefresh::parse(" 5 , URL = 'next.html' "),
+            Some(MetaRefresh {
+                delay: 5,
+                url: Some("next.html".to_string())
+            })
+        );
+        assert_eq!(
+            MetaRefresh::parse("30"),
+            Some(MetaRefresh {
+                delay: 30,
+                url: None
+            })
+        );
+        assert_eq!(MetaRefresh::parse("url=next.html"), None);
+    }
+
+    #[test]
+    fn test_links() {
+        let markup = r##"<html><head><base href="/docs/"><link rel="stylesheet" href="main.css">
+            <script src="//cdn.example.com/app.js"></script><script>inline()</script></head>
+            <body><a href="intro">Intro</a><a name="anchor">No href</a><img src="/logo.png">
+            <map><area href="#top"></map><a href="http://[::1">Broken</a></body></html>"##;
+        let document = HTMLParser::with_options(markup, document_options())
+            .parse()
+            .unwrap();
+
+        let links: Vec<(&str, String)> = links(&document, "https://example.com/a/page.html")
+            .into_iter()
+            .map(|link| (link.kind.as_str(), link.url))
+            .collect();
+
+        assert_eq!(
+            links,
+            [
+                ("link", "https://example.com/docs/main.css".to_string()),
+                ("script", "https://cdn.example.com/app.js".to_string()),
+                ("anchor", "https://example.com/docs/intro".to_string()),
+                ("image", "https://example.com/logo.png".to_string()),
+                ("anchor", "https://example.com/docs/#top".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_follow_meta_refresh() {
+        let url = serve(vec![
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<html><head><meta http-equiv=\"Refresh\" content=\"0; url=/next\"></head></html>",
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<html><p>destination</p></html>",
+        ]);
+        let page = load_page(&url).unwrap();
+
+        assert_eq!(page.url, format!("{}next", url));
+        assert_eq!(page.redirects, vec![url]);
+    }
+
+    #[test]
+    fn test_http_and_meta_refresh_redirects() {
+        let url = serve(vec![
+            b"HTTP/1.0 301 Moved Permanently\r\nLocation: /refresh\r\n\r\n",
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<html><head><meta http-equiv=\"refresh\" content=\"0; url=/final\"></head></html>",
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<html><p>final</p></html>",
+        ]);
+        let page = load_page(&url).unwrap();
+
+        assert_eq!(page.url, format!("{}final", url));
+        assert_eq!(page.redirects, vec![url.clone(), format!("{}refresh", url)]);
+    }
+
+    #[test]
+    fn test_meta_refresh_disabled() {
+        let url = serve_once(b"HTTP/1.0 200 OK\r\n\r\n<html><head><meta http-equiv=\"refresh\" content=\"0; url=/next\"></head></html>");
+        let page = load_page_with(
+            &url,
+            &LoadOptions {
+                max_refresh_hops: 0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(page.url, url);
+        assert!(page.redirects.is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+// Display lists (https://browser.engineering/graphics.html): the drawing commands for a
+// layout tree in painting order, what a canvas based front end goes through to show a page
+
+use crate::layout::{font_size, BoxKind, LayoutBox};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayCommand {
+    /// A filled rectangle, for backgrounds
+    DrawRect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        color: String,
+    },
+    /// A word with its top left corner at `x`, `y`
+    DrawText {
+        x: f64,
+        y: f64,
+        text: String,
+        color: String,
+        font_size: f64,
+        /// The `font-weight` value, "normal" when not set
+        font_weight: String,
+        /// The `font-style` value, "normal" when not set
+        font_style: String,
+    },
+}
+
+/// Commands to paint `root_box` in a viewport scrolled `scroll` pixels down and `height`
+/// pixels high (unbounded when `None`). Coordinates are relative to the viewport and what
+/// falls entirely outside of it is left out.
+pub fn display_list(root_box: &LayoutBox, scroll: f64, height: Option<f64>) -> Vec<DisplayCommand> {
+    let mut commands = Vec::new();
+
+    paint(root_box, &mut commands);
+
+    let bottom = scroll + height.unwrap_or(f64::INFINITY);
+
+    commands
+        .into_iter()
+        .filter(|command| {
+            let (top, height) = match command {
+                DisplayCommand::DrawRect { y, height, .. } => (*y, *height),
+                // Nothing but its top is known, the font size stands in for its height
+                DisplayCommand::DrawText { y, font_size, .. } => (*y, *font_size),
+            };
+
+            top + height > scroll && top < bottom
+        })
+        .map(|mut command| {
+            match &mut command {
+                DisplayCommand::DrawRect { y, .. } | DisplayCommand::DrawText { y, .. } => {
+                    *y -= scroll
+                }
+            }
+
+            command
+        })
+        .collect()
+}
+
+fn paint(layout_box: &LayoutBox, commands: &mut Vec<DisplayCommand>) {
+    let style = &layout_box.style;
+
+    match layout_box.kind {
+        BoxKind::Block => {
+            let background = style
+                .get("background-color")
+                .or_else(|| style.get("background"))
+                .filter(|color| !matches!(color.as_str(), "none" | "transparent"));
+
+            if let Some(color) = background {
+                commands.push(DisplayCommand::DrawRect {
+                    x: layout_box.x,
+                    y: layout_box.y,
+                    width: layout_box.width,
+                    height: layout_box.height,
+                    color: color.clone(),
+                });
+            }
+        }
+        BoxKind::Line => {}
+        BoxKind::Text => {
+            let value = |name: &str| style.get(name).map_or("normal", String::as_str);
+
+            commands.push(DisplayCommand::DrawText {
+                x: layout_box.x,
+                y: layout_box.y,
+                text: layout_box.text.clone().unwrap_or_default(),
+                color: style
+                    .get("color")
+                    .map_or("black", String::as_str)
+                    .to_string(),
+                font_size: font_size(style),
+                font_weight: value("font-weight").to_string(),
+                font_style: value("font-style").to_string(),
+            });
+        }
+    }
+
+    for child in &layout_box.children {
+        paint(child, commands);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::HTMLParser;
+    use crate::layout::layout;
+
+    use super::*;
+
+    fn fixed_width(text: &str, _font_size: f64) -> f64 {
+        text.chars().count() as f64 * 10.0
+    }
+
+    fn text(x: f64, y: f64, text: &str, color: &str, font_weight: &str) -> DisplayCommand {
+        DisplayCommand::DrawText {
+            x,
+            y,
+            text: text.to_string(),
+            color: color.to_string(),
+            font_size: 16.0,
+            font_weight: font_weight.to_string(),
+            font_style: "normal".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_display_list() {
+        let html = r#"<div style="background-color: #eee; color: navy"><p>one <b style="font-weight: bold">two</b></p><p style="background: transparent">three</p></div>"#;
+        let root = HTMLParser::new(html).parse().unwrap();
+        let root_box = layout(&root, &[], &[], 100.0, &fixed_width);
+
+        assert_eq!(
+            display_list(&root_box, 0.0, None),
+            [
+                DisplayCommand::DrawRect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 100.0,
+                    height: 40.0,
+                    color: "#eee".to_string(),
+                },
+                text(0.0, 0.0, "one", "navy", "normal"),
+                text(40.0, 0.0, "two", "navy", "bold"),
+                text(0.0, 20.0, "three", "navy", "normal"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scroll_clipping() {
+        let html = "<div><p>a</p><p>b</p><p>c</p><p>d</p></div>";
+        let root = HTMLParser::new(html).parse().unwrap();
+        let root_box = layout(&root, &[], &[], 100.0, &fixed_width);
+
+        let words = |scroll, height| {
+            display_list(&root_box, scroll, height)
+                .into_iter()
+                .map(|command| match command {
+                    DisplayCommand::DrawText { y, text, .. } => (text, y),
+                    DisplayCommand::DrawRect { .. } => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            words(30.0, Some(20.0)),
+            [("b".to_string(), -10.0), ("c".to_string(), 10.0)]
+        );
+        assert_eq!(
+            words(40.0, None),
+            [("c".to_string(), 0.0), ("d".to_string(), 20.0)]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+// Keep-alive connection reuse for HTTP/1.1 (https://httpwg.org/specs/rfc9112.html#persistent.connections)
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rustls as tls;
+
+/// Connections idle for longer are closed instead of reused, servers usually give up on
+/// them well before that.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Idle connections kept around per origin
+const MAX_IDLE_PER_HOST: usize = 8;
+
+/// Scheme, host and port of the origin a connection talks to, and the proxy in between.
+pub type PoolKey = (String, String, u16, Option<String>);
+
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(Box<tls::StreamOwned<tls::ClientConnection, TcpStream>>),
+}
+
+impl Connection {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Connection::Plain(sock) => sock,
+            Connection::Tls(stream) => &stream.sock,
+        }
+    }
+
+    pub fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(read)?;
+        self.tcp().set_write_timeout(write)
+    }
+
+    // A connection the server closed (or wrote to unasked) while idle can't carry a request
+    fn is_alive(&self) -> bool {
+        let sock = self.tcp();
+
+        if sock.set_nonblocking(true).is_err() {
+            return false;
+        }
+
+        let alive = matches!(
+            sock.peek(&mut [0u8; 1]),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock
+        );
+
+        sock.set_nonblocking(false).is_ok() && alive
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(sock) => sock.read(buf),
+            Connection::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(sock) => sock.write(buf),
+            Connection::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(sock) => sock.flush(),
+            Connection::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Idle HTTP/1.1 connections shared by the requests of a session.
+pub struct ConnectionPool {
+    idle_timeout: Duration,
+    idle: Mutex<HashMap<PoolKey, Vec<(Connection, Instant)>>>,
+}
+
+impl Default for ConnectionPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+impl ConnectionPool {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            idle_timeout,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Most recently used live connection to `key`, expired and dead ones are dropped.
+    pub fn take(&self, key: &PoolKey) -> Option<Connection> {
+        let mut idle = self.idle.lock().unwrap();
+        let connections = idle.get_mut(key)?;
+
+        while let Some((connection, since)) = connections.pop() {
+            if since.elapsed() < self.idle_timeout && connection.is_alive() {
+                return Some(connection);
+            }
+        }
+
+        None
+    }
+
+    pub fn put(&self, key: PoolKey, connection: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        let connections = idle.entry(key).or_default();
+
+        if connections.len() >= MAX_IDLE_PER_HOST {
+            connections.remove(0);
+        }
+
+        connections.push((connection, Instant::now()));
+    }
+
+    /// Closes every idle connection.
+    pub fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn key() -> PoolKey {
+        ("http".to_string(), "127.0.0.1".to_string(), 80, None)
+    }
+
+    fn connect(listener: &TcpListener) -> (Connection, TcpStream) {
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        (Connection::Plain(client), server)
+    }
+
+    #[test]
+    fn test_reuses_live_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (connection, _server) = connect(&listener);
+        let pool = ConnectionPool::default();
+
+        pool.put(key(), connection);
+
+        assert!(pool.take(&key()).is_some());
+        assert!(pool.take(&key()).is_none());
+    }
+
+    #[test]
+    fn test_drops_closed_and_expired_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let pool = ConnectionPool::default();
+
+        let (connection, server) = connect(&listener);
+        pool.put(key(), connection);
+        drop(server);
+        // Give the FIN a moment to arrive
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert!(pool.take(&key()).is_none());
+
+        let pool = ConnectionPool::new(Duration::ZERO);
+        let (connection, _server) = connect(&listener);
+        pool.put(key(), connection);
+
+        assert!(pool.take(&key()).is_none());
+    }
+}
@@ -0,0 +1,234 @@
+// Robots Exclusion Protocol (RFC 9309, https://www.rfc-editor.org/rfc/rfc9309)
+
+use std::time::Duration;
+
+/// Product token we look for in `User-agent` lines.
+pub const USER_AGENT_TOKEN: &str = "ewb";
+
+#[derive(Debug, Default)]
+pub struct Robots {
+    groups: Vec<Group>,
+    // Set when robots.txt was unreachable, nothing may be crawled then
+    disallow_all: bool,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<f64>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Robots {
+    pub fn parse(source: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        // Consecutive user-agent lines share the rules that follow them
+        let mut reading_agents = false;
+
+        for line in source.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if !reading_agents {
+                        groups.push(Group::default());
+                    }
+                    reading_agents = true;
+
+                    if let Some(group) = groups.last_mut() {
+                        group.user_agents.push(value.to_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    reading_agents = false;
+
+                    // Rules before any user-agent line belong to no group
+                    if let Some(group) = groups.last_mut() {
+                        if !value.is_empty() {
+                            group.rules.push(Rule {
+                                allow: key == "allow",
+                                pattern: value.to_string(),
+                            });
+                        }
+                    }
+                }
+                "crawl-delay" => {
+                    reading_agents = false;
+
+                    if let Some(group) = groups.last_mut() {
+                        group.crawl_delay = value.parse().ok();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            groups,
+            disallow_all: false,
+        }
+    }
+
+    /// Used when robots.txt could not be fetched because of a server or network error.
+    pub fn disallow_all() -> Self {
+        Self {
+            groups: Vec::new(),
+            disallow_all: true,
+        }
+    }
+
+    /// Whether `path` (path and query of a URL) may be crawled. The longest matching rule
+    /// wins, `Allow` wins ties.
+    pub fn is_allowed(&self, user_agent: &str, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+
+        if self.disallow_all {
+            return false;
+        }
+
+        let mut best: Option<&Rule> = None;
+
+        for rule in self
+            .matching_groups(user_agent)
+            .flat_map(|group| &group.rules)
+        {
+            if !pattern_matches(&rule.pattern, path) {
+                continue;
+            }
+
+            best = match best {
+                Some(current)
+                    if current.pattern.len() > rule.pattern.len()
+                        || (current.pattern.len() == rule.pattern.len() && current.allow) =>
+                {
+                    Some(current)
+                }
+                _ => Some(rule),
+            };
+        }
+
+        best.is_none_or(|rule| rule.allow)
+    }
+
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.matching_groups(user_agent)
+            .filter_map(|group| group.crawl_delay)
+            .reduce(f64::max)
+            .filter(|delay| delay.is_finite() && *delay >= 0.0)
+            .map(Duration::from_secs_f64)
+    }
+
+    // Groups naming our product token, the `*` groups when there are none
+    fn matching_groups(&self, user_agent: &str) -> impl Iterator<Item = &Group> {
+        let user_agent = user_agent.to_lowercase();
+        let named = self
+            .groups
+            .iter()
+            .any(|group| group.user_agents.contains(&user_agent));
+        let wanted = if named { user_agent } else { "*".to_string() };
+
+        self.groups
+            .iter()
+            .filter(move |group| group.user_agents.contains(&wanted))
+    }
+}
+
+// `*` matches any sequence of characters, a trailing `$` anchors the end of the path
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+
+    for (index, part) in parts.iter().enumerate() {
+        // The last part of an anchored pattern has to match at the very end
+        if anchored && index == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+
+        match rest.find(part) {
+            Some(found) => rest = &rest[found + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+# comment
+User-agent: *
+Disallow: /private/
+Allow: /private/public.html
+Disallow: /*.pdf$
+Crawl-delay: 2
+
+User-agent: ewb
+User-agent: other
+Disallow: /no-ewb
+";
+
+    #[test]
+    fn test_named_group_wins() {
+        let robots = Robots::parse(ROBOTS);
+
+        assert!(!robots.is_allowed("ewb", "/no-ewb/page"));
+        // Only the named group applies to us
+        assert!(robots.is_allowed("ewb", "/private/page"));
+        assert!(robots.crawl_delay("ewb").is_none());
+    }
+
+    #[test]
+    fn test_longest_match() {
+        let robots = Robots::parse(ROBOTS);
+
+        assert!(!robots.is_allowed("bot", "/private/page"));
+        assert!(robots.is_allowed("bot", "/private/public.html"));
+        assert!(robots.is_allowed("bot", "/no-ewb"));
+        assert_eq!(robots.crawl_delay("bot"), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let robots = Robots::parse(ROBOTS);
+
+        assert!(!robots.is_allowed("bot", "/docs/file.pdf"));
+        assert!(robots.is_allowed("bot", "/docs/file.pdf?download=1"));
+        assert!(pattern_matches("/a*b*c", "/a-x-b-y-c-z"));
+        assert!(!pattern_matches("/a*c$", "/abcd"));
+    }
+
+    #[test]
+    fn test_disallow_all() {
+        let robots = Robots::disallow_all();
+
+        assert!(!robots.is_allowed("ewb", "/"));
+        assert!(robots.is_allowed("ewb", "/robots.txt"));
+        assert!(Robots::parse("").is_allowed("ewb", "/anything"));
+    }
+}
@@ -0,0 +1,384 @@
+// Runs the inline scripts of a document with boa (https://boajs.dev), against a small part
+// of the DOM: `document.querySelector(All)`, `getAttribute`/`setAttribute`, `innerHTML`,
+// `tagName` and `console.log`. Script elements are JavaScript objects holding the way down
+// to their node, the tree itself stays with the context while the scripts run.
+
+use std::cell::RefCell;
+
+use boa_engine::{
+    js_string, object::builtins::JsArray, object::ObjectInitializer, property::Attribute, Context,
+    Finalize, JsData, JsError, JsNativeError, JsObject, JsResult, JsValue, NativeFunction, Source,
+    Trace,
+};
+
+use crate::html::{HTMLParser, Node, NodeRef, ParserOptions, Step};
+
+// MIME types of classic scripts, modules would need loading
+const SCRIPT_TYPES: [&str; 4] = [
+    "",
+    "text/javascript",
+    "application/javascript",
+    "application/ecmascript",
+];
+
+/// What running the scripts of a document printed and threw.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScriptOutcome {
+    /// Lines logged with `console.log`
+    pub console: Vec<String>,
+    /// Uncaught exceptions, one per failing script
+    pub errors: Vec<String>,
+}
+
+// The document the scripts work on and what they logged
+#[derive(Trace, Finalize, JsData)]
+struct Host {
+    #[unsafe_ignore_trace]
+    document: RefCell<Node>,
+    #[unsafe_ignore_trace]
+    console: RefCell<Vec<String>>,
+}
+
+#[derive(Trace, Finalize, JsData)]
+struct Element {
+    #[unsafe_ignore_trace]
+    path: Vec<Step>,
+}
+
+/// Runs the inline classic scripts of `document` in document order, the changes they make
+/// end up in it. A script that throws doesn't stop the ones after it. Scripts are taken
+/// before any of them runs, so the ones they insert are not run (like with `innerHTML` in
+/// browsers).
+pub fn run_scripts(document: &mut Node) -> ScriptOutcome {
+    let sources = scripts(document);
+    let mut outcome = ScriptOutcome::default();
+
+    let mut context = Context::default();
+    context.insert_data(Host {
+        document: RefCell::new(document.clone()),
+        console: RefCell::new(Vec::new()),
+    });
+
+    if let Err(error) = install_globals(&mut context) {
+        outcome.errors.push(error.to_string());
+        return outcome;
+    }
+
+    for source in sources {
+        if let Err(error) = context.eval(Source::from_bytes(&source)) {
+            outcome.errors.push(error.to_string());
+        }
+    }
+
+    if let Some(host) = context.remove_data::<Host>() {
+        *document = host.document.borrow().clone();
+        outcome.console = host.console.take();
+    }
+
+    outcome
+}
+
+fn scripts(document: &Node) -> Vec<String> {
+    document
+        .query_selector_all("script")
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|script| !script.data.attributes.contains_key("src"))
+        .filter(|script| {
+            let kind = script.data.attributes.get("type").map_or("", |kind| kind);
+            SCRIPT_TYPES.contains(&kind.trim().to_ascii_lowercase().as_str())
+        })
+        .map(|script| {
+            script
+                .children
+                .iter()
+                .filter_map(|child| child.data.attributes.get("content"))
+                .map(String::as_str)
+                .collect()
+        })
+        .collect()
+}
+
+fn install_globals(context: &mut Context) -> JsResult<()> {
+    let console = ObjectInitializer::new(context)
+        .function(NativeFunction::from_fn_ptr(log), js_string!("log"), 0)
+        .build();
+    context.register_global_property(js_string!("console"), console, Attribute::all())?;
+
+    let document = element(Vec::new(), context);
+    context.register_global_property(js_string!("document"), document, Attribute::all())?;
+
+    Ok(())
+}
+
+// The JavaScript object for the node at `path`
+fn element(path: Vec<Step>, context: &mut Context) -> JsObject {
+    let realm = context.realm().clone();
+    let accessor = |function| Some(NativeFunction::from_fn_ptr(function).to_js_function(&realm));
+
+    ObjectInitializer::with_native_data(Element { path }, context)
+        .function(
+            NativeFunction::from_fn_ptr(query_selector),
+            js_string!("querySelector"),
+            1,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(query_selector_all),
+            js_string!("querySelectorAll"),
+            1,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(get_attribute),
+            js_string!("getAttribute"),
+            1,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(set_attribute),
+            js_string!("setAttribute"),
+            2,
+        )
+        .accessor(
+            js_string!("innerHTML"),
+            accessor(get_inner_html),
+            accessor(set_inner_html),
+            Attribute::ENUMERABLE,
+        )
+        .accessor(
+            js_string!("tagName"),
+            accessor(tag_name),
+            None,
+            Attribute::ENUMERABLE,
+        )
+        .build()
+}
+
+fn host(context: &Context) -> &Host {
+    // Inserted before any script runs
+    context.get_data::<Host>().unwrap()
+}
+
+fn element_path(this: &JsValue) -> JsResult<Vec<Step>> {
+    this.as_object()
+        .and_then(|object| Some(object.downcast_ref::<Element>()?.path.clone()))
+        .ok_or_else(|| JsNativeError::typ().with_message("Not an element").into())
+}
+
+fn detached() -> JsError {
+    JsNativeError::typ()
+        .with_message("The element is no longer in the document")
+        .into()
+}
+
+// Reads the node `this` stands for
+fn with_node<R>(this: &JsValue, context: &Context, read: impl FnOnce(&Node) -> R) -> JsResult<R> {
+    let path = element_path(this)?;
+    let document = host(context).document.borrow();
+
+    Ok(read(document.descendant(&path).ok_or_else(detached)?))
+}
+
+// Changes the node `this` stands for
+fn with_node_mut(
+    this: &JsValue,
+    context: &Context,
+    change: impl FnOnce(&mut Node),
+) -> JsResult<()> {
+    let path = element_path(this)?;
+    let mut document = host(context).document.borrow_mut();
+
+    change(descendant_mut(&mut document, &path).ok_or_else(detached)?);
+
+    Ok(())
+}
+
+fn descendant_mut<'a>(node: &'a mut Node, path: &[Step]) -> Option<&'a mut Node> {
+    path.iter().try_fold(node, |node, step| match *step {
+        Step::Child(index) => node.children.get_mut(index),
+        Step::Shadow(index) => node.shadow_root.as_mut()?.children.get_mut(index),
+    })
+}
+
+fn string_argument(args: &[JsValue], index: usize, context: &mut Context) -> JsResult<String> {
+    let value = args.get(index).cloned().unwrap_or_default();
+
+    Ok(value.to_string(context)?.to_std_string_escaped())
+}
+
+fn log(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let mut parts = Vec::new();
+
+    for arg in args {
+        parts.push(match arg.as_string() {
+            Some(string) => string.to_std_string_escaped(),
+            None => arg.display().to_string(),
+        });
+    }
+
+    host(context).console.borrow_mut().push(parts.join(" "));
+
+    Ok(JsValue::undefined())
+}
+
+// Paths of the descendants of `this` matching the selectors of the first argument
+fn select(
+    this: &JsValue,
+    args: &[JsValue],
+    context: &mut Context,
+    first: bool,
+) -> JsResult<Vec<Vec<Step>>> {
+    let selectors = string_argument(args, 0, context)?;
+    let path = element_path(this)?;
+    let document = host(context).document.borrow();
+    let node = document.descendant(&path).ok_or_else(detached)?;
+
+    let found = match first {
+        true => node
+            .query_selector(&selectors)
+            .map(|found| found.into_iter().collect()),
+        false => node.query_selector_all(&selectors),
+    }
+    .map_err(|error| JsNativeError::syntax().with_message(error.to_string()))?;
+
+    Ok(NodeRef::locate(&document, &found)
+        .iter()
+        .map(|found| found.path().to_vec())
+        .collect())
+}
+
+fn query_selector(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    match select(this, args, context, true)?.pop() {
+        Some(path) => Ok(element(path, context).into()),
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn query_selector_all(
+    this: &JsValue,
+    args: &[JsValue],
+    context: &mut Context,
+) -> JsResult<JsValue> {
+    let elements: Vec<JsValue> = select(this, args, context, false)?
+        .into_iter()
+        .map(|path| element(path, context).into())
+        .collect();
+
+    Ok(JsArray::from_iter(elements, context).into())
+}
+
+fn get_attribute(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let name = string_argument(args, 0, context)?.to_ascii_lowercase();
+    let value = with_node(this, context, |node| {
+        node.data.attributes.get(&name).cloned()
+    })?;
+
+    Ok(value.map_or(JsValue::null(), |value| js_string!(value).into()))
+}
+
+fn set_attribute(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let name = string_argument(args, 0, context)?.to_ascii_lowercase();
+    let value = string_argument(args, 1, context)?;
+
+    with_node_mut(this, context, |node| {
+        node.data.attributes.insert(name, value);
+    })?;
+
+    Ok(JsValue::undefined())
+}
+
+fn get_inner_html(this: &JsValue, _: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let html = with_node(this, context, Node::inner_html)?;
+
+    Ok(js_string!(html).into())
+}
+
+fn set_inner_html(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let children = parse_fragment(&string_argument(args, 0, context)?);
+
+    with_node_mut(this, context, |node| node.children = children)?;
+
+    Ok(JsValue::undefined())
+}
+
+fn tag_name(this: &JsValue, _: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let tag_name = with_node(this, context, |node| {
+        node.data.tag_name.to_ascii_uppercase()
+    })?;
+
+    Ok(js_string!(tag_name).into())
+}
+
+// Nodes of `markup` as if it was the content of a body
+fn parse_fragment(markup: &str) -> Vec<Node> {
+    let options = ParserOptions {
+        document: true,
+        ..Default::default()
+    };
+    let source = format!("<body>{}", markup);
+    let document = HTMLParser::with_options(&source, options).parse();
+
+    document
+        .as_ref()
+        .and_then(|document| document.query_selector("body").ok().flatten())
+        .map(|body| body.children.clone())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(markup: &str) -> Node {
+        let options = ParserOptions {
+            document: true,
+            ..Default::default()
+        };
+
+        HTMLParser::with_options(markup, options).parse().unwrap()
+    }
+
+    #[test]
+    fn test_scripts_change_the_document() {
+        let mut document = document(
+            r##"<html><body><ul id="list"></ul><a href="/a">A</a><a href="/b">B</a>
+            <script>
+                const links = document.querySelectorAll("a");
+                for (const link of links) {
+                    link.setAttribute("href", "https://example.com" + link.getAttribute("href"));
+                }
+                document.querySelector("#list").innerHTML = "<li>one</li><li>two</li>";
+                console.log(links.length, "links", links[0].tagName);
+            </script></body></html>"##,
+        );
+
+        let outcome = run_scripts(&mut document);
+
+        assert_eq!(outcome.console, ["2 links A"]);
+        assert!(outcome.errors.is_empty());
+
+        let items = document.query_selector_all("#list li").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].inner_html(), "two");
+        assert_eq!(
+            document.query_selector("a").unwrap().unwrap().attr("href"),
+            "https://example.com/a"
+        );
+    }
+
+    #[test]
+    fn test_failing_script_does_not_stop_the_others() {
+        let mut document = document(
+            r#"<html><body><p>text</p>
+            <script>throw new Error("broken");</script>
+            <script type="text/plain">console.log("data, not code");</script>
+            <script src="/app.js">console.log("not run either");</script>
+            <script>console.log(document.querySelector("p").innerHTML, document.querySelector("h1"));</script>
+            </body></html>"#,
+        );
+
+        let outcome = run_scripts(&mut document);
+
+        assert_eq!(outcome.console, ["text null"]);
+        assert_eq!(outcome.errors.len(), 1);
+        assert!(outcome.errors[0].contains("broken"));
+    }
+}
@@ -0,0 +1,558 @@
+// CSS selectors (https://www.w3.org/TR/selectors-4/), the subset that matches on the tree alone:
+// type, universal, class, id and attribute selectors with the four combinators
+
+use std::{error::Error, fmt::Display};
+
+use crate::html::{Node, NodeType};
+
+type Err = Box<dyn Error>;
+
+#[derive(Debug)]
+pub struct SelectorError {
+    pub message: String,
+    /// Byte offset in the selector where the error was detected
+    pub offset: usize,
+}
+
+impl Display for SelectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl Error for SelectorError {}
+
+/// Counts of id selectors, of class and attribute selectors, and of type selectors, compared
+/// in that order (https://www.w3.org/TR/selectors-4/#specificity-rules).
+pub type Specificity = (u32, u32, u32);
+
+/// A comma separated list of selectors, an element matches when any of them does.
+#[derive(Debug)]
+pub struct SelectorList(Vec<ComplexSelector>);
+
+// Compound selectors joined by combinators, `combinators[i]` sits between `compounds[i]`
+// and `compounds[i + 1]`
+#[derive(Debug)]
+struct ComplexSelector {
+    compounds: Vec<CompoundSelector>,
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Debug, Default)]
+struct CompoundSelector {
+    /// `None` for `*` or when only other simple selectors are given
+    tag_name: Option<String>,
+    simple: Vec<SimpleSelector>,
+}
+
+#[derive(Debug)]
+enum SimpleSelector {
+    Id(String),
+    Class(String),
+    Attribute {
+        name: String,
+        matcher: Option<(AttributeOperator, String)>,
+        case_insensitive: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AttributeOperator {
+    /// `[a=v]`
+    Equals,
+    /// `[a~=v]`, one of the whitespace separated words
+    Includes,
+    /// `[a|=v]`, `v` or starting with `v-`
+    DashMatch,
+    /// `[a^=v]`
+    Prefix,
+    /// `[a$=v]`
+    Suffix,
+    /// `[a*=v]`
+    Substring,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+impl SelectorList {
+    pub fn parse(source: &str) -> Result<Self, Err> {
+        SelectorParser {
+            source,
+            position: 0,
+        }
+        .parse_list()
+    }
+
+    /// Whether `element` matches, `ancestors` are its ancestors from the outermost down to
+    /// its parent.
+    pub fn matches(&self, element: &Node, ancestors: &[&Node]) -> bool {
+        self.0
+            .iter()
+            .any(|selector| selector.matches(selector.compounds.len() - 1, element, ancestors))
+    }
+
+    /// Highest specificity among the selectors `element` matches, `None` when none does.
+    pub fn match_specificity(&self, element: &Node, ancestors: &[&Node]) -> Option<Specificity> {
+        self.0
+            .iter()
+            .filter(|selector| selector.matches(selector.compounds.len() - 1, element, ancestors))
+            .map(ComplexSelector::specificity)
+            .max()
+    }
+
+    /// Specificity of each selector of the list, in order.
+    pub fn specificities(&self) -> Vec<Specificity> {
+        self.0.iter().map(ComplexSelector::specificity).collect()
+    }
+
+    /// Elements below `root` (not `root` itself) that match, in document order.
+    pub fn select<'a>(&self, root: &'a Node, first_only: bool) -> Vec<&'a Node> {
+        let mut found = Vec::new();
+
+        self.collect(root, &mut vec![root], first_only, &mut found);
+
+        found
+    }
+
+    fn collect<'a>(
+        &self,
+        node: &'a Node,
+        ancestors: &mut Vec<&'a Node>,
+        first_only: bool,
+        found: &mut Vec<&'a Node>,
+    ) {
+        for child in node.children.iter().filter(|child| is_element(child)) {
+            if first_only && !found.is_empty() {
+                return;
+            }
+
+            if self.matches(child, ancestors) {
+                found.push(child);
+            }
+
+            ancestors.push(child);
+            self.collect(child, ancestors, first_only, found);
+            ancestors.pop();
+        }
+    }
+}
+
+impl ComplexSelector {
+    fn specificity(&self) -> Specificity {
+        let simple = self.compounds.iter().flat_map(|compound| &compound.simple);
+        let ids = simple
+            .clone()
+            .filter(|simple| matches!(simple, SimpleSelector::Id(_)))
+            .count();
+        let types = self
+            .compounds
+            .iter()
+            .filter(|compound| compound.tag_name.is_some())
+            .count();
+
+        (ids as u32, (simple.count() - ids) as u32, types as u32)
+    }
+
+    // Matches right to left, `compounds[index]` against `element`
+    fn matches(&self, index: usize, element: &Node, ancestors: &[&Node]) -> bool {
+        if !self.compounds[index].matches(element) {
+            return false;
+        }
+
+        if index == 0 {
+            return true;
+        }
+
+        match self.combinators[index - 1] {
+            Combinator::Child => ancestors
+                .split_last()
+                .is_some_and(|(parent, rest)| self.matches(index - 1, parent, rest)),
+            Combinator::Descendant => (0..ancestors.len())
+                .rev()
+                .any(|depth| self.matches(index - 1, ancestors[depth], &ancestors[..depth])),
+            Combinator::NextSibling => previous_siblings(element, ancestors)
+                .last()
+                .is_some_and(|sibling| self.matches(index - 1, sibling, ancestors)),
+            Combinator::SubsequentSibling => previous_siblings(element, ancestors)
+                .iter()
+                .any(|sibling| self.matches(index - 1, sibling, ancestors)),
+        }
+    }
+}
+
+impl CompoundSelector {
+    fn matches(&self, element: &Node) -> bool {
+        let tag_matches = self
+            .tag_name
+            .as_ref()
+            .is_none_or(|tag_name| element.data.tag_name.eq_ignore_ascii_case(tag_name));
+
+        tag_matches && self.simple.iter().all(|simple| simple.matches(element))
+    }
+}
+
+impl SimpleSelector {
+    fn matches(&self, element: &Node) -> bool {
+        match self {
+            SimpleSelector::Id(id) => attribute(element, "id") == Some(id),
+            SimpleSelector::Class(class) => attribute(element, "class")
+                .is_some_and(|classes| classes.split_ascii_whitespace().any(|name| name == class)),
+            SimpleSelector::Attribute {
+                name,
+                matcher,
+                case_insensitive,
+            } => {
+                let Some(value) = attribute(element, name) else {
+                    return false;
+                };
+                let Some((operator, expected)) = matcher else {
+                    return true;
+                };
+
+                let (value, expected) = match case_insensitive {
+                    true => (value.to_lowercase(), expected.to_lowercase()),
+                    false => (value.clone(), expected.clone()),
+                };
+
+                match operator {
+                    AttributeOperator::Equals => value == expected,
+                    AttributeOperator::Includes => {
+                        value.split_ascii_whitespace().any(|word| word == expected)
+                    }
+                    AttributeOperator::DashMatch => {
+                        value == expected || value.starts_with(&format!("{}-", expected))
+                    }
+                    // An empty value never matches these three
+                    AttributeOperator::Prefix => {
+                        !expected.is_empty() && value.starts_with(&expected)
+                    }
+                    AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+                    AttributeOperator::Substring => {
+                        !expected.is_empty() && value.contains(&expected)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn is_element(node: &Node) -> bool {
+    node.node_type() == NodeType::Element
+}
+
+// Attribute names keep the case of the markup, they are matched in any case
+pub(crate) fn attribute<'a>(element: &'a Node, name: &str) -> Option<&'a String> {
+    element
+        .data
+        .attributes
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+// Element siblings before `element`, closest last
+fn previous_siblings<'a>(element: &Node, ancestors: &[&'a Node]) -> Vec<&'a Node> {
+    let Some(parent) = ancestors.last() else {
+        return Vec::new();
+    };
+
+    parent
+        .children
+        .iter()
+        .take_while(|sibling| !std::ptr::eq(*sibling, element))
+        .filter(|sibling| is_element(sibling))
+        .collect()
+}
+
+struct SelectorParser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl SelectorParser<'_> {
+    fn parse_list(&mut self) -> Result<SelectorList, Err> {
+        let mut selectors = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.parse_complex()?);
+
+            match self.peek() {
+                None => return Ok(SelectorList(selectors)),
+                Some(',') => self.position += 1,
+                Some(_) => return Err(self.err("Unexpected character")),
+            }
+        }
+    }
+
+    fn parse_complex(&mut self) -> Result<ComplexSelector, Err> {
+        let mut compounds = vec![self.parse_compound()?];
+        let mut combinators = Vec::new();
+
+        loop {
+            let had_whitespace = self.skip_whitespace();
+
+            let combinator = match self.peek() {
+                None | Some(',') => break,
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(_) if had_whitespace => Combinator::Descendant,
+                Some(_) => return Err(self.err("Unexpected character")),
+            };
+
+            if !matches!(combinator, Combinator::Descendant) {
+                self.position += 1;
+                self.skip_whitespace();
+            }
+
+            combinators.push(combinator);
+            compounds.push(self.parse_compound()?);
+        }
+
+        Ok(ComplexSelector {
+            compounds,
+            combinators,
+        })
+    }
+
+    fn parse_compound(&mut self) -> Result<CompoundSelector, Err> {
+        let start = self.position;
+        let mut compound = CompoundSelector::default();
+
+        if self.peek() == Some('*') {
+            self.position += 1;
+        } else if self.peek().is_some_and(is_identifier_char) {
+            compound.tag_name = Some(self.parse_identifier()?.to_ascii_lowercase());
+        }
+
+        loop {
+            let simple = match self.peek() {
+                Some('#') => {
+                    self.position += 1;
+                    SimpleSelector::Id(self.parse_identifier()?)
+                }
+                Some('.') => {
+                    self.position += 1;
+                    SimpleSelector::Class(self.parse_identifier()?)
+                }
+                Some('[') => {
+                    self.position += 1;
+                    self.parse_attribute()?
+                }
+                Some(':') => return Err(self.err("Pseudo-classes are not supported")),
+                _ => break,
+            };
+
+            compound.simple.push(simple);
+        }
+
+        if self.position == start {
+            return Err(self.err("Expected a selector"));
+        }
+
+        Ok(compound)
+    }
+
+    // After the `[`, up to and including the `]`
+    fn parse_attribute(&mut self) -> Result<SimpleSelector, Err> {
+        self.skip_whitespace();
+        let name = self.parse_identifier()?;
+        self.skip_whitespace();
+
+        let operator = match self.peek() {
+            Some(']') => None,
+            Some('=') => Some(AttributeOperator::Equals),
+            Some(prefix) if self.rest()[prefix.len_utf8()..].starts_with('=') => {
+                Some(match prefix {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    '*' => AttributeOperator::Substring,
+                    _ => return Err(self.err("Unknown attribute operator")),
+                })
+            }
+            _ => return Err(self.err("Expected ']' or an attribute operator")),
+        };
+
+        let mut matcher = None;
+        let mut case_insensitive = false;
+
+        if let Some(operator) = operator {
+            self.position += match operator {
+                AttributeOperator::Equals => 1,
+                _ => 2,
+            };
+            self.skip_whitespace();
+
+            let value = match self.peek() {
+                Some(quote @ ('"' | '\'')) => self.parse_string(quote)?,
+                _ => self.parse_identifier()?,
+            };
+            matcher = Some((operator, value));
+
+            self.skip_whitespace();
+
+            if let Some(flag @ ('i' | 'I' | 's' | 'S')) = self.peek() {
+                case_insensitive = flag.eq_ignore_ascii_case(&'i');
+                self.position += 1;
+                self.skip_whitespace();
+            }
+        }
+
+        if self.peek() != Some(']') {
+            return Err(self.err("Expected ']'"));
+        }
+        self.position += 1;
+
+        Ok(SimpleSelector::Attribute {
+            name,
+            matcher,
+            case_insensitive,
+        })
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, Err> {
+        let mut identifier = String::new();
+
+        while let Some(char) = self.peek() {
+            if char == '\\' {
+                // An escaped character is taken literally
+                self.position += 1;
+                let escaped = self.peek().ok_or_else(|| self.err("Unfinished escape"))?;
+                identifier.push(escaped);
+                self.position += escaped.len_utf8();
+            } else if is_identifier_char(char) {
+                identifier.push(char);
+                self.position += char.len_utf8();
+            } else {
+                break;
+            }
+        }
+
+        if identifier.is_empty() {
+            return Err(self.err("Expected an identifier"));
+        }
+
+        Ok(identifier)
+    }
+
+    fn parse_string(&mut self, quote: char) -> Result<String, Err> {
+        self.position += 1;
+
+        let end = self
+            .rest()
+            .find(quote)
+            .ok_or_else(|| self.err("Unterminated string"))?;
+        let value = self.rest()[..end].to_string();
+        self.position += end + 1;
+
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let skipped = self.rest().len() - self.rest().trim_start().len();
+        self.position += skipped;
+
+        skipped > 0
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn rest(&self) -> &str {
+        &self.source[self.position..]
+    }
+
+    fn err(&self, message: &str) -> Err {
+        Box::new(SelectorError {
+            message: message.to_string(),
+            offset: self.position,
+        })
+    }
+}
+
+fn is_identifier_char(char: char) -> bool {
+    char.is_ascii_alphanumeric() || char == '-' || char == '_' || !char.is_ascii()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::HTMLParser;
+
+    use super::*;
+
+    const HTML: &str = r#"<div id="main" class="page wide">
+        <h1 class="title">Title</h1>
+        <ul class="menu">
+            <li><a href="/home" lang="en-US">Home</a></li>
+            <li class="active"><a href="https://example.com/about">About</a></li>
+            <li><a href="/contact.pdf" data-x="A B">Contact</a></li>
+        </ul>
+        <p>intro <span>one</span></p>
+        <section><p><span>two</span></p></section>
+    </div>"#;
+
+    fn select(selector: &str) -> Vec<String> {
+        let root = HTMLParser::new(HTML).parse().unwrap();
+        let selectors = SelectorList::parse(selector).unwrap();
+
+        selectors
+            .select(&root, false)
+            .into_iter()
+            .map(|node| {
+                let text = node.text_nodes(false);
+                let label = text.first().map(|text| text.attr("content").clone());
+                format!("{}:{}", node.data.tag_name, label.unwrap_or_default())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_simple_selectors() {
+        assert_eq!(select("h1"), ["h1:Title"]);
+        assert_eq!(select(".title, #missing"), ["h1:Title"]);
+        assert_eq!(select("LI.active a"), ["a:About"]);
+        assert_eq!(select("[href]").len(), 3);
+        assert_eq!(select("a[href^=https]"), ["a:About"]);
+        assert_eq!(select("a[href$='.pdf']"), ["a:Contact"]);
+        assert_eq!(select("a[href*=\"contact\"]"), ["a:Contact"]);
+        assert_eq!(select("[lang|=en]"), ["a:Home"]);
+        assert_eq!(select("[data-x~=b i]"), ["a:Contact"]);
+        assert_eq!(select("[data-x~=b]"), Vec::<String>::new());
+        assert_eq!(select("*").len(), 13);
+    }
+
+    #[test]
+    fn test_combinators() {
+        assert_eq!(select("div span"), ["span:one", "span:two"]);
+        assert_eq!(select("div > p > span"), ["span:one"]);
+        assert_eq!(select("#main > p"), ["p:intro "]);
+        assert_eq!(select("li + li > a"), ["a:About", "a:Contact"]);
+        assert_eq!(select("h1 ~ p"), ["p:intro "]);
+        assert_eq!(select("h1+p"), Vec::<String>::new());
+        // Document order, even when several selectors match
+        assert_eq!(select("span, h1"), ["h1:Title", "span:one", "span:two"]);
+    }
+
+    #[test]
+    fn test_invalid_selectors() {
+        for selector in [
+            "", "a,", "a >", "[href", "a:hover", "a[x=]", "a!", "[x=\"y]",
+        ] {
+            assert!(SelectorList::parse(selector).is_err(), "{selector}");
+        }
+    }
+}
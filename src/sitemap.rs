@@ -0,0 +1,198 @@
+// Sitemaps protocol (https://www.sitemaps.org/protocol.html)
+
+use std::{collections::HashSet, error::Error};
+
+use crate::html::Node;
+use crate::url::{URLError, URL};
+use crate::xml::{local_name, XMLParser};
+
+type Err = Box<dyn Error>;
+
+// Upper bound on sitemap documents fetched while expanding indexes
+const MAX_SITEMAPS: usize = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapEntry {
+    pub loc: String,
+    /// W3C Datetime as written in the sitemap (e.g. `2024-01-31` or `2024-01-31T10:00:00+00:00`)
+    pub lastmod: Option<String>,
+    pub changefreq: Option<String>,
+    /// Between 0.0 and 1.0, crawlers assume 0.5 when missing
+    pub priority: Option<f32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Sitemap {
+    /// A `<urlset>` listing pages
+    Urls(Vec<SitemapEntry>),
+    /// A `<sitemapindex>` listing further sitemaps
+    Index(Vec<SitemapEntry>),
+}
+
+impl Sitemap {
+    pub fn parse(source: &str) -> Result<Self, Err> {
+        let root = XMLParser::new(source).parse()?;
+
+        match local_name(&root.data.tag_name) {
+            "urlset" => Ok(Sitemap::Urls(entries(&root, "url"))),
+            "sitemapindex" => Ok(Sitemap::Index(entries(&root, "sitemap"))),
+            other => Err(Box::new(URLError {
+                message: format!("Not a sitemap, root element is <{}>", other),
+            })),
+        }
+    }
+}
+
+/// Fetches the `/sitemap.xml` of the site `url` belongs to, expanding sitemap indexes.
+///
+/// Sitemaps referenced from an index that fail to load are skipped.
+pub fn fetch_sitemap(url: &str) -> Result<Vec<SitemapEntry>, Err> {
+    let sitemap_url = URL::new(url.to_string())?.resolve("/sitemap.xml");
+
+    let mut entries = Vec::new();
+    let mut pending = match fetch_one(&sitemap_url)? {
+        Sitemap::Urls(urls) => return Ok(urls),
+        Sitemap::Index(sitemaps) => sitemaps,
+    };
+    let mut seen = HashSet::from([sitemap_url]);
+
+    pending.reverse();
+
+    while let Some(sitemap) = pending.pop() {
+        if seen.len() >= MAX_SITEMAPS {
+            break;
+        }
+
+        if !seen.insert(sitemap.loc.clone()) {
+            continue;
+        }
+
+        match fetch_one(&sitemap.loc) {
+            Ok(Sitemap::Urls(urls)) => entries.extend(urls),
+            Ok(Sitemap::Index(sitemaps)) => pending.extend(sitemaps.into_iter().rev()),
+            Err(_) => continue,
+        }
+    }
+
+    Ok(entries)
+}
+
+fn fetch_one(url: &str) -> Result<Sitemap, Err> {
+    let mut sitemap_url = URL::new(url.to_string())?;
+    sitemap_url.fetch()?;
+
+    if sitemap_url.status() != 200 {
+        return Err(Box::new(URLError {
+            message: format!(
+                "Fetching {} failed with status {}",
+                url,
+                sitemap_url.status()
+            ),
+        }));
+    }
+
+    Sitemap::parse(&String::from_utf8_lossy(sitemap_url.body()))
+}
+
+fn entries(root: &Node, entry_tag: &str) -> Vec<SitemapEntry> {
+    root.children
+        .iter()
+        .filter(|child| local_name(&child.data.tag_name) == entry_tag)
+        .filter_map(|entry| {
+            let field = |name: &str| {
+                entry
+                    .children
+                    .iter()
+                    .find(|child| local_name(&child.data.tag_name) == name)
+                    .map(text_content)
+                    .filter(|text| !text.is_empty())
+            };
+
+            Some(SitemapEntry {
+                loc: field("loc")?,
+                lastmod: field("lastmod"),
+                changefreq: field("changefreq").map(|changefreq| changefreq.to_lowercase()),
+                priority: field("priority")
+                    .and_then(|priority| priority.parse().ok())
+                    .filter(|priority| (0.0..=1.0).contains(priority)),
+            })
+        })
+        .collect()
+}
+
+fn text_content(node: &Node) -> String {
+    node.text_nodes(false)
+        .iter()
+        .filter_map(|text| text.data.attributes.get("content"))
+        .map(|content| content.as_str())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{listen, serve_routes_on};
+
+    #[test]
+    fn test_parse_urlset() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc>https://example.com/</loc>
+    <lastmod>2024-01-31</lastmod>
+    <changefreq>Daily</changefreq>
+    <priority>0.8</priority>
+  </url>
+  <url><loc> https://example.com/about?a=1&amp;b=2 </loc><priority>7</priority></url>
+  <url><lastmod>2024-01-31</lastmod></url>
+</urlset>"#;
+
+        assert_eq!(
+            Sitemap::parse(source).unwrap(),
+            Sitemap::Urls(vec![
+                SitemapEntry {
+                    loc: "https://example.com/".to_string(),
+                    lastmod: Some("2024-01-31".to_string()),
+                    changefreq: Some("daily".to_string()),
+                    priority: Some(0.8),
+                },
+                SitemapEntry {
+                    loc: "https://example.com/about?a=1&b=2".to_string(),
+                    lastmod: None,
+                    changefreq: None,
+                    priority: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_other_documents() {
+        assert!(Sitemap::parse("<html><body></body></html>").is_err());
+        assert!(Sitemap::parse("<urlset><url></urlset>").is_err());
+    }
+
+    #[test]
+    fn test_fetch_sitemap_index() {
+        let (listener, port) = listen();
+        let origin = format!("http://127.0.0.1:{}", port);
+
+        let index = format!(
+            "<sitemapindex><sitemap><loc>{0}/pages.xml</loc></sitemap>\
+             <sitemap><loc>{0}/missing.xml</loc></sitemap></sitemapindex>",
+            origin
+        );
+        let pages = "<urlset><url><loc>https://example.com/a</loc></url></urlset>".to_string();
+        serve_routes_on(
+            listener,
+            vec![("/sitemap.xml", index), ("/pages.xml", pages)],
+        );
+
+        let entries = fetch_sitemap(&format!("{}/some/page.html", origin)).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].loc, "https://example.com/a");
+    }
+}
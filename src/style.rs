@@ -0,0 +1,269 @@
+// The cascade (https://www.w3.org/TR/css-cascade-4/): the values style sheets and inline
+// `style` attributes give to the properties of an element, plus what it inherits
+
+use std::collections::HashMap;
+
+use crate::css::{parse_declarations, Declaration, Stylesheet};
+use crate::html::{Node, NodeType};
+use crate::selector::Specificity;
+
+/// Property names to their values.
+pub type ComputedStyle = HashMap<String, String>;
+
+// Properties an element takes from its parent when nothing sets them
+static INHERITED_PROPERTIES: [&str; 17] = [
+    "color",
+    "cursor",
+    "direction",
+    "font",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-variant",
+    "font-weight",
+    "letter-spacing",
+    "line-height",
+    "list-style",
+    "list-style-type",
+    "text-align",
+    "text-transform",
+    "visibility",
+    "white-space",
+];
+
+// What `em` and `%` font sizes are relative to at the root
+const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+// Declarations win over the ones ordered before them: important ones over normal ones, inline
+// ones over the style sheets', then by specificity and position
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Precedence {
+    important: bool,
+    inline: bool,
+    specificity: Specificity,
+    order: usize,
+}
+
+/// Style of `element` under `stylesheets` (later ones win ties), `ancestors` are its ancestors
+/// from the outermost down to its parent. Other nodes than elements, like text, only get
+/// what they inherit.
+pub fn computed_style(
+    stylesheets: &[&Stylesheet],
+    element: &Node,
+    ancestors: &[&Node],
+) -> ComputedStyle {
+    let parent_style = match ancestors.split_last() {
+        Some((parent, rest)) => computed_style(stylesheets, parent, rest),
+        None => ComputedStyle::new(),
+    };
+
+    computed_style_with_parent(stylesheets, element, ancestors, &parent_style)
+}
+
+/// Like `computed_style`, for when the style of the parent is already known (`parent_style`
+/// is empty for the root).
+pub fn computed_style_with_parent(
+    stylesheets: &[&Stylesheet],
+    element: &Node,
+    ancestors: &[&Node],
+    parent_style: &ComputedStyle,
+) -> ComputedStyle {
+    let mut style: ComputedStyle = parent_style
+        .iter()
+        .filter(|(name, _)| is_inherited(name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    if element.node_type() != NodeType::Element {
+        return style;
+    }
+
+    for declaration in cascade(stylesheets, element, ancestors) {
+        let inherited = parent_style.get(&declaration.name);
+
+        match declaration.value.to_ascii_lowercase().as_str() {
+            "inherit" => set(&mut style, &declaration.name, inherited),
+            "initial" => set(&mut style, &declaration.name, None),
+            "unset" if is_inherited(&declaration.name) => {
+                set(&mut style, &declaration.name, inherited)
+            }
+            "unset" => set(&mut style, &declaration.name, None),
+            _ => {
+                let value = match declaration.name.as_str() {
+                    "font-size" => resolve_font_size(&declaration.value, inherited),
+                    _ => declaration.value.clone(),
+                };
+
+                style.insert(declaration.name.clone(), value);
+            }
+        }
+    }
+
+    style
+}
+
+// Declarations that apply to `element`, the winning ones last
+fn cascade<'a>(
+    stylesheets: &[&'a Stylesheet],
+    element: &'a Node,
+    ancestors: &[&Node],
+) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+
+    let rules = stylesheets.iter().flat_map(|stylesheet| &stylesheet.rules);
+
+    for rule in rules {
+        let Some(specificity) = rule.selectors.match_specificity(element, ancestors) else {
+            continue;
+        };
+
+        for declaration in &rule.declarations {
+            declarations.push((
+                Precedence {
+                    important: declaration.important,
+                    inline: false,
+                    specificity,
+                    order: declarations.len(),
+                },
+                declaration.clone(),
+            ));
+        }
+    }
+
+    if let Some(inline) = element.data.attributes.get("style") {
+        for declaration in parse_declarations(inline) {
+            declarations.push((
+                Precedence {
+                    important: declaration.important,
+                    inline: true,
+                    specificity: (0, 0, 0),
+                    order: declarations.len(),
+                },
+                declaration,
+            ));
+        }
+    }
+
+    declarations.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    declarations
+        .into_iter()
+        .map(|(_, declaration)| declaration)
+        .collect()
+}
+
+fn is_inherited(name: &str) -> bool {
+    name.starts_with("--") || INHERITED_PROPERTIES.contains(&name)
+}
+
+fn set(style: &mut ComputedStyle, name: &str, value: Option<&String>) {
+    match value {
+        Some(value) => style.insert(name.to_string(), value.clone()),
+        None => style.remove(name),
+    };
+}
+
+// `em` and `%` sizes are relative to the parent's, they become pixels when that one is known
+fn resolve_font_size(value: &str, parent: Option<&String>) -> String {
+    let parent_size = match parent {
+        Some(parent) => match pixels(parent) {
+            Some(size) => size,
+            None => return value.to_string(),
+        },
+        None => DEFAULT_FONT_SIZE,
+    };
+
+    let value = value.trim();
+    let factor = match (value.strip_suffix("em"), value.strip_suffix('%')) {
+        (Some(number), _) if !number.ends_with('r') => number.parse::<f64>().ok(),
+        (_, Some(number)) => number.parse::<f64>().ok().map(|percent| percent / 100.0),
+        _ => None,
+    };
+
+    match factor {
+        Some(factor) => format!("{}px", parent_size * factor),
+        None => value.to_string(),
+    }
+}
+
+pub fn pixels(value: &str) -> Option<f64> {
+    value.trim().strip_suffix("px")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::HTMLParser;
+
+    use super::*;
+
+    fn style_of(html: &str, selector: &str) -> ComputedStyle {
+        let root = HTMLParser::new(html).parse().unwrap();
+        let stylesheets = root.stylesheets();
+        let stylesheets: Vec<&Stylesheet> = stylesheets.iter().collect();
+        let element = root.query_selector(selector).unwrap().unwrap();
+        let mut ancestors: Vec<&Node> = root
+            .locate(element)
+            .unwrap()
+            .ancestors()
+            .iter()
+            .map(|ancestor| ancestor.node())
+            .collect();
+        ancestors.reverse();
+
+        computed_style(&stylesheets, element, &ancestors)
+    }
+
+    #[test]
+    fn test_cascade() {
+        let html = r#"<div><style>
+            p { color: black; margin: 0 }
+            .note { color: blue; margin: 1px }
+            #first { color: green }
+            div p { margin: 2px !important; padding: 1px }
+            div p { padding: 3px }
+        </style><p id="first" class="note" style="color: red; margin: 4px">a</p></div>"#;
+        let style = style_of(html, "p");
+
+        assert_eq!(style["color"], "red");
+        assert_eq!(style["margin"], "2px");
+        // Same specificity, the later rule wins
+        assert_eq!(style["padding"], "3px");
+
+        let html = r#"<div><style>#a { color: green !important }</style><p id="a" style="color: red">a</p></div>"#;
+        assert_eq!(style_of(html, "p")["color"], "green");
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let html = r#"<div><style>
+            section { color: navy; font-size: 20px; border: 1px solid; --gap: 2px }
+            .big { font-size: 1.5em }
+            .half { font-size: 50% }
+            .own { color: inherit; border: inherit; font-size: initial }
+        </style><section><p class="big">a<b class="half">b</b><i class="own">c</i></p></section></div>"#;
+
+        let paragraph = style_of(html, "p");
+        assert_eq!(paragraph["color"], "navy");
+        assert_eq!(paragraph["font-size"], "30px");
+        assert_eq!(paragraph["--gap"], "2px");
+        assert!(!paragraph.contains_key("border"));
+
+        assert_eq!(style_of(html, "b")["font-size"], "15px");
+
+        let own = style_of(html, "i");
+        assert_eq!(own["color"], "navy");
+        assert!(!own.contains_key("border"));
+        assert!(!own.contains_key("font-size"));
+    }
+
+    #[test]
+    fn test_resolve_font_size() {
+        assert_eq!(resolve_font_size("2em", None), "32px");
+        assert_eq!(resolve_font_size("2rem", None), "2rem");
+        assert_eq!(resolve_font_size("larger", None), "larger");
+        assert_eq!(
+            resolve_font_size("150%", Some(&"small".to_string())),
+            "150%"
+        );
+    }
+}
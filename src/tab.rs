@@ -0,0 +1,208 @@
+// A browser tab: the page it shows and the navigation from it, following links the way
+// clicking them would. Visited pages are kept in the session history as they were loaded,
+// going back and forward shows them again without fetching anything.
+
+use std::error::Error;
+
+use crate::html::Node;
+use crate::page::{self, LoadOptions, Page};
+use crate::url::{URLError, URL};
+
+type Err = Box<dyn Error>;
+
+pub struct Tab {
+    options: LoadOptions,
+    /// Session history, oldest first
+    history: Vec<Page>,
+    /// Position of the page shown in `history`
+    current: usize,
+}
+
+impl Tab {
+    /// Every page is loaded with `options`, its `prepare` hook is where a session plugs in.
+    pub fn new(options: LoadOptions) -> Self {
+        Self {
+            options,
+            history: Vec::new(),
+            current: 0,
+        }
+    }
+
+    /// The page shown, `None` before the first navigation.
+    pub fn page(&self) -> Option<&Page> {
+        self.history.get(self.current)
+    }
+
+    /// Pages of the session history, oldest first.
+    pub fn history(&self) -> &[Page] {
+        &self.history
+    }
+
+    /// Position of the page shown in the history.
+    pub fn history_index(&self) -> usize {
+        self.current
+    }
+
+    /// Loads `url`, a relative one is resolved against the current page. The pages after the
+    /// current one in the history are dropped for it. Nothing is fetched when `url` points
+    /// to a fragment of the current page.
+    pub fn navigate(&mut self, url: &str) -> Result<&Page, Err> {
+        let target = match self.page() {
+            Some(page) => URL::new(page.base_url())?.resolve(url),
+            None => url.trim().to_string(),
+        };
+
+        let page = match self.page() {
+            Some(page) if same_document(&page.url, &target) => Page {
+                url: target,
+                ..page.clone()
+            },
+            _ => page::load_page_with(&target, &self.options)?,
+        };
+
+        self.history.truncate(self.current + 1);
+        self.history.push(page);
+        self.current = self.history.len() - 1;
+
+        Ok(&self.history[self.current])
+    }
+
+    /// Shows the previous page of the history again, `None` when there is none.
+    pub fn back(&mut self) -> Option<&Page> {
+        self.go(-1)
+    }
+
+    /// Shows the next page of the history again, `None` when there is none.
+    pub fn forward(&mut self) -> Option<&Page> {
+        self.go(1)
+    }
+
+    fn go(&mut self, delta: isize) -> Option<&Page> {
+        let index = self
+            .current
+            .checked_add_signed(delta)
+            .filter(|index| *index < self.history.len())?;
+        self.current = index;
+
+        self.page()
+    }
+
+    /// Navigates to where `link`, an `<a>` or `<area>` element with an `href`, points.
+    pub fn follow(&mut self, link: &Node) -> Result<&Page, Err> {
+        let is_link = ["a", "area"].contains(&link.data.tag_name.to_ascii_lowercase().as_str());
+
+        let Some(href) = link.data.attributes.get("href").filter(|_| is_link) else {
+            return Err(Box::new(URLError {
+                message: "Only a or area elements with an href can be followed".to_string(),
+            }));
+        };
+
+        self.navigate(href)
+    }
+}
+
+// `target` is a fragment of the document at `current`
+fn same_document(current: &str, target: &str) -> bool {
+    let without_fragment = |url: &str| url.split('#').next().unwrap_or("").to_string();
+
+    target.contains('#') && without_fragment(current) == without_fragment(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::test_server::serve_routes;
+
+    #[test]
+    fn test_navigate_and_follow() {
+        let origin = serve_routes(vec![
+            (
+                "/",
+                r##"<html><head><base href="/docs/"></head><body><a href="intro">Intro</a><a href="/#end">End</a><p>home</p></body></html>"##,
+            ),
+            ("/docs/intro", "<html><body><p>intro</p></body></html>"),
+        ]);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let mut tab = Tab::new(LoadOptions {
+            prepare: Some(Arc::new(move |_: &mut URL| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..Default::default()
+        });
+
+        assert!(tab.page().is_none());
+
+        let page = tab.navigate(&format!("{}/", origin)).unwrap();
+        let document = page.document.clone().unwrap();
+        let links = document.query_selector_all("a").unwrap();
+
+        // A fragment of the current page isn't fetched again
+        let page = tab.follow(links[1]).unwrap();
+        assert_eq!(page.url, format!("{}/#end", origin));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // Relative to the `<base>`
+        let page = tab.follow(links[0]).unwrap();
+        assert_eq!(page.url, format!("{}/docs/intro", origin));
+        assert_eq!(page.document.as_ref().unwrap().inner_text(), "intro");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let paragraph = document.query_selector("p").unwrap().unwrap();
+        assert!(tab.follow(paragraph).is_err());
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let origin = serve_routes(vec![
+            ("/a", "<html><head><title> Page\n A </title></head></html>"),
+            ("/b", "<html><body><p>no title</p></body></html>"),
+            ("/c", "<html><head><title>C</title></head></html>"),
+        ]);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let mut tab = Tab::new(LoadOptions {
+            prepare: Some(Arc::new(move |_: &mut URL| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..Default::default()
+        });
+
+        assert!(tab.back().is_none());
+
+        tab.navigate(&format!("{}/a", origin)).unwrap();
+        tab.navigate("b").unwrap();
+        tab.navigate("#top").unwrap();
+        assert_eq!(tab.history().len(), 3);
+        assert!(tab.forward().is_none());
+
+        // Shown again as loaded, nothing is fetched
+        assert_eq!(tab.back().unwrap().url, format!("{}/b", origin));
+        assert_eq!(tab.back().unwrap().title().unwrap(), "Page A");
+        assert!(tab.back().is_none());
+        assert_eq!(tab.forward().unwrap().url, format!("{}/b", origin));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Navigating drops the pages after the current one
+        tab.navigate("c").unwrap();
+        let history: Vec<(String, Option<String>)> = tab
+            .history()
+            .iter()
+            .map(|page| (page.url.replace(&origin, ""), page.title()))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                ("/a".to_string(), Some("Page A".to_string())),
+                ("/b".to_string(), None),
+                ("/c".to_string(), Some("C".to_string())),
+            ]
+        );
+        assert_eq!(tab.history_index(), 2);
+    }
+}
@@ -0,0 +1,115 @@
+// Local HTTP servers for the tests, on a random port of 127.0.0.1. Each request is read whole,
+// body included, before it is answered.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread::{self, JoinHandle},
+};
+
+/// A listener on a random local port, and the port.
+pub fn listen() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    (listener, port)
+}
+
+/// Reads a request with its body (sized or chunked), or what came of it before the client
+/// hung up.
+pub fn read_request<R: BufRead>(reader: &mut R) -> String {
+    let mut request = String::new();
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return request;
+        }
+        request.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+
+    let lowercase = request.to_lowercase();
+    if let Some(length) = lowercase
+        .split("\r\n")
+        .find_map(|line| line.strip_prefix("content-length: "))
+    {
+        let mut body = Vec::new();
+        let _ = reader.take(length.parse().unwrap()).read_to_end(&mut body);
+        request.push_str(&String::from_utf8_lossy(&body));
+    } else if lowercase.contains("transfer-encoding: chunked") {
+        while !request.ends_with("\r\n0\r\n\r\n") {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            request.push_str(&line);
+        }
+    }
+
+    request
+}
+
+/// Accepts one connection and answers it with what `respond` makes of its request, which is
+/// handed back. A client that hangs up without a request gets nothing.
+pub fn answer<R: AsRef<[u8]>>(listener: &TcpListener, respond: impl FnOnce(&str) -> R) -> String {
+    let (sock, _) = listener.accept().unwrap();
+    let mut reader = BufReader::new(sock);
+    let request = read_request(&mut reader);
+
+    // Clients may stop reading early on purpose
+    if !request.is_empty() {
+        let _ = reader.get_mut().write_all(respond(&request).as_ref());
+    }
+
+    request
+}
+
+/// Answers a single connection with `response`, the request is handed back.
+pub fn serve_once<R: AsRef<[u8]> + Send + 'static>(response: R) -> (u16, JoinHandle<String>) {
+    let (listener, port) = listen();
+
+    (port, thread::spawn(move || answer(&listener, |_| response)))
+}
+
+/// Answers each incoming connection with the next response, the requests are handed back.
+pub fn serve_sequence<R: AsRef<[u8]> + Send + 'static>(
+    responses: Vec<R>,
+) -> (u16, JoinHandle<Vec<String>>) {
+    let (listener, port) = listen();
+
+    let handle = thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|response| answer(&listener, |_| response))
+            .collect()
+    });
+
+    (port, handle)
+}
+
+/// Serves pages by path until the test ends, unknown paths get a 404. Returns the origin.
+pub fn serve_routes<B: AsRef<str> + Send + 'static>(routes: Vec<(&'static str, B)>) -> String {
+    let (listener, port) = listen();
+    serve_routes_on(listener, routes);
+
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// Like `serve_routes`, on a listener bound beforehand for pages that need the origin.
+pub fn serve_routes_on<B: AsRef<str> + Send + 'static>(
+    listener: TcpListener,
+    routes: Vec<(&'static str, B)>,
+) {
+    thread::spawn(move || loop {
+        answer(&listener, |request| {
+            let path = request.split(' ').nth(1).unwrap_or("");
+            match routes.iter().find(|(route, _)| *route == path) {
+                Some((_, body)) => format!("HTTP/1.0 200 OK\r\n\r\n{}", body.as_ref()),
+                None => "HTTP/1.0 404 Not Found\r\n\r\n".to_string(),
+            }
+        });
+    });
+}
@@ -0,0 +1,95 @@
+// Text measuring helpers that work on grapheme clusters (what a reader sees as one
+// character) instead of chars, so combining marks, emoji sequences and wide CJK
+// characters are counted and cut correctly.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub const ELLIPSIS: &str = "…";
+
+/// Number of user-perceived characters in `text`.
+pub fn grapheme_len(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Columns `text` takes in a monospaced terminal: East Asian wide characters and emoji
+/// take two, combining marks none.
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Shortens `text` to at most `max_width` columns, ending it with `ellipsis` when
+/// something was cut. Grapheme clusters are never split.
+pub fn truncate(text: &str, max_width: usize, ellipsis: &str) -> String {
+    if display_width(text) <= max_width {
+        return text.to_string();
+    }
+
+    let ellipsis_width = display_width(ellipsis);
+
+    // Not even the ellipsis fits, cut the ellipsis itself
+    if ellipsis_width > max_width {
+        return truncate(ellipsis, max_width, "");
+    }
+
+    let mut truncated = String::new();
+    let mut width = 0;
+
+    for grapheme in text.graphemes(true) {
+        let next_width = width + grapheme_width(grapheme);
+
+        if next_width + ellipsis_width > max_width {
+            break;
+        }
+
+        truncated.push_str(grapheme);
+        width = next_width;
+    }
+
+    truncated.push_str(ellipsis);
+    truncated
+}
+
+// A whole cluster renders as a single glyph (e.g. an emoji ZWJ sequence), so its width is
+// capped at that of the widest glyphs
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().min(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &str = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+
+    #[test]
+    fn test_grapheme_len() {
+        assert_eq!(grapheme_len("hello"), 5);
+        assert_eq!(grapheme_len("e\u{301}"), 1);
+        assert_eq!(grapheme_len(FAMILY), 1);
+        assert_eq!(grapheme_len("西游记"), 3);
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("hello"), 5);
+        assert_eq!(display_width("西游记"), 6);
+        assert_eq!(display_width("第一回 hi"), 9);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width(FAMILY), 2);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("hello world", 20, ELLIPSIS), "hello world");
+        assert_eq!(truncate("hello world", 6, ELLIPSIS), "hello…");
+        // A wide character that would straddle the limit is dropped entirely
+        assert_eq!(truncate("西游记第一回", 6, ELLIPSIS), "西游…");
+        assert_eq!(truncate("cafe\u{301} au lait", 5, "..."), "ca...");
+        assert_eq!(
+            truncate("e\u{301}e\u{301}e\u{301}", 2, ""),
+            "e\u{301}e\u{301}"
+        );
+        assert_eq!(truncate("hello", 2, "..."), "..");
+    }
+}
@@ -0,0 +1,256 @@
+// Serializes a parsed tree as well-formed XML, following the XHTML syntax rules
+// (https://html.spec.whatwg.org/multipage/xhtml.html)
+
+use crate::html::{Node, NodeType, VOID_ELEMENTS};
+
+const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+#[derive(Debug, Default, Clone)]
+pub struct XhtmlOptions {
+    /// Prefix the output with `<?xml version="1.0" encoding="UTF-8"?>`
+    pub xml_declaration: bool,
+}
+
+/// Serializes `node` and its descendants as namespace-well-formed XML.
+pub fn to_xhtml(node: &Node, options: &XhtmlOptions) -> String {
+    let mut output = String::new();
+
+    if options.xml_declaration {
+        output.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    }
+
+    write_node(node, None, &mut output);
+
+    output
+}
+
+fn write_node(node: &Node, parent_namespace: Option<&str>, output: &mut String) {
+    let tag_name = &node.data.tag_name;
+
+    match node.node_type() {
+        NodeType::Element | NodeType::Document => {}
+        NodeType::Text => {
+            if let Some(content) = node.data.attributes.get("content") {
+                escape_into(content, false, output);
+            }
+            return;
+        }
+        NodeType::Comment => {
+            // XML comments can't contain `--` nor end with `-`
+            let mut content = node.attr("content").clone();
+            while content.contains("--") {
+                content = content.replace("--", "- -");
+            }
+
+            output.push_str("<!--");
+            output.push_str(&content);
+            if content.ends_with('-') {
+                output.push(' ');
+            }
+            output.push_str("-->");
+            return;
+        }
+        // An XHTML document needs none
+        NodeType::Doctype => return,
+    }
+
+    if !is_xml_name(tag_name) {
+        // Nothing sensible can be emitted for the element itself, keep its content
+        for child in &node.children {
+            write_node(child, parent_namespace, output);
+        }
+        return;
+    }
+
+    let namespace = element_namespace(tag_name, parent_namespace);
+    let name = match namespace {
+        XHTML_NAMESPACE => tag_name.to_lowercase(),
+        _ => tag_name.clone(),
+    };
+
+    output.push('<');
+    output.push_str(&name);
+
+    if parent_namespace != Some(namespace) {
+        output.push_str(" xmlns=\"");
+        output.push_str(namespace);
+        output.push('"');
+    }
+
+    write_attributes(node, output);
+
+    if node.children.is_empty()
+        && (namespace != XHTML_NAMESPACE || VOID_ELEMENTS.contains(&name.as_str()))
+    {
+        output.push_str(" />");
+        return;
+    }
+
+    output.push('>');
+
+    for child in &node.children {
+        write_node(child, Some(namespace), output);
+    }
+
+    output.push_str("</");
+    output.push_str(&name);
+    output.push('>');
+}
+
+fn element_namespace(tag_name: &str, parent_namespace: Option<&str>) -> &'static str {
+    match (tag_name.to_lowercase().as_str(), parent_namespace) {
+        ("svg", _) => SVG_NAMESPACE,
+        ("math", _) => MATHML_NAMESPACE,
+        (_, Some(SVG_NAMESPACE)) => SVG_NAMESPACE,
+        (_, Some(MATHML_NAMESPACE)) => MATHML_NAMESPACE,
+        _ => XHTML_NAMESPACE,
+    }
+}
+
+fn write_attributes(node: &Node, output: &mut String) {
+    // Sorted so the output does not depend on the hashing order
+    let mut attributes: Vec<(&String, &String)> = node.data.attributes.iter().collect();
+    attributes.sort();
+
+    let mut declared_xlink = false;
+
+    for (name, value) in attributes {
+        // Namespaces are declared from the tree structure, source declarations are dropped
+        if name == "xmlns" || name.starts_with("xmlns:") || !is_xml_name(name) {
+            continue;
+        }
+
+        match name.split_once(':') {
+            None => {}
+            Some(("xml", _)) => {}
+            Some(("xlink", _)) if !declared_xlink => {
+                output.push_str(" xmlns:xlink=\"");
+                output.push_str(XLINK_NAMESPACE);
+                output.push('"');
+                declared_xlink = true;
+            }
+            Some(("xlink", _)) => {}
+            // Undeclared prefix, would not be namespace-well-formed
+            Some(_) => continue,
+        }
+
+        output.push(' ');
+        output.push_str(name);
+        output.push_str("=\"");
+        escape_into(value, true, output);
+        output.push('"');
+    }
+}
+
+fn escape_into(text: &str, in_attribute: bool, output: &mut String) {
+    for char in text.chars() {
+        match char {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' if in_attribute => output.push_str("&quot;"),
+            // Attribute value normalization would turn these into spaces otherwise
+            '\t' if in_attribute => output.push_str("&#9;"),
+            '\n' if in_attribute => output.push_str("&#10;"),
+            '\r' => output.push_str("&#13;"),
+            // Not allowed anywhere in an XML document
+            char if is_restricted_char(char) => {}
+            char => output.push(char),
+        }
+    }
+}
+
+fn is_restricted_char(char: char) -> bool {
+    matches!(char, '\u{0}'..='\u{8}' | '\u{b}' | '\u{c}' | '\u{e}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}')
+}
+
+// Simplified XML Name production: no leading digit, dash or dot and no unusual punctuation
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    if !(first.is_alphabetic() || first == '_' || first == ':') {
+        return false;
+    }
+
+    chars.all(|char| char.is_alphanumeric() || ['_', ':', '-', '.'].contains(&char))
+        && name.matches(':').count() <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::{HTMLParser, ParserOptions};
+
+    use super::*;
+
+    fn serialize(html: &str) -> String {
+        let node = HTMLParser::new(html).parse().unwrap();
+        to_xhtml(&node, &XhtmlOptions::default())
+    }
+
+    #[test]
+    fn test_void_elements_are_self_closed() {
+        assert_eq!(
+            serialize(r#"<html><p>one<br>two</p><img src="a.png"></html>"#),
+            r#"<html xmlns="http://www.w3.org/1999/xhtml"><p>one<br />two</p><img src="a.png" /></html>"#
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(
+            serialize(r#"<div title="a<b">2 > 1 & 3</div>"#),
+            r#"<div xmlns="http://www.w3.org/1999/xhtml" title="a&lt;b">2 &gt; 1 &amp; 3</div>"#
+        );
+    }
+
+    #[test]
+    fn test_foreign_namespaces() {
+        let xhtml = serialize(
+            r#"<html><svg viewBox="0 0 10 10"><use xlink:href="symbol"></use></svg></html>"#,
+        );
+
+        assert_eq!(
+            xhtml,
+            concat!(
+                r#"<html xmlns="http://www.w3.org/1999/xhtml">"#,
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">"#,
+                r#"<use xmlns:xlink="http://www.w3.org/1999/xlink" xlink:href="symbol" />"#,
+                r#"</svg></html>"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let options = ParserOptions {
+            document: true,
+            comments: true,
+            ..Default::default()
+        };
+        let document = HTMLParser::with_options("<!DOCTYPE html><p>a<!-- b--c ---></p>", options)
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            to_xhtml(&document, &XhtmlOptions::default()),
+            r#"<html xmlns="http://www.w3.org/1999/xhtml"><head></head><body><p>a<!-- b- -c - --></p></body></html>"#
+        );
+    }
+
+    #[test]
+    fn test_xml_declaration() {
+        let node = HTMLParser::new("<p>text</p>").parse().unwrap();
+        let options = XhtmlOptions {
+            xml_declaration: true,
+        };
+
+        assert!(to_xhtml(&node, &options).starts_with("<?xml version=\"1.0\""));
+    }
+}
@@ -0,0 +1,409 @@
+// Strict XML parsing for machine-readable documents (sitemaps, feeds, ...) where the
+// forgiving HTML parser would silently produce a wrong tree. Any well-formedness error
+// fails the whole parse.
+
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use crate::html::{Node, NodeData};
+
+type Err = Box<dyn Error>;
+
+#[derive(Debug)]
+pub struct XMLError {
+    pub message: String,
+    /// Byte offset in the source where the error was detected
+    pub offset: usize,
+}
+
+impl Display for XMLError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl Error for XMLError {}
+
+pub struct XMLParser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> XMLParser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source: source.strip_prefix('\u{feff}').unwrap_or(source),
+            position: 0,
+        }
+    }
+
+    /// Parses the whole document and returns its root element. Element and attribute
+    /// names are kept as written, prefixes included.
+    pub fn parse(&mut self) -> Result<Node, Err> {
+        self.skip_misc()?;
+
+        if self.rest().starts_with("<!DOCTYPE") {
+            self.skip_doctype()?;
+            self.skip_misc()?;
+        }
+
+        if !self.rest().starts_with('<') {
+            return Err(self.err("Expected root element"));
+        }
+
+        let root = self.parse_element()?;
+
+        self.skip_misc()?;
+
+        if !self.rest().is_empty() {
+            return Err(self.err("Content after root element"));
+        }
+
+        Ok(root)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn err(&self, message: &str) -> Box<dyn Error> {
+        Box::new(XMLError {
+            message: message.to_string(),
+            offset: self.position,
+        })
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), Err> {
+        if !self.rest().starts_with(expected) {
+            return Err(self.err(&format!("Expected '{}'", expected)));
+        }
+
+        self.position += expected.len();
+
+        Ok(())
+    }
+
+    // Moves past `terminator` and returns everything before it
+    fn read_until(&mut self, terminator: &str) -> Result<&'a str, Err> {
+        let end = self.rest().find(terminator).ok_or_else(|| {
+            self.err(&format!("Unterminated construct, missing '{}'", terminator))
+        })?;
+        let read = &self.rest()[..end];
+
+        self.position += end + terminator.len();
+
+        Ok(read)
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let trimmed = self.rest().trim_start_matches(is_whitespace);
+        let skipped = self.rest().len() - trimmed.len();
+
+        self.position += skipped;
+
+        skipped > 0
+    }
+
+    // Whitespace, comments and processing instructions (the XML declaration included)
+    fn skip_misc(&mut self) -> Result<(), Err> {
+        loop {
+            self.skip_whitespace();
+
+            if self.rest().starts_with("<!--") {
+                self.skip_comment()?;
+            } else if self.rest().starts_with("<?") {
+                self.read_until("?>")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) -> Result<(), Err> {
+        self.expect("<!--")?;
+
+        if self.read_until("-->")?.contains("--") {
+            return Err(self.err("'--' is not allowed inside comments"));
+        }
+
+        Ok(())
+    }
+
+    fn skip_doctype(&mut self) -> Result<(), Err> {
+        // The internal subset may contain '>' so brackets are tracked
+        let mut depth = 0;
+
+        for (offset, char) in self.rest().char_indices() {
+            match char {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.position += offset + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        Err(self.err("Unterminated doctype"))
+    }
+
+    fn parse_name(&mut self) -> Result<String, Err> {
+        let rest = self.rest();
+        let length = rest
+            .char_indices()
+            .find(|(index, char)| !is_name_char(*char) || (*index == 0 && !is_name_start(*char)))
+            .map_or(rest.len(), |(index, _)| index);
+
+        if length == 0 {
+            return Err(self.err("Expected a name"));
+        }
+
+        self.position += length;
+
+        Ok(rest[..length].to_string())
+    }
+
+    fn parse_element(&mut self) -> Result<Node, Err> {
+        self.expect("<")?;
+
+        let tag_name = self.parse_name()?;
+        let mut attributes = HashMap::new();
+
+        loop {
+            let separated = self.skip_whitespace();
+
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(Node::new(element_data(tag_name, attributes), Vec::new()));
+            }
+
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break;
+            }
+
+            if !separated {
+                return Err(self.err("Expected whitespace between attributes"));
+            }
+
+            let (name, value) = self.parse_attribute()?;
+
+            if attributes.insert(name, value).is_some() {
+                return Err(self.err("Duplicate attribute"));
+            }
+        }
+
+        let children = self.parse_content()?;
+
+        self.expect("</")?;
+        if self.parse_name()? != tag_name {
+            return Err(self.err(&format!("Mismatched end tag, expected </{}>", tag_name)));
+        }
+        self.skip_whitespace();
+        self.expect(">")?;
+
+        Ok(Node::new(element_data(tag_name, attributes), children))
+    }
+
+    fn parse_attribute(&mut self) -> Result<(String, String), Err> {
+        let name = self.parse_name()?;
+
+        self.skip_whitespace();
+        self.expect("=")?;
+        self.skip_whitespace();
+
+        let quote = match self.rest().chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.err("Attribute values must be quoted")),
+        };
+        self.position += 1;
+
+        let raw = self.read_until(&quote.to_string())?;
+        if raw.contains('<') {
+            return Err(self.err("'<' is not allowed in attribute values"));
+        }
+
+        Ok((name, self.decode_references(raw)?))
+    }
+
+    // Everything up to the parent's end tag, adjacent text and CDATA become a single text node
+    fn parse_content(&mut self) -> Result<Vec<Node>, Err> {
+        let mut children = Vec::new();
+        let mut text = String::new();
+
+        loop {
+            let rest = self.rest();
+
+            if rest.is_empty() {
+                return Err(self.err("Unexpected end of document"));
+            }
+
+            if rest.starts_with("</") {
+                break;
+            }
+
+            if rest.starts_with("<![CDATA[") {
+                self.position += "<![CDATA[".len();
+                text.push_str(self.read_until("]]>")?);
+            } else if rest.starts_with("<!--") {
+                self.skip_comment()?;
+            } else if rest.starts_with("<?") {
+                self.read_until("?>")?;
+            } else if rest.starts_with('<') {
+                flush_text(&mut text, &mut children);
+                children.push(self.parse_element()?);
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let raw = &rest[..end];
+
+                if raw.contains("]]>") {
+                    return Err(self.err("']]>' is not allowed in text"));
+                }
+
+                text.push_str(&self.decode_references(raw)?);
+                self.position += end;
+            }
+        }
+
+        flush_text(&mut text, &mut children);
+
+        Ok(children)
+    }
+
+    // Only the predefined entities and character references exist without a DTD
+    fn decode_references(&self, raw: &str) -> Result<String, Err> {
+        let mut decoded = String::with_capacity(raw.len());
+        let mut rest = raw;
+
+        while let Some(start) = rest.find('&') {
+            decoded.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let end = rest
+                .find(';')
+                .ok_or_else(|| self.err("Unterminated entity reference"))?;
+            let reference = &rest[1..end];
+
+            let char = match reference {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => match reference.strip_prefix('#') {
+                    Some(hex) if hex.starts_with('x') => u32::from_str_radix(&hex[1..], 16)
+                        .ok()
+                        .and_then(char::from_u32),
+                    Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+
+            decoded.push(
+                char.ok_or_else(|| self.err(&format!("Unknown reference '&{};'", reference)))?,
+            );
+            rest = &rest[end + 1..];
+        }
+
+        decoded.push_str(rest);
+
+        Ok(decoded)
+    }
+}
+
+/// Name without its namespace prefix, `image:loc` -> `loc`.
+pub fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+fn element_data(tag_name: String, attributes: HashMap<String, String>) -> NodeData {
+    NodeData {
+        tag_name,
+        attributes,
+        in_noscript: false,
+    }
+}
+
+fn flush_text(text: &mut String, children: &mut Vec<Node>) {
+    if text.is_empty() {
+        return;
+    }
+
+    let mut attributes = HashMap::new();
+    attributes.insert("content".to_string(), std::mem::take(text));
+
+    children.push(Node::new(
+        element_data("text".to_string(), attributes),
+        Vec::new(),
+    ));
+}
+
+fn is_whitespace(char: char) -> bool {
+    [' ', '\t', '\n', '\r'].contains(&char)
+}
+
+fn is_name_start(char: char) -> bool {
+    char.is_alphabetic() || char == '_' || char == ':'
+}
+
+fn is_name_char(char: char) -> bool {
+    is_name_start(char) || char.is_numeric() || ['-', '.', '\u{b7}'].contains(&char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Node, Err> {
+        XMLParser::new(source).parse()
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- generated -->
+<feed xmlns="http://www.w3.org/2005/Atom" lang='en'>
+  <title>A &amp; B &#x263A;</title>
+  <link href="/a?x=1&amp;y=2"/>
+  <summary><![CDATA[<b>raw</b>]]> text</summary>
+</feed>"#;
+        let root = parse(source).unwrap();
+        let elements: Vec<&Node> = root
+            .children
+            .iter()
+            .filter(|child| child.data.tag_name != "text")
+            .collect();
+
+        assert_eq!(root.data.tag_name, "feed");
+        assert_eq!(root.attr("lang"), "en");
+        assert_eq!(elements[0].children[0].attr("content"), "A & B \u{263a}");
+        assert_eq!(elements[1].attr("href"), "/a?x=1&y=2");
+        assert!(elements[1].children.is_empty());
+        assert_eq!(elements[2].children[0].attr("content"), "<b>raw</b> text");
+    }
+
+    #[test]
+    fn test_well_formedness_errors() {
+        assert!(parse("<a><b></a></b>").is_err());
+        assert!(parse("<a>").is_err());
+        assert!(parse("<a x=1></a>").is_err());
+        assert!(parse(r#"<a x="1" x="2"></a>"#).is_err());
+        assert!(parse("<a>&nbsp;</a>").is_err());
+        assert!(parse("<a></a><b></b>").is_err());
+        assert!(parse("text").is_err());
+    }
+
+    #[test]
+    fn test_error_offset() {
+        let error = parse("<a></b>").unwrap_err();
+        let error = error.downcast_ref::<XMLError>().unwrap();
+
+        assert_eq!(error.offset, 6);
+    }
+
+    #[test]
+    fn test_local_name() {
+        assert_eq!(local_name("image:loc"), "loc");
+        assert_eq!(local_name("loc"), "loc");
+    }
+}